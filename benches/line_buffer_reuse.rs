@@ -0,0 +1,76 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use dir_lines_streamer::DirectoryLinesStreamer;
+
+/// Counts the allocations of the process.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const LINES: usize = 100_000;
+
+/// Directory of a single file of `LINES` lines.
+fn log_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let content: String = (0..LINES)
+        .map(|i| format!("line {} of messages, padded to look like a log line\n", i))
+        .collect();
+    fs::write(dir.path().join("messages"), content).unwrap();
+    dir
+}
+
+fn build(dir: &tempfile::TempDir, reuse: bool) -> DirectoryLinesStreamer {
+    DirectoryLinesStreamer::builder(dir.path())
+        .with_line_buffer_reuse(reuse)
+        .build()
+        .unwrap()
+}
+
+/// Reading every line without and with a reused line buffer. The allocations per line are
+/// printed once, criterion only times the reads.
+fn line_buffer_reuse(c: &mut Criterion) {
+    let dir = log_dir();
+    let mut group = c.benchmark_group("line_buffer_reuse");
+    for reuse in [false, true] {
+        let mut streamer = build(&dir, reuse);
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        assert_eq!(Iterator::by_ref(&mut streamer).count(), LINES);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!(
+            "line_buffer_reuse/{}: {:.2} allocations per line",
+            reuse,
+            allocations as f64 / LINES as f64
+        );
+        group.bench_function(format!("{}", reuse), |b| {
+            b.iter_batched_ref(
+                || build(&dir, reuse),
+                |streamer| streamer.by_ref().count(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, line_buffer_reuse);
+criterion_main!(benches);
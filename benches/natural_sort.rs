@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use dir_lines_streamer::sort_files_natural;
+
+/// Rotated and dated log file names, shuffled.
+fn file_names() -> Vec<PathBuf> {
+    let mut names: Vec<PathBuf> = (0..5000)
+        .map(|i| PathBuf::from(format!("logs/messages.{}", i)))
+        .chain((0..5000).map(|i| {
+            PathBuf::from(format!(
+                "logs/app-2024-{:02}-{:02}.log.{}",
+                i % 12 + 1,
+                i % 28 + 1,
+                i / 336
+            ))
+        }))
+        .collect();
+    // Deterministic shuffle, so that both sorts start from the same order
+    let mut state: u64 = 42;
+    for i in (1..names.len()).rev() {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1);
+        names.swap(i, (state >> 33) as usize % (i + 1));
+    }
+    names
+}
+
+/// The in-crate natural sort against the `alphanumeric-sort` crate it replaced.
+fn natural_sort(c: &mut Criterion) {
+    let names = file_names();
+    let mut group = c.benchmark_group("natural_sort");
+    group.bench_function("dir_lines_streamer", |b| {
+        b.iter_batched_ref(
+            || names.clone(),
+            |names| sort_files_natural(names),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("alphanumeric_sort", |b| {
+        b.iter_batched_ref(
+            || names.clone(),
+            |names| alphanumeric_sort::sort_path_slice(names),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, natural_sort);
+criterion_main!(benches);
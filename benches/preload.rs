@@ -0,0 +1,46 @@
+use std::fs;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use dir_lines_streamer::DirectoryLinesStreamer;
+
+/// Directory of 20 files of 5000 lines each.
+fn log_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..20 {
+        let content: String = (0..5000)
+            .map(|j| format!("line {} of messages.{}\n", j, i))
+            .collect();
+        fs::write(dir.path().join(format!("messages.{}", i)), content).unwrap();
+    }
+    dir
+}
+
+/// Construction and first line, then first line alone once constructed.
+fn preload(c: &mut Criterion) {
+    let dir = log_dir();
+    let mut group = c.benchmark_group("preload");
+    for preload in [false, true] {
+        let build = || {
+            DirectoryLinesStreamer::builder(dir.path())
+                .with_preload(preload)
+                .build()
+                .unwrap()
+        };
+        group.bench_function(format!("build_and_first_line/{}", preload), |b| {
+            b.iter(|| build().next().unwrap())
+        });
+        group.bench_function(format!("first_line/{}", preload), |b| {
+            // By reference, not to time dropping the streamer
+            b.iter_batched_ref(
+                build,
+                |streamer| streamer.next().unwrap(),
+                criterion::BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, preload);
+criterion_main!(benches);
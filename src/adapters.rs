@@ -0,0 +1,1366 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    builder::Options, log_error, strip_newline, DirectoryLinesStreamer,
+    DirectoryLinesStreamerBuilder, DirectoryLinesStreamerError,
+};
+
+/// Yields lines while the file they come from matches a predicate, see
+/// `DirectoryLinesStreamer::take_while_file()`.
+#[derive(Debug)]
+pub struct TakeWhileFile<F> {
+    streamer: DirectoryLinesStreamer,
+    predicate: F,
+    // Index of the first file the predicate was not evaluated on yet
+    next_index: usize,
+    done: bool,
+}
+
+impl<F> TakeWhileFile<F>
+where
+    F: Fn(&Path) -> bool,
+{
+    pub(crate) fn new(streamer: DirectoryLinesStreamer, predicate: F) -> TakeWhileFile<F> {
+        TakeWhileFile {
+            streamer,
+            predicate,
+            next_index: 0,
+            done: false,
+        }
+    }
+}
+
+impl<F> Iterator for TakeWhileFile<F>
+where
+    F: Fn(&Path) -> bool,
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.done {
+            return None;
+        }
+        let line = self.streamer.next()?;
+        // Evaluate the predicate once per file, including the files without lines skipped
+        // since the last line.
+        let index = self.streamer.current_file_index();
+        while self.next_index <= index {
+            let accepted = match self.streamer.all_files.get(self.next_index) {
+                Some(path) if self.next_index < index => {
+                    (self.predicate)(&self.streamer.options.strip_path_prefix(path.clone()))
+                }
+                _ => (self.predicate)(&self.streamer.opened_file_path),
+            };
+            if !accepted {
+                self.done = true;
+                return None;
+            }
+            self.next_index += 1;
+        }
+        Some(line)
+    }
+}
+
+/// Yields each line with the path of its file, see `DirectoryLinesStreamer::lines_with_path()`.
+#[derive(Debug)]
+pub struct LinesWithPath {
+    streamer: DirectoryLinesStreamer,
+}
+
+impl LinesWithPath {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer) -> LinesWithPath {
+        LinesWithPath { streamer }
+    }
+}
+
+impl Iterator for LinesWithPath {
+    type Item = (Arc<PathBuf>, String);
+
+    fn next(&mut self) -> Option<(Arc<PathBuf>, String)> {
+        let line = self.streamer.next()?;
+        let path = match &self.streamer.options.virtual_concat_filename {
+            Some(name) => name.clone(),
+            None => self.streamer.opened_file_path.clone(),
+        };
+        Some((path, line))
+    }
+}
+
+/// Information about a line yielded by `DirectoryLinesStreamer::lines_with_metadata()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMetadata {
+    /// `current_file()` when the line was read
+    pub file: Arc<PathBuf>,
+    /// `line_number()` when the line was read
+    pub line_number: usize,
+    /// Whether the line ended with the newline delimiter when read, before any
+    /// transformation; only the last line of a file can not
+    pub ends_with_newline: bool,
+    /// `stream_id()` of the streamer the line was read from
+    pub stream_id: u64,
+}
+
+/// Yields each line with its metadata, see `DirectoryLinesStreamer::lines_with_metadata()`.
+#[derive(Debug)]
+pub struct LinesWithMetadata {
+    lines: LinesWithPath,
+}
+
+impl LinesWithMetadata {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer) -> LinesWithMetadata {
+        LinesWithMetadata {
+            lines: LinesWithPath::new(streamer),
+        }
+    }
+}
+
+impl Iterator for LinesWithMetadata {
+    type Item = (LineMetadata, String);
+
+    fn next(&mut self) -> Option<(LineMetadata, String)> {
+        let (file, line) = self.lines.next()?;
+        let streamer = &self.lines.streamer;
+        let metadata = LineMetadata {
+            file,
+            line_number: streamer.line_number(),
+            ends_with_newline: streamer.at_separator || streamer.raw_line_ends_with_newline,
+            stream_id: streamer.stream_id(),
+        };
+        Some((metadata, line))
+    }
+}
+
+/// Yields the bytes of each line, see `DirectoryLinesStreamer::into_bytes_streamer()`.
+#[derive(Debug)]
+pub struct DirectoryBytesStreamer {
+    streamer: DirectoryLinesStreamer,
+}
+
+impl DirectoryBytesStreamer {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer) -> DirectoryBytesStreamer {
+        DirectoryBytesStreamer { streamer }
+    }
+
+    /// Path of the file the last line was read from.
+    pub fn current_file(&self) -> &Path {
+        self.streamer.current_file()
+    }
+}
+
+impl Iterator for DirectoryBytesStreamer {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        self.streamer.next_line_bytes()
+    }
+}
+
+/// Lines read in memory by `DirectoryLinesStreamer::prefetch_all()`.
+#[derive(Debug)]
+pub struct PrefetchedLinesStreamer {
+    lines: VecDeque<String>,
+    // Metadata of each line of `lines`
+    metadata: VecDeque<LineMetadata>,
+    // Metadata of the last yielded line
+    current: Option<LineMetadata>,
+}
+
+impl PrefetchedLinesStreamer {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer) -> PrefetchedLinesStreamer {
+        let (metadata, lines) = LinesWithMetadata::new(streamer).unzip();
+        PrefetchedLinesStreamer {
+            lines,
+            metadata,
+            current: None,
+        }
+    }
+
+    /// Number of lines left.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Whether all the lines were yielded.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// File the last yielded line was read from, `None` before the first line.
+    pub fn current_file(&self) -> Option<&Path> {
+        self.current
+            .as_ref()
+            .map(|metadata| metadata.file.as_path())
+    }
+
+    /// `DirectoryLinesStreamer::line_number()` of the last yielded line, `None` before the
+    /// first line.
+    pub fn line_number(&self) -> Option<usize> {
+        self.current.as_ref().map(|metadata| metadata.line_number)
+    }
+
+    /// Metadata of the last yielded line, `None` before the first line.
+    pub fn current_metadata(&self) -> Option<&LineMetadata> {
+        self.current.as_ref()
+    }
+}
+
+impl Iterator for PrefetchedLinesStreamer {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let line = self.lines.pop_front()?;
+        self.current = self.metadata.pop_front();
+        Some(line)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+/// Yields groups of consecutive lines with the same key, see
+/// `DirectoryLinesStreamer::group_consecutive_by()`.
+#[derive(Debug)]
+pub struct GroupConsecutiveBy<K, F> {
+    streamer: DirectoryLinesStreamer,
+    f: F,
+    // Key, file index and lines of the group being built
+    group: Option<(K, usize, Vec<String>)>,
+}
+
+impl<K, F> GroupConsecutiveBy<K, F> {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer, f: F) -> GroupConsecutiveBy<K, F> {
+        GroupConsecutiveBy {
+            streamer,
+            f,
+            group: None,
+        }
+    }
+}
+
+impl<K, F> Iterator for GroupConsecutiveBy<K, F>
+where
+    K: PartialEq,
+    F: Fn(&str) -> K,
+{
+    type Item = (K, Vec<String>);
+
+    fn next(&mut self) -> Option<(K, Vec<String>)> {
+        loop {
+            let line = match self.streamer.next() {
+                Some(line) => line,
+                None => return self.group.take().map(|(key, _, lines)| (key, lines)),
+            };
+            let key = (self.f)(&line);
+            let file_index = self.streamer.current_file_index();
+            match &mut self.group {
+                Some((group_key, group_file_index, lines))
+                    if *group_key == key && *group_file_index == file_index =>
+                {
+                    lines.push(line)
+                }
+                group => {
+                    let previous = group.replace((key, file_index, vec![line]));
+                    if let Some((key, _, lines)) = previous {
+                        return Some((key, lines));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Yields each line with the previous one, see `DirectoryLinesStreamer::zip_with_previous()`.
+#[derive(Debug)]
+pub struct ZipWithPrevious {
+    streamer: DirectoryLinesStreamer,
+    reset_on_file_boundary: bool,
+    // File index and content of the last yielded line
+    previous: Option<(usize, String)>,
+}
+
+impl ZipWithPrevious {
+    pub(crate) fn new(
+        streamer: DirectoryLinesStreamer,
+        reset_on_file_boundary: bool,
+    ) -> ZipWithPrevious {
+        ZipWithPrevious {
+            streamer,
+            reset_on_file_boundary,
+            previous: None,
+        }
+    }
+}
+
+impl Iterator for ZipWithPrevious {
+    type Item = (Option<String>, String);
+
+    fn next(&mut self) -> Option<(Option<String>, String)> {
+        let line = self.streamer.next()?;
+        let file_index = self.streamer.current_file_index();
+        let previous = self
+            .previous
+            .replace((file_index, line.clone()))
+            .filter(|(previous_index, _)| {
+                !self.reset_on_file_boundary || *previous_index == file_index
+            })
+            .map(|(_, previous)| previous);
+        Some((previous, line))
+    }
+}
+
+/// Line yielded by `DirectoryLinesStreamer::context_lines()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextLine {
+    /// Line as yielded by the streamer
+    pub line: String,
+    /// Whether the line is a match, rather than context of a match
+    pub is_match: bool,
+    /// Distance to the match the line is context of: negative before it, positive after it,
+    /// 0 for a match
+    pub context_distance: isize,
+}
+
+/// Yields the matching lines with the lines around them, see
+/// `DirectoryLinesStreamer::context_lines()`.
+#[derive(Debug)]
+pub struct ContextLines<F> {
+    streamer: DirectoryLinesStreamer,
+    before: usize,
+    after: usize,
+    is_match: F,
+    // Last lines not yielded, up to `before` of them
+    previous: VecDeque<String>,
+    // Lines ready to be yielded
+    ready: VecDeque<ContextLine>,
+    // Distance to the last match, while still in its `after` context
+    after_distance: Option<usize>,
+}
+
+impl<F> ContextLines<F> {
+    pub(crate) fn new(
+        streamer: DirectoryLinesStreamer,
+        before: usize,
+        after: usize,
+        is_match: F,
+    ) -> ContextLines<F> {
+        ContextLines {
+            streamer,
+            before,
+            after,
+            is_match,
+            // Grown as needed: `before` can be larger than the number of lines
+            previous: VecDeque::with_capacity(before.min(1024)),
+            ready: VecDeque::new(),
+            after_distance: None,
+        }
+    }
+}
+
+impl<F> Iterator for ContextLines<F>
+where
+    F: Fn(&str) -> bool,
+{
+    type Item = ContextLine;
+
+    fn next(&mut self) -> Option<ContextLine> {
+        loop {
+            if let Some(line) = self.ready.pop_front() {
+                return Some(line);
+            }
+            let line = self.streamer.next()?;
+            if (self.is_match)(&line) {
+                let nb_previous = self.previous.len() as isize;
+                for (i, line) in self.previous.drain(..).enumerate() {
+                    self.ready.push_back(ContextLine {
+                        line,
+                        is_match: false,
+                        context_distance: i as isize - nb_previous,
+                    });
+                }
+                self.ready.push_back(ContextLine {
+                    line,
+                    is_match: true,
+                    context_distance: 0,
+                });
+                self.after_distance = Some(0);
+                continue;
+            }
+            match self.after_distance {
+                Some(distance) if distance < self.after => {
+                    self.after_distance = Some(distance + 1);
+                    return Some(ContextLine {
+                        line,
+                        is_match: false,
+                        context_distance: distance as isize + 1,
+                    });
+                }
+                _ => {
+                    self.after_distance = None;
+                    if self.before > 0 {
+                        if self.previous.len() == self.before {
+                            self.previous.pop_front();
+                        }
+                        self.previous.push_back(line);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Yields streamers over successive groups of files, see
+/// `DirectoryLinesStreamer::window_files()`.
+#[derive(Debug)]
+pub struct WindowFiles {
+    dir: PathBuf,
+    options: Options,
+    // Files not part of a window yet, in order
+    files: VecDeque<PathBuf>,
+    n: usize,
+}
+
+impl WindowFiles {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer, n: usize) -> WindowFiles {
+        assert!(n > 0, "window_files() needs at least one file per window");
+        WindowFiles {
+            dir: streamer.dir,
+            options: options_for_listed_files(streamer.options),
+            files: streamer.all_files.into(),
+            n,
+        }
+    }
+}
+
+/// `options` to build a streamer over files already listed and selected with them.
+fn options_for_listed_files(mut options: Options) -> Options {
+    options.file_offset = 0;
+    options.file_limit = 0;
+    options.max_total_bytes = None;
+    options.start_position = None;
+    options.watch_interval = None;
+    options
+}
+
+impl WindowFiles {
+    /// Builder and files of the next window, to build its streamer elsewhere.
+    pub(crate) fn next_window(&mut self) -> Option<(DirectoryLinesStreamerBuilder, Vec<PathBuf>)> {
+        let nb_files = self.n.min(self.files.len());
+        if nb_files == 0 {
+            return None;
+        }
+        let window: Vec<PathBuf> = self.files.drain(..nb_files).collect();
+        let builder =
+            DirectoryLinesStreamerBuilder::from_options(self.dir.clone(), self.options.clone());
+        Some((builder, window))
+    }
+}
+
+impl Iterator for WindowFiles {
+    type Item = DirectoryLinesStreamer;
+
+    fn next(&mut self) -> Option<DirectoryLinesStreamer> {
+        loop {
+            let (builder, window) = self.next_window()?;
+            match builder.build_from_sorted_files(window) {
+                Ok(streamer) => return Some(streamer),
+                Err(error) => {
+                    log::debug!("Skipping window of files");
+                    log_error(&self.options, error);
+                }
+            }
+        }
+    }
+}
+
+/// Yields the lines of the same files several times, with the index of the pass, see
+/// `DirectoryLinesStreamer::multi_pass()`.
+#[derive(Debug)]
+pub struct MultiPassStreamer {
+    // Streamer of the current pass
+    streamer: Option<DirectoryLinesStreamer>,
+    dir: PathBuf,
+    options: Options,
+    files: Vec<PathBuf>,
+    pass: usize,
+    passes: usize,
+}
+
+impl MultiPassStreamer {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer, passes: usize) -> MultiPassStreamer {
+        let dir = streamer.dir.clone();
+        let options = options_for_listed_files(streamer.options.clone());
+        let files = streamer.all_files.clone();
+        MultiPassStreamer {
+            streamer: if passes > 0 { Some(streamer) } else { None },
+            dir,
+            options,
+            files,
+            pass: 0,
+            passes,
+        }
+    }
+}
+
+impl Iterator for MultiPassStreamer {
+    type Item = (usize, String);
+
+    fn next(&mut self) -> Option<(usize, String)> {
+        loop {
+            if let Some(line) = self.streamer.as_mut().and_then(Iterator::next) {
+                return Some((self.pass, line));
+            }
+            self.streamer = None;
+            self.pass += 1;
+            if self.pass >= self.passes {
+                return None;
+            }
+            let builder =
+                DirectoryLinesStreamerBuilder::from_options(self.dir.clone(), self.options.clone());
+            match builder.build_from_sorted_files(self.files.clone()) {
+                Ok(streamer) => self.streamer = Some(streamer),
+                Err(error) => {
+                    log::debug!("Skipping pass {}", self.pass);
+                    log_error(&self.options, error);
+                }
+            }
+        }
+    }
+}
+
+/// Yields lines until a sentinel line is found, see
+/// `DirectoryLinesStreamer::take_until_sentinel()`.
+#[derive(Debug)]
+pub struct TakeUntilSentinel {
+    streamer: DirectoryLinesStreamer,
+    sentinel: String,
+    inclusive: bool,
+    done: bool,
+}
+
+impl TakeUntilSentinel {
+    pub(crate) fn new(
+        streamer: DirectoryLinesStreamer,
+        sentinel: &str,
+        inclusive: bool,
+    ) -> TakeUntilSentinel {
+        TakeUntilSentinel {
+            streamer,
+            sentinel: sentinel.to_string(),
+            inclusive,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for TakeUntilSentinel {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.done {
+            return None;
+        }
+        let line = self.streamer.next()?;
+        if strip_newline(&line, self.streamer.options.newline) == self.sentinel {
+            self.done = true;
+            if !self.inclusive {
+                return None;
+            }
+        }
+        Some(line)
+    }
+}
+
+/// Yields lines from two streamers in turn, see `DirectoryLinesStreamer::interleave()`.
+#[derive(Debug)]
+pub struct Interleaved {
+    left: DirectoryLinesStreamer,
+    right: DirectoryLinesStreamer,
+    left_turn: bool,
+}
+
+impl Interleaved {
+    pub(crate) fn new(left: DirectoryLinesStreamer, right: DirectoryLinesStreamer) -> Interleaved {
+        Interleaved {
+            left,
+            right,
+            left_turn: true,
+        }
+    }
+}
+
+impl Iterator for Interleaved {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let (first, second) = if self.left_turn {
+            (&mut self.left, &mut self.right)
+        } else {
+            (&mut self.right, &mut self.left)
+        };
+        self.left_turn = !self.left_turn;
+        first.next().or_else(|| second.next())
+    }
+}
+
+/// Yields lines from several streamers in turn, see `DirectoryLinesStreamer::interleave_n()`.
+#[derive(Debug)]
+pub struct InterleavedN {
+    // Streamers not exhausted yet, in their original order
+    streamers: Vec<DirectoryLinesStreamer>,
+    cursor: usize,
+}
+
+impl InterleavedN {
+    pub(crate) fn new(streamers: Vec<DirectoryLinesStreamer>) -> InterleavedN {
+        InterleavedN {
+            streamers,
+            cursor: 0,
+        }
+    }
+}
+
+impl Iterator for InterleavedN {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while !self.streamers.is_empty() {
+            if self.cursor >= self.streamers.len() {
+                self.cursor = 0;
+            }
+            match self.streamers[self.cursor].next() {
+                Some(line) => {
+                    self.cursor += 1;
+                    return Some(line);
+                }
+                // The next streamer takes its place under the cursor.
+                None => {
+                    self.streamers.remove(self.cursor);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Yields values computed from each line and a running state, see
+/// `DirectoryLinesStreamer::scan_lines()`.
+#[derive(Debug)]
+pub struct ScanLines<S, F> {
+    streamer: DirectoryLinesStreamer,
+    state: S,
+    f: F,
+}
+
+impl<S, B, F> ScanLines<S, F>
+where
+    F: FnMut(&mut S, String) -> Option<B>,
+{
+    pub(crate) fn new(streamer: DirectoryLinesStreamer, initial: S, f: F) -> ScanLines<S, F> {
+        ScanLines {
+            streamer,
+            state: initial,
+            f,
+        }
+    }
+}
+
+impl<S, B, F> Iterator for ScanLines<S, F>
+where
+    F: FnMut(&mut S, String) -> Option<B>,
+{
+    type Item = B;
+
+    fn next(&mut self) -> Option<B> {
+        let line = self.streamer.next()?;
+        (self.f)(&mut self.state, line)
+    }
+}
+
+/// Yields lines until a number of bytes is reached, see `DirectoryLinesStreamer::limit_bytes()`.
+#[derive(Debug)]
+pub struct ByteLimited {
+    streamer: DirectoryLinesStreamer,
+    max_bytes: u64,
+    bytes_yielded: u64,
+}
+
+impl ByteLimited {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer, max_bytes: u64) -> ByteLimited {
+        ByteLimited {
+            streamer,
+            max_bytes,
+            bytes_yielded: 0,
+        }
+    }
+
+    /// Total length of the lines yielded so far, in bytes.
+    pub fn bytes_yielded(&self) -> u64 {
+        self.bytes_yielded
+    }
+}
+
+impl Iterator for ByteLimited {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.max_bytes == 0 || self.bytes_yielded > self.max_bytes {
+            return None;
+        }
+        let line = self.streamer.next()?;
+        self.bytes_yielded += line.len() as u64;
+        Some(line)
+    }
+}
+
+/// Yields the lines produced by a function from each line, see
+/// `DirectoryLinesStreamer::flat_map_lines()`.
+#[derive(Debug)]
+pub struct FlatMapLines<F, I>
+where
+    I: IntoIterator,
+{
+    streamer: DirectoryLinesStreamer,
+    f: F,
+    // Lines produced from the last line read, to yield before reading further
+    current: Option<I::IntoIter>,
+}
+
+impl<B, I, F> FlatMapLines<F, I>
+where
+    F: Fn(String) -> I,
+    I: IntoIterator<Item = B>,
+    B: Into<String>,
+{
+    pub(crate) fn new(streamer: DirectoryLinesStreamer, f: F) -> FlatMapLines<F, I> {
+        FlatMapLines {
+            streamer,
+            f,
+            current: None,
+        }
+    }
+}
+
+impl<B, I, F> Iterator for FlatMapLines<F, I>
+where
+    F: Fn(String) -> I,
+    I: IntoIterator<Item = B>,
+    B: Into<String>,
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            if let Some(line) = self.current.as_mut().and_then(Iterator::next) {
+                return Some(line.into());
+            }
+            let line = self.streamer.next()?;
+            self.current = Some((self.f)(line).into_iter());
+        }
+    }
+}
+
+/// Yields lines at a bounded rate, see `DirectoryLinesStreamer::rate_limit()`.
+#[derive(Debug)]
+pub struct RateLimited {
+    streamer: DirectoryLinesStreamer,
+    interval: Duration,
+    // When the next line may be yielded
+    next_slot: Option<Instant>,
+}
+
+impl RateLimited {
+    pub(crate) fn new(
+        streamer: DirectoryLinesStreamer,
+        lines_per_second: f64,
+    ) -> Result<RateLimited, DirectoryLinesStreamerError> {
+        if lines_per_second.is_nan() || lines_per_second <= 0.0 {
+            return Err(DirectoryLinesStreamerError::InvalidArgument(format!(
+                "lines_per_second must be strictly positive, not {}",
+                lines_per_second
+            )));
+        }
+        // The interval must be added to instants without overflowing
+        let interval = Duration::try_from_secs_f64(1.0 / lines_per_second)
+            .ok()
+            .filter(|interval| Instant::now().checked_add(*interval).is_some())
+            .ok_or_else(|| {
+                DirectoryLinesStreamerError::InvalidArgument(format!(
+                    "lines_per_second {} is too small",
+                    lines_per_second
+                ))
+            })?;
+        Ok(RateLimited {
+            streamer,
+            interval,
+            next_slot: None,
+        })
+    }
+}
+
+impl Iterator for RateLimited {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        // Read first, not to sleep before finding out there are no more lines
+        let line = self.streamer.next()?;
+        let now = Instant::now();
+        // Token bucket holding a single token: slots are scheduled from the previous slot,
+        // not from when the caller came back, so sleeping does not accumulate drift. A slow
+        // caller does not earn a burst of lines either.
+        let slot = match self.next_slot {
+            Some(slot) if slot > now => {
+                thread::sleep(slot - now);
+                slot
+            }
+            _ => now,
+        };
+        self.next_slot = Some(slot + self.interval);
+        Some(line)
+    }
+}
+
+/// Yields lines and the errors encountered while reading them, see
+/// `DirectoryLinesStreamer::try_lines()`.
+#[derive(Debug)]
+pub struct TryLines {
+    streamer: DirectoryLinesStreamer,
+    // Line read after errors that must be yielded first
+    pending_line: Option<String>,
+}
+
+impl TryLines {
+    pub(crate) fn new(streamer: DirectoryLinesStreamer) -> TryLines {
+        TryLines {
+            streamer,
+            pending_line: None,
+        }
+    }
+}
+
+impl Iterator for TryLines {
+    type Item = Result<String, DirectoryLinesStreamerError>;
+
+    fn next(&mut self) -> Option<Result<String, DirectoryLinesStreamerError>> {
+        if let Some(error) = self.streamer.errors.pop_front() {
+            return Some(Err(error));
+        }
+        if let Some(line) = self.pending_line.take() {
+            return Some(Ok(line));
+        }
+        let line = self.streamer.next();
+        match self.streamer.errors.pop_front() {
+            Some(error) => {
+                self.pending_line = line;
+                Some(Err(error))
+            }
+            None => line.map(Ok),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_while_file() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let lines: Vec<String> = streamer
+            .take_while_file(|path| path.file_name().unwrap() != "messages.10")
+            .collect();
+
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "line one from messages\n");
+        assert_eq!(lines[8], "line three from messages.2\n");
+    }
+
+    #[test]
+    fn take_while_file_rejects_first_file() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let mut iter = streamer.take_while_file(|_| false);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn take_while_file_rejects_empty_file() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/with-empty-dir").unwrap();
+        let lines: Vec<String> = streamer
+            .take_while_file(|path| path.file_name().unwrap() != "messages.2")
+            .collect();
+        assert_eq!(lines, &["one\n"]);
+    }
+
+    #[test]
+    fn lines_with_path() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let lines: Vec<(Arc<PathBuf>, String)> = streamer.lines_with_path().collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(
+            *lines[0].0,
+            Path::new("fixtures/non-empty-dir").join("messages")
+        );
+        assert_eq!(lines[0].1, "line one from messages\n");
+        assert!(Arc::ptr_eq(&lines[0].0, &lines[1].0));
+        assert!(!Arc::ptr_eq(&lines[2].0, &lines[3].0));
+        assert_eq!(
+            *lines[3].0,
+            Path::new("fixtures/non-empty-dir").join("messages.1")
+        );
+    }
+
+    #[test]
+    fn lines_with_metadata() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/incomplete-dir").unwrap();
+        let lines: Vec<(LineMetadata, String)> = streamer.lines_with_metadata().collect();
+        let ends_with_newline: Vec<bool> = lines
+            .iter()
+            .map(|(metadata, _)| metadata.ends_with_newline)
+            .collect();
+        assert_eq!(ends_with_newline, &[true, true, false, true, true, false]);
+        assert_eq!(lines[2].1, "partial");
+
+        // Whatever the lines are turned into
+        let streamer = DirectoryLinesStreamer::builder("fixtures/incomplete-dir")
+            .with_nul_terminated_lines(true)
+            .build()
+            .unwrap();
+        let nul_terminated: Vec<bool> = streamer
+            .lines_with_metadata()
+            .map(|(metadata, _)| metadata.ends_with_newline)
+            .collect();
+        assert_eq!(nul_terminated, ends_with_newline);
+        assert_eq!(lines[2].0.line_number, 3);
+        assert_eq!(
+            *lines[3].0.file,
+            Path::new("fixtures/incomplete-dir").join("messages.1")
+        );
+    }
+
+    #[test]
+    fn into_bytes_streamer() {
+        for dir in ["fixtures/non-empty-dir", "fixtures/invalid-utf8-dir"] {
+            let raw_bytes: Vec<Vec<u8>> = DirectoryLinesStreamer::from_dir(dir)
+                .unwrap()
+                .into_bytes_streamer()
+                .collect();
+            let lines: Vec<String> = raw_bytes
+                .iter()
+                .map(|line| String::from_utf8_lossy(line).into_owned())
+                .collect();
+            let expected: Vec<String> = DirectoryLinesStreamer::from_dir(dir).unwrap().collect();
+            assert_eq!(lines, expected);
+        }
+
+        let raw_bytes: Vec<Vec<u8>> = DirectoryLinesStreamer::from_dir("fixtures/invalid-utf8-dir")
+            .unwrap()
+            .into_bytes_streamer()
+            .collect();
+        assert!(raw_bytes.iter().any(|line| str::from_utf8(line).is_err()));
+    }
+
+    #[test]
+    fn into_bytes_streamer_after_lines_read() {
+        use std::{
+            io::{BufRead, Read},
+            sync::Mutex,
+        };
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let callback_calls = Arc::clone(&calls);
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_line_callback(move |_, line_number, _| {
+                callback_calls.lock().unwrap().push(line_number);
+            })
+            .build()
+            .unwrap();
+        let mut start = [0; 5];
+        streamer.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"line ");
+        let lines: Vec<Vec<u8>> = streamer.into_bytes_streamer().collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(lines[0], b"one from messages\n");
+        // Once per line, whether yielded as bytes or not
+        assert_eq!(*calls.lock().unwrap(), (1..=15).collect::<Vec<usize>>());
+
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        streamer.peek_first_line();
+        streamer.extend(vec!["injected\n".to_string()]);
+        let mut line = String::new();
+        streamer.read_line(&mut line).unwrap();
+        assert_eq!(line, "line one from messages\n");
+        let lines: Vec<Vec<u8>> = streamer.into_bytes_streamer().collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(lines[0], b"injected\n");
+        assert_eq!(lines[1], b"line two from messages\n");
+
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        streamer.peek_first_line();
+        let lines: Vec<Vec<u8>> = streamer.into_bytes_streamer().collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(lines[0], b"line one from messages\n");
+    }
+
+    #[test]
+    fn stream_id() {
+        let streamer = |dir, id| {
+            DirectoryLinesStreamer::builder(dir)
+                .with_stream_id(id)
+                .build()
+                .unwrap()
+                .lines_with_metadata()
+        };
+        let merged: Vec<(LineMetadata, String)> = streamer("fixtures/incomplete-dir", 1)
+            .chain(streamer("fixtures/non-empty-dir", 2))
+            .collect();
+        assert_eq!(merged.len(), 6 + 15);
+        for (metadata, _) in &merged {
+            let expected = if metadata.file.starts_with("fixtures/incomplete-dir") {
+                1
+            } else {
+                2
+            };
+            assert_eq!(metadata.stream_id, expected);
+        }
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.stream_id(), 0);
+    }
+
+    #[test]
+    fn zip_with_previous() {
+        let lines: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        let pairs: Vec<(Option<String>, String)> =
+            DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+                .unwrap()
+                .zip_with_previous()
+                .collect();
+        assert_eq!(pairs.len(), 15);
+        assert_eq!(pairs[0], (None, lines[0].clone()));
+        for i in 1..lines.len() {
+            assert_eq!(pairs[i], (Some(lines[i - 1].clone()), lines[i].clone()));
+        }
+
+        let pairs: Vec<(Option<String>, String)> =
+            DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+                .unwrap()
+                .zip_with_previous_reset_on_file_boundary()
+                .collect();
+        let first_lines: Vec<usize> = pairs
+            .iter()
+            .enumerate()
+            .filter(|(_, (previous, _))| previous.is_none())
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(first_lines, &[0, 3, 6, 9, 12]);
+        assert_eq!(pairs[4], (Some(lines[3].clone()), lines[4].clone()));
+    }
+
+    #[test]
+    fn context_lines() {
+        let lines = [
+            "a", "ERROR 1", "b", "c", "d", "e", "ERROR 2", "f", "ERROR 3", "g", "h", "i",
+        ];
+        let streamer =
+            DirectoryLinesStreamer::from_lines(lines.iter().map(|line| line.to_string()).collect());
+        let context: Vec<(String, bool, isize)> = streamer
+            .context_lines(2, 1, |line| line.starts_with("ERROR"))
+            .map(|context| {
+                let line = context.line.trim_end().to_string();
+                (line, context.is_match, context.context_distance)
+            })
+            .collect();
+        let expected = &[
+            ("a", false, -1),
+            ("ERROR 1", true, 0),
+            ("b", false, 1),
+            ("d", false, -2),
+            ("e", false, -1),
+            ("ERROR 2", true, 0),
+            ("f", false, 1),
+            ("ERROR 3", true, 0),
+            ("g", false, 1),
+        ];
+        let expected: Vec<(String, bool, isize)> = expected
+            .iter()
+            .map(|(line, is_match, distance)| (line.to_string(), *is_match, *distance))
+            .collect();
+        assert_eq!(context, expected);
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let context = streamer.context_lines(usize::MAX, 0, |line| line.contains("messages.20"));
+        assert_eq!(context.count(), 15);
+    }
+
+    #[test]
+    fn window_files() {
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        let windows: Vec<DirectoryLinesStreamer> =
+            DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+                .unwrap()
+                .window_files(2)
+                .collect();
+        let nb_files: Vec<usize> = windows.iter().map(|window| window.total_files()).collect();
+        assert_eq!(nb_files, &[2, 2, 1]);
+        let lines: Vec<String> = windows.into_iter().flatten().collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn multi_pass() {
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        let lines: Vec<(usize, String)> =
+            DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+                .unwrap()
+                .multi_pass(3)
+                .collect();
+        assert_eq!(lines.len(), 3 * expected.len());
+        for (pass, chunk) in lines.chunks(expected.len()).enumerate() {
+            assert!(chunk.iter().all(|(line_pass, _)| *line_pass == pass));
+            let pass_lines: Vec<String> = chunk.iter().map(|(_, line)| line.clone()).collect();
+            assert_eq!(pass_lines, expected);
+        }
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.multi_pass(0).count(), 0);
+    }
+
+    #[test]
+    fn prefetch_all() {
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        let mut prefetched = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .prefetch_all();
+        assert_eq!(prefetched.len(), 15);
+        assert!(!prefetched.is_empty());
+        assert_eq!(prefetched.current_file(), None);
+
+        assert_eq!(prefetched.next().unwrap(), expected[0]);
+        assert_eq!(prefetched.len(), 14);
+        assert_eq!(
+            prefetched.current_file(),
+            Some(Path::new("fixtures/non-empty-dir/messages"))
+        );
+        assert_eq!(prefetched.line_number(), Some(1));
+
+        let lines: Vec<String> = Iterator::by_ref(&mut prefetched).collect();
+        assert_eq!(lines, &expected[1..]);
+        assert_eq!(prefetched.len(), 0);
+        assert!(prefetched.is_empty());
+        assert_eq!(
+            prefetched.current_file(),
+            Some(Path::new("fixtures/non-empty-dir/messages.20"))
+        );
+        assert_eq!(prefetched.line_number(), Some(15));
+        assert!(prefetched.current_metadata().unwrap().ends_with_newline);
+    }
+
+    #[test]
+    fn group_consecutive_by() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/threads-dir").unwrap();
+        let groups: Vec<(String, usize)> = streamer
+            .group_consecutive_by(|line| line.split(' ').next().unwrap().to_string())
+            .map(|(key, lines)| (key, lines.len()))
+            .collect();
+        let expected = &[("t1", 2), ("t2", 1), ("t1", 1), ("t1", 2), ("t3", 1)];
+        let expected: Vec<(String, usize)> = expected
+            .iter()
+            .map(|(key, len)| (key.to_string(), *len))
+            .collect();
+        // The last `t1` group of `app.log` does not continue in `app.log.1`
+        assert_eq!(groups, expected);
+    }
+
+    #[test]
+    fn take_until_sentinel() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/sentinel-dir").unwrap();
+        let mut lines = streamer.take_until_sentinel("---END---");
+        let taken: Vec<String> = lines.by_ref().collect();
+        assert_eq!(taken, &["line one from messages\n", "---END---\n"]);
+        // The lines after the sentinel stay discarded
+        assert_eq!(lines.next(), None);
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/sentinel-dir").unwrap();
+        let lines: Vec<String> = streamer
+            .take_until_sentinel_exclusive("---END---")
+            .collect();
+        assert_eq!(lines, &["line one from messages\n"]);
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/sentinel-dir").unwrap();
+        assert_eq!(streamer.take_until_sentinel("not there").count(), 4);
+    }
+
+    #[test]
+    fn scan_lines() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        // Set by the streamer when it moves to the next file
+        let new_file = Arc::new(AtomicBool::new(false));
+        let callback_new_file = Arc::clone(&new_file);
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_transition_callback(move |_, _| {
+                callback_new_file.store(true, Ordering::Relaxed)
+            })
+            .build()
+            .unwrap();
+        let numbered: Vec<(usize, String)> = streamer
+            .scan_lines(0, |line_in_file, line| {
+                if new_file.swap(false, Ordering::Relaxed) {
+                    *line_in_file = 0;
+                }
+                *line_in_file += 1;
+                Some((*line_in_file, line))
+            })
+            .collect();
+
+        assert_eq!(numbered.len(), 15);
+        assert_eq!(numbered[2], (3, "line three from messages\n".to_string()));
+        assert_eq!(numbered[3], (1, "line one from messages.1\n".to_string()));
+        assert_eq!(
+            numbered[14],
+            (3, "line three from messages.20\n".to_string())
+        );
+
+        // Like `Iterator::scan()`, iteration stops once `f` returns `None`
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let count = streamer
+            .scan_lines(0, |nb_lines, _| {
+                *nb_lines += 1;
+                if *nb_lines <= 4 {
+                    Some(*nb_lines)
+                } else {
+                    None
+                }
+            })
+            .count();
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn limit_bytes() {
+        // Lines are 23 to 28 bytes long
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let mut limited = streamer.limit_bytes(100);
+        let lines: Vec<String> = Iterator::by_ref(&mut limited).collect();
+        assert_eq!(lines.len(), 5);
+        assert!(limited.bytes_yielded() > 100);
+        assert!(limited.bytes_yielded() <= 100 + 28);
+        assert_eq!(limited.next(), None);
+
+        // Reaching the limit exactly is not exceeding it
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let two_lines = lines[0].len() + lines[1].len();
+        assert_eq!(streamer.limit_bytes(two_lines as u64).count(), 3);
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.limit_bytes(0).next(), None);
+    }
+
+    #[test]
+    fn flat_map_lines() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let words: Vec<String> = streamer
+            .flat_map_lines(|line| {
+                line.split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<String>>()
+            })
+            .collect();
+        assert_eq!(words.len(), 15 * 4);
+        assert_eq!(&words[..4], &["line", "one", "from", "messages"]);
+
+        // Lines can also be dropped
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.flat_map_lines(|_| None::<String>).count(), 0);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let start = Instant::now();
+        // The first line is yielded right away, the next 10 lines one every 100ms
+        assert_eq!(streamer.rate_limit(10.0).unwrap().take(11).count(), 11);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(1000), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1100), "{:?}", elapsed);
+
+        // The end of the lines is not delayed
+        let streamer = DirectoryLinesStreamer::from_lines(vec!["one".to_string()]);
+        let mut lines = streamer.rate_limit(0.5).unwrap();
+        assert_eq!(lines.next().unwrap(), "one\n");
+        let start = Instant::now();
+        assert_eq!(lines.next(), None);
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        for lines_per_second in [0.0, -1.0, f64::NAN, 1e-300] {
+            let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+            match streamer.rate_limit(lines_per_second) {
+                Err(DirectoryLinesStreamerError::InvalidArgument(_)) => {}
+                _ => panic!("Unexpected result for {}", lines_per_second),
+            }
+        }
+    }
+
+    #[test]
+    fn interleave() {
+        let left = DirectoryLinesStreamer::from_dir("fixtures/sentinel-dir").unwrap();
+        let right = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let lines: Vec<String> = left.interleave(right).collect();
+
+        let left: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/sentinel-dir")
+            .unwrap()
+            .collect();
+        let right: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), left.len() + right.len());
+        for (i, line) in left.iter().enumerate() {
+            assert_eq!(&lines[2 * i], line);
+            assert_eq!(&lines[2 * i + 1], &right[i]);
+        }
+        // The remaining lines of the longer stream come last
+        assert_eq!(lines[2 * left.len()..], right[left.len()..]);
+    }
+
+    #[test]
+    fn interleave_n() {
+        let streamers = vec![
+            DirectoryLinesStreamer::from_lines(vec!["a1".to_string(), "a2".to_string()]),
+            DirectoryLinesStreamer::from_lines(
+                ["b1", "b2", "b3", "b4"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            ),
+            DirectoryLinesStreamer::from_lines(vec!["c1".to_string()]),
+        ];
+        let lines: Vec<String> = DirectoryLinesStreamer::interleave_n(streamers).collect();
+        assert_eq!(
+            lines,
+            &["a1\n", "b1\n", "c1\n", "a2\n", "b2\n", "b3\n", "b4\n"]
+        );
+
+        assert_eq!(
+            DirectoryLinesStreamer::interleave_n(Vec::new()).next(),
+            None
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::{collections::VecDeque, path::PathBuf};
+
+use futures::{stream, Stream};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncBufReadExt, BufReader},
+};
+
+use crate::{
+    builder::Options, sort::sort_files, DirectoryLinesStreamer, DirectoryLinesStreamerError,
+};
+
+/// State of the stream returned by `DirectoryLinesStreamer::from_dir_async()`.
+struct AsyncLines {
+    // Remaining files, in order
+    files: VecDeque<PathBuf>,
+    opened_file: Option<(PathBuf, BufReader<File>)>,
+    line_id: usize,
+}
+
+impl AsyncLines {
+    async fn next_line(&mut self) -> Option<Result<String, DirectoryLinesStreamerError>> {
+        loop {
+            let (path, reader) = match &mut self.opened_file {
+                Some(opened_file) => opened_file,
+                None => {
+                    let path = self.files.pop_front()?;
+                    log::debug!("Opening next file: {:?}", path);
+                    match File::open(&path).await {
+                        Ok(file) => self.opened_file.insert((path, BufReader::new(file))),
+                        Err(error) => {
+                            return Some(Err(DirectoryLinesStreamerError::OpenFile {
+                                file: path,
+                                error,
+                            }))
+                        }
+                    }
+                }
+            };
+            let mut buf = Vec::new();
+            match reader.read_until(b'\n', &mut buf).await {
+                // EOF reached. Try next file
+                Ok(0) => self.opened_file = None,
+                Ok(_) => {
+                    self.line_id += 1;
+                    return Some(Ok(String::from_utf8_lossy(&buf).to_string()));
+                }
+                Err(error) => {
+                    let error = DirectoryLinesStreamerError::ReadLine {
+                        file: path.clone(),
+                        line_id: self.line_id + 1,
+                        error,
+                    };
+                    // Do not retry a failing file forever
+                    self.opened_file = None;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+
+    /// List and order the files of `dir` like `DirectoryLinesStreamer::from_dir()` does.
+    async fn from_dir(dir: PathBuf) -> Result<AsyncLines, DirectoryLinesStreamerError> {
+        if !fs::try_exists(&dir).await.unwrap_or(false) {
+            return Err(DirectoryLinesStreamerError::DirectoryDoesNotExists(dir));
+        }
+        let mut dir_entries = fs::read_dir(&dir)
+            .await
+            .map_err(DirectoryLinesStreamerError::Io)?;
+        let mut files: Vec<PathBuf> = Vec::new();
+        while let Some(dir_entry) = dir_entries
+            .next_entry()
+            .await
+            .map_err(DirectoryLinesStreamerError::Io)?
+        {
+            let path = dir_entry.path();
+            match fs::metadata(&path).await {
+                Ok(metadata) if !metadata.is_file() => {
+                    log::debug!("Skipping {:?}: not a regular file", path)
+                }
+                // Entries that cannot be inspected are kept so that opening them reports the error.
+                _ => files.push(path),
+            }
+        }
+        sort_files(&mut files, &Options::default());
+        log::debug!("files: {:?}", files);
+        if files.is_empty() {
+            return Err(DirectoryLinesStreamerError::EmptyDirectory(dir));
+        }
+
+        Ok(AsyncLines {
+            files: files.into(),
+            opened_file: None,
+            line_id: 0,
+        })
+    }
+}
+
+impl DirectoryLinesStreamer {
+    /// Stream the lines of the files in `input_dir` without blocking the async runtime.
+    ///
+    /// Files are listed and ordered like `from_dir()` does; errors are yielded in the stream.
+    pub async fn from_dir_async<P>(
+        input_dir: P,
+    ) -> Result<
+        impl Stream<Item = Result<String, DirectoryLinesStreamerError>>,
+        DirectoryLinesStreamerError,
+    >
+    where
+        P: Into<PathBuf>,
+    {
+        let lines = AsyncLines::from_dir(input_dir.into()).await?;
+        Ok(stream::unfold(lines, |mut lines| async move {
+            let line = lines.next_line().await?;
+            Some((line, lines))
+        }))
+    }
+
+    /// Like `lines_per_file()`, without blocking the async runtime.
+    ///
+    /// Fails on the first error encountered while reading.
+    pub async fn lines_per_file_async<P>(
+        input_dir: P,
+    ) -> Result<Vec<(PathBuf, usize)>, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        let mut lines = AsyncLines::from_dir(input_dir.into()).await?;
+        let mut counts: Vec<(PathBuf, usize)> =
+            lines.files.iter().map(|path| (path.clone(), 0)).collect();
+        let mut index = 0;
+        while let Some(line) = lines.next_line().await {
+            line?;
+            // Set since a line was just read from it
+            let (path, _) = lines.opened_file.as_ref().unwrap();
+            // Files are read in order, the ones skipped had no line
+            while counts[index].0 != *path {
+                index += 1;
+            }
+            counts[index].1 += 1;
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn from_dir_async() {
+        let stream = DirectoryLinesStreamer::from_dir_async("fixtures/non-empty-dir")
+            .await
+            .unwrap();
+        let lines: Vec<String> = stream.map(Result::unwrap).collect().await;
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        assert_eq!(lines, expected);
+
+        assert!(
+            DirectoryLinesStreamer::from_dir_async("fixtures/non-existing-dir")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn lines_per_file_async() {
+        let counts = DirectoryLinesStreamer::lines_per_file_async("fixtures/non-empty-dir")
+            .await
+            .unwrap();
+        let expected = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .lines_per_file();
+        assert_eq!(counts, expected);
+
+        let counts = DirectoryLinesStreamer::lines_per_file_async("fixtures/with-empty-dir")
+            .await
+            .unwrap();
+        let expected = DirectoryLinesStreamer::from_dir("fixtures/with-empty-dir")
+            .unwrap()
+            .lines_per_file();
+        assert_eq!(counts, expected);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{builder::AuditLog, DirectoryLinesStreamer, DirectoryLinesStreamerError};
+
+impl DirectoryLinesStreamer {
+    /// Record that the current file was opened, see `with_audit_log()`.
+    pub(crate) fn audit_open(&mut self) {
+        if let Some(audit_log) = &self.options.audit_log {
+            let line = format!(
+                "{{\"event\":\"open\",\"stream_id\":{},\"path\":{},\"timestamp\":\"{}\"}}\n",
+                self.options.stream_id,
+                json_string(&self.opened_file_path),
+                rfc3339_now()
+            );
+            let written = write_line(audit_log, &line);
+            self.audit_file_open = true;
+            self.report_audit_error(written);
+        }
+    }
+
+    /// Record that the current file was read until its end, see `with_audit_log()`.
+    pub(crate) fn audit_close(&mut self) {
+        if !self.audit_file_open {
+            return;
+        }
+        self.audit_file_open = false;
+        if let Some(audit_log) = &self.options.audit_log {
+            let line = format!(
+                concat!(
+                    "{{\"event\":\"close\",\"stream_id\":{},\"path\":{},",
+                    "\"lines_read\":{},\"bytes_read\":{}}}\n"
+                ),
+                self.options.stream_id,
+                json_string(&self.opened_file_path),
+                self.file_position.lines,
+                self.file_position.byte_offset
+            );
+            let written = write_line(audit_log, &line);
+            self.report_audit_error(written);
+        }
+    }
+
+    fn report_audit_error(&mut self, written: io::Result<()>) {
+        if let Err(error) = written {
+            let error = io::Error::new(
+                error.kind(),
+                format!("failed to write to the audit log: {}", error),
+            );
+            self.emit_error(DirectoryLinesStreamerError::Io(error));
+        }
+    }
+}
+
+fn write_line(audit_log: &AuditLog, line: &str) -> io::Result<()> {
+    let mut writer = audit_log.lock().unwrap();
+    writer.write_all(line.as_bytes())?;
+    writer.flush()
+}
+
+/// `path` as a JSON string literal.
+fn json_string(path: &Path) -> String {
+    let mut json = String::from("\"");
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Current UTC time, like `2024-01-31T12:34:56.789Z`.
+fn rfc3339_now() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        now.subsec_millis()
+    )
+}
+
+/// Date of the day `days` after 1970-01-01, from Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use crate::ErrorPolicy;
+
+    /// Audit sink readable once given to the builder.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn audit_log() {
+        let buffer = SharedBuffer::default();
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_audit_log(buffer.clone())
+            .with_stream_id(7)
+            .build()
+            .unwrap();
+        let total_files = streamer.total_files();
+        assert_eq!(streamer.count(), 15);
+
+        let content = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<&str> = content.lines().collect();
+        assert_eq!(events.len(), 2 * total_files);
+        let file_names = [
+            "messages",
+            "messages.1",
+            "messages.2",
+            "messages.10",
+            "messages.20",
+        ];
+        for (i, file_name) in file_names.iter().enumerate() {
+            let path = Path::new("fixtures/non-empty-dir").join(file_name);
+            let json_path = format!("\"path\":{}", json_string(&path));
+            assert!(events[2 * i].starts_with("{\"event\":\"open\",\"stream_id\":7,"));
+            assert!(events[2 * i].contains(&json_path));
+            assert_eq!(
+                events[2 * i + 1],
+                format!(
+                    "{{\"event\":\"close\",\"stream_id\":7,{},\"lines_read\":3,\"bytes_read\":{}}}",
+                    json_path,
+                    std::fs::metadata(&path).unwrap().len()
+                )
+            );
+        }
+    }
+
+    /// Audit sink failing every write.
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn audit_log_errors() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_audit_log(FailingWriter)
+            .with_error_policy(ErrorPolicy::Collect)
+            .build()
+            .unwrap();
+        let (lines, errors) = streamer.collect_errors();
+        assert_eq!(lines.len(), 15);
+        // Opening and closing each file
+        assert_eq!(errors.len(), 2 * 5);
+        assert!(errors.iter().all(|error| error
+            .to_string()
+            .starts_with("failed to write to the audit log")));
+    }
+
+    #[test]
+    #[should_panic(expected = "failed to write to the audit log: disk full")]
+    fn audit_log_eager_error_reporting() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_audit_log(FailingWriter)
+            .with_eager_error_reporting(true)
+            .build()
+            .unwrap();
+        streamer.for_each(drop);
+    }
+
+    #[test]
+    fn rfc3339() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_753), (2024, 1, 31));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(
+            json_string(Path::new("a\"b\\c\n")),
+            "\"a\\\"b\\\\c\\u000a\""
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::{collections::hash_map::RandomState, hash::BuildHasher, time::Duration};
+
+/// Delay before retrying to open a file, see
+/// `DirectoryLinesStreamerBuilder::with_open_retries()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackoffStrategy {
+    /// Always wait the same duration.
+    Fixed(Duration),
+    /// Wait `start`, then `step` longer after each failed retry.
+    Linear { start: Duration, step: Duration },
+    /// Wait `base`, then `factor` times longer after each failed retry, up to `max`.
+    Exponential {
+        base: Duration,
+        factor: f64,
+        max: Duration,
+    },
+    /// Add a random duration, up to the given one, to the delays of a strategy.
+    Jitter(Box<BackoffStrategy>, Duration),
+}
+
+impl BackoffStrategy {
+    /// Delay before the retry number `attempt` (0 for the first retry).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match self {
+            BackoffStrategy::Fixed(delay) => *delay,
+            BackoffStrategy::Linear { start, step } => {
+                start.saturating_add(step.saturating_mul(attempt))
+            }
+            BackoffStrategy::Exponential { base, factor, max } => {
+                let secs = base.as_secs_f64() * factor.powi(attempt.min(i32::MAX as u32) as i32);
+                if secs.is_finite() && secs < max.as_secs_f64() {
+                    Duration::from_secs_f64(secs.max(0.0))
+                } else {
+                    *max
+                }
+            }
+            BackoffStrategy::Jitter(strategy, max_jitter) => {
+                // Rounding can make the product slightly larger than `max_jitter`
+                let jitter = max_jitter.as_secs_f64() * random_fraction(attempt);
+                let jitter = Duration::try_from_secs_f64(jitter)
+                    .map_or(*max_jitter, |jitter| jitter.min(*max_jitter));
+                strategy.delay(attempt).saturating_add(jitter)
+            }
+        }
+    }
+}
+
+/// Pseudo-random number in `[0, 1]`, with a new seed on every call.
+fn random_fraction(attempt: u32) -> f64 {
+    RandomState::new().hash_one(attempt) as f64 / u64::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays() {
+        let fixed = BackoffStrategy::Fixed(Duration::from_millis(10));
+        assert_eq!(fixed.delay(0), Duration::from_millis(10));
+        assert_eq!(fixed.delay(5), Duration::from_millis(10));
+
+        let linear = BackoffStrategy::Linear {
+            start: Duration::from_millis(10),
+            step: Duration::from_millis(5),
+        };
+        assert_eq!(linear.delay(0), Duration::from_millis(10));
+        assert_eq!(linear.delay(2), Duration::from_millis(20));
+
+        let exponential = BackoffStrategy::Exponential {
+            base: Duration::from_millis(10),
+            factor: 2.0,
+            max: Duration::from_millis(50),
+        };
+        let delays: Vec<Duration> = (0..5).map(|attempt| exponential.delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            &[
+                Duration::from_millis(10),
+                Duration::from_millis(20),
+                Duration::from_millis(40),
+                Duration::from_millis(50),
+                Duration::from_millis(50),
+            ]
+        );
+        assert_eq!(exponential.delay(u32::MAX), Duration::from_millis(50));
+
+        let jitter = BackoffStrategy::Jitter(Box::new(fixed), Duration::from_millis(5));
+        for attempt in 0..10 {
+            let delay = jitter.delay(attempt);
+            assert!(delay >= Duration::from_millis(10) && delay <= Duration::from_millis(15));
+        }
+
+        let jitter = BackoffStrategy::Jitter(
+            Box::new(BackoffStrategy::Fixed(Duration::MAX)),
+            Duration::MAX,
+        );
+        for attempt in 0..10 {
+            assert_eq!(jitter.delay(attempt), Duration::MAX);
+        }
+    }
+}
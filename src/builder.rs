@@ -0,0 +1,2441 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "chrono")]
+use chrono::NaiveDate;
+
+#[cfg(feature = "ioprio")]
+use crate::ioprio::{set_io_priority, IoPriority};
+#[cfg(feature = "chrono")]
+use crate::template::FileNameTemplate;
+#[cfg(feature = "unicode-normalization")]
+use crate::NormalizationForm;
+use crate::{
+    open::{file_metadata, is_open_timeout, open_reader, open_source},
+    position::FilePosition,
+    preread::Prereader,
+    rename::{file_identity, FileIdentity},
+    sort::{compare_file_names, select_files_per_directory, sort_files, SelectMode},
+    BackoffStrategy, DirectoryLinesStreamer, DirectoryLinesStreamerError, ErrorPolicy,
+    LineNumberingMode, OpenFlags, StreamPosition, Utf8Mode, VirtualMetadata,
+};
+
+pub(crate) type AuditLog = Arc<Mutex<Box<dyn Write + Send>>>;
+pub(crate) type FileTransitionCallback = Arc<dyn Fn(Option<&Path>, &Path) + Send + Sync>;
+pub(crate) type LineCallback = Arc<dyn Fn(&Path, usize, &str) + Send + Sync>;
+pub(crate) type FilePriority = Arc<dyn Fn(&Path) -> i64 + Send + Sync>;
+pub(crate) type FileSelector = Arc<dyn Fn(&[PathBuf]) -> Vec<PathBuf> + Send + Sync>;
+pub(crate) type PathMapper = Arc<dyn Fn(PathBuf) -> PathBuf + Send + Sync>;
+pub(crate) type ErrorCallback = Arc<dyn Fn(&DirectoryLinesStreamerError) + Send + Sync>;
+pub(crate) type Scanner = Arc<dyn Fn() -> io::Result<Vec<PathBuf>> + Send + Sync>;
+pub(crate) type FileNameNormalizer = Arc<dyn Fn(&OsStr) -> OsString + Send + Sync>;
+pub(crate) type OpenOptionsFactory = Arc<dyn Fn(&Path) -> OpenOptions + Send + Sync>;
+pub(crate) type MetadataSource = Arc<dyn Fn(&Path) -> Option<VirtualMetadata> + Send + Sync>;
+pub(crate) type Sleeper = Arc<dyn Fn(Duration) + Send + Sync>;
+// Digests of whole files by path, valid while their size and modification time stay the same
+pub(crate) type DigestCache = Arc<Mutex<HashMap<PathBuf, (u64, SystemTime, [u8; 32])>>>;
+pub(crate) type CustomOpen = Arc<dyn Fn(&Path) -> io::Result<Box<dyn Read + Send>> + Send + Sync>;
+
+/// Settings shared between the builder and the streamer it builds.
+#[derive(Clone)]
+pub(crate) struct Options {
+    pub(crate) newline: u8,
+    pub(crate) interfile_separator: Option<String>,
+    pub(crate) empty_file_placeholder: Option<String>,
+    pub(crate) utf8_mode: Utf8Mode,
+    pub(crate) error_policy: ErrorPolicy,
+    pub(crate) on_error: Option<ErrorCallback>,
+    pub(crate) eager_error_reporting: bool,
+    pub(crate) preload: bool,
+    pub(crate) concurrent_file_preread: usize,
+    pub(crate) cached_file_handles: bool,
+    pub(crate) max_open_files: usize,
+    pub(crate) open_flags: OpenFlags,
+    pub(crate) fadvise_sequential: bool,
+    pub(crate) fadvise_willneed: bool,
+    pub(crate) open_options: Option<OpenOptionsFactory>,
+    pub(crate) allow_fifos: bool,
+    pub(crate) recursive: bool,
+    pub(crate) deduplicate_files: bool,
+    // Shared by the scans of a watched directory
+    pub(crate) digest_cache: DigestCache,
+    pub(crate) inode_deduplication: bool,
+    pub(crate) file_offset: usize,
+    pub(crate) file_limit: usize,
+    pub(crate) max_total_bytes: Option<u64>,
+    pub(crate) file_size_limit: Option<u64>,
+    pub(crate) stable_sort: bool,
+    pub(crate) case_insensitive_sort: bool,
+    pub(crate) symlink_target_sort: bool,
+    pub(crate) file_priority: Option<FilePriority>,
+    pub(crate) file_selector: Option<FileSelector>,
+    pub(crate) select_files_per_directory: Option<SelectMode>,
+    pub(crate) file_order_override: Option<Vec<PathBuf>>,
+    pub(crate) file_name_normalizer: Option<FileNameNormalizer>,
+    pub(crate) magic_byte_detection: bool,
+    pub(crate) path_prefix_strip: Option<PathBuf>,
+    pub(crate) virtual_concat_filename: Option<Arc<PathBuf>>,
+    pub(crate) flatten_json_arrays: bool,
+    #[cfg(feature = "unicode-normalization")]
+    pub(crate) utf8_normalization: Option<NormalizationForm>,
+    #[cfg(feature = "chrono")]
+    pub(crate) file_name_template_filter: Option<FileNameTemplate>,
+    #[cfg(feature = "ioprio")]
+    pub(crate) io_priority: Option<IoPriority>,
+    pub(crate) line_buffer_reuse: bool,
+    pub(crate) nul_terminated_lines: bool,
+    pub(crate) skip_first_lines: usize,
+    pub(crate) drop_incomplete_last_line: bool,
+    pub(crate) line_ending_statistics: bool,
+    pub(crate) record_prefix: Option<String>,
+    pub(crate) record_prefix_filter: bool,
+    pub(crate) take_last_lines: Option<usize>,
+    pub(crate) reverse_line_order: bool,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) per_line_timeout: Option<Duration>,
+    pub(crate) watch_interval: Option<Duration>,
+    pub(crate) file_rename_detection: bool,
+    pub(crate) rename_check_interval: usize,
+    pub(crate) open_timeout: Option<Duration>,
+    pub(crate) open_retries: u32,
+    pub(crate) open_backoff: BackoffStrategy,
+    // `thread::sleep()` if not set
+    pub(crate) sleep: Option<Sleeper>,
+    pub(crate) start_position: Option<StreamPosition>,
+    pub(crate) line_number_offset: usize,
+    pub(crate) line_numbering_mode: LineNumberingMode,
+    pub(crate) file_transition_callback: Option<FileTransitionCallback>,
+    pub(crate) line_callback: Option<LineCallback>,
+    pub(crate) audit_log: Option<AuditLog>,
+    pub(crate) stream_id: u64,
+    pub(crate) path_mapper: Option<PathMapper>,
+    pub(crate) scanner: Option<Scanner>,
+    pub(crate) custom_open: Option<CustomOpen>,
+    pub(crate) metadata_source: Option<MetadataSource>,
+}
+
+impl fmt::Debug for Options {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Options");
+        debug
+            .field("newline", &self.newline)
+            .field("interfile_separator", &self.interfile_separator)
+            .field("empty_file_placeholder", &self.empty_file_placeholder)
+            .field("utf8_mode", &self.utf8_mode)
+            .field("error_policy", &self.error_policy)
+            .field("on_error", &self.on_error.is_some())
+            .field("eager_error_reporting", &self.eager_error_reporting)
+            .field("preload", &self.preload)
+            .field("concurrent_file_preread", &self.concurrent_file_preread)
+            .field("cached_file_handles", &self.cached_file_handles)
+            .field("max_open_files", &self.max_open_files)
+            .field("open_flags", &self.open_flags)
+            .field("fadvise_sequential", &self.fadvise_sequential)
+            .field("fadvise_willneed", &self.fadvise_willneed)
+            .field("open_options", &self.open_options.is_some())
+            .field("allow_fifos", &self.allow_fifos)
+            .field("recursive", &self.recursive)
+            .field("deduplicate_files", &self.deduplicate_files)
+            .field("inode_deduplication", &self.inode_deduplication)
+            .field("file_offset", &self.file_offset)
+            .field("file_limit", &self.file_limit)
+            .field("max_total_bytes", &self.max_total_bytes)
+            .field("file_size_limit", &self.file_size_limit)
+            .field("stable_sort", &self.stable_sort)
+            .field("case_insensitive_sort", &self.case_insensitive_sort)
+            .field("symlink_target_sort", &self.symlink_target_sort)
+            .field("file_priority", &self.file_priority.is_some())
+            .field("file_selector", &self.file_selector.is_some())
+            .field("file_order_override", &self.file_order_override)
+            .field(
+                "select_files_per_directory",
+                &self.select_files_per_directory,
+            )
+            .field("file_name_normalizer", &self.file_name_normalizer.is_some())
+            .field("magic_byte_detection", &self.magic_byte_detection)
+            .field("path_prefix_strip", &self.path_prefix_strip)
+            .field("virtual_concat_filename", &self.virtual_concat_filename)
+            .field("flatten_json_arrays", &self.flatten_json_arrays)
+            .field("line_buffer_reuse", &self.line_buffer_reuse)
+            .field("nul_terminated_lines", &self.nul_terminated_lines)
+            .field("skip_first_lines", &self.skip_first_lines)
+            .field("drop_incomplete_last_line", &self.drop_incomplete_last_line)
+            .field("line_ending_statistics", &self.line_ending_statistics)
+            .field("record_prefix", &self.record_prefix)
+            .field("record_prefix_filter", &self.record_prefix_filter)
+            .field("take_last_lines", &self.take_last_lines)
+            .field("reverse_line_order", &self.reverse_line_order)
+            .field("read_timeout", &self.read_timeout)
+            .field("per_line_timeout", &self.per_line_timeout)
+            .field("watch_interval", &self.watch_interval)
+            .field("file_rename_detection", &self.file_rename_detection)
+            .field("rename_check_interval", &self.rename_check_interval)
+            .field("open_timeout", &self.open_timeout)
+            .field("open_retries", &self.open_retries)
+            .field("open_backoff", &self.open_backoff)
+            .field("sleep", &self.sleep.is_some())
+            .field("start_position", &self.start_position)
+            .field("line_number_offset", &self.line_number_offset)
+            .field("line_numbering_mode", &self.line_numbering_mode)
+            .field(
+                "file_transition_callback",
+                &self.file_transition_callback.is_some(),
+            )
+            .field("line_callback", &self.line_callback.is_some())
+            .field("audit_log", &self.audit_log.is_some())
+            .field("stream_id", &self.stream_id)
+            .field("path_mapper", &self.path_mapper.is_some())
+            .field("scanner", &self.scanner.is_some())
+            .field("custom_open", &self.custom_open.is_some())
+            .field("metadata_source", &self.metadata_source.is_some());
+        #[cfg(feature = "unicode-normalization")]
+        debug.field("utf8_normalization", &self.utf8_normalization);
+        #[cfg(feature = "ioprio")]
+        debug.field("io_priority", &self.io_priority);
+        #[cfg(feature = "chrono")]
+        debug.field("file_name_template_filter", &self.file_name_template_filter);
+        debug.finish()
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            newline: b'\n',
+            interfile_separator: None,
+            empty_file_placeholder: None,
+            utf8_mode: Utf8Mode::Lossy,
+            error_policy: ErrorPolicy::Log,
+            on_error: None,
+            eager_error_reporting: false,
+            preload: false,
+            concurrent_file_preread: 0,
+            cached_file_handles: false,
+            max_open_files: 64,
+            open_flags: OpenFlags::empty(),
+            fadvise_sequential: false,
+            fadvise_willneed: false,
+            open_options: None,
+            allow_fifos: false,
+            recursive: false,
+            deduplicate_files: false,
+            digest_cache: DigestCache::default(),
+            inode_deduplication: false,
+            file_offset: 0,
+            file_limit: 0,
+            max_total_bytes: None,
+            file_size_limit: None,
+            stable_sort: false,
+            case_insensitive_sort: false,
+            symlink_target_sort: false,
+            file_priority: None,
+            file_selector: None,
+            select_files_per_directory: None,
+            file_order_override: None,
+            file_name_normalizer: None,
+            magic_byte_detection: false,
+            path_prefix_strip: None,
+            virtual_concat_filename: None,
+            flatten_json_arrays: false,
+            #[cfg(feature = "unicode-normalization")]
+            utf8_normalization: None,
+            #[cfg(feature = "chrono")]
+            file_name_template_filter: None,
+            #[cfg(feature = "ioprio")]
+            io_priority: None,
+            line_buffer_reuse: false,
+            nul_terminated_lines: false,
+            skip_first_lines: 0,
+            drop_incomplete_last_line: false,
+            line_ending_statistics: false,
+            record_prefix: None,
+            record_prefix_filter: false,
+            take_last_lines: None,
+            reverse_line_order: false,
+            read_timeout: None,
+            per_line_timeout: None,
+            watch_interval: None,
+            file_rename_detection: false,
+            rename_check_interval: 100,
+            open_timeout: None,
+            open_retries: 0,
+            open_backoff: BackoffStrategy::Fixed(Duration::from_millis(100)),
+            sleep: None,
+            start_position: None,
+            line_number_offset: 0,
+            line_numbering_mode: LineNumberingMode::LineCount,
+            file_transition_callback: None,
+            line_callback: None,
+            audit_log: None,
+            stream_id: 0,
+            path_mapper: None,
+            scanner: None,
+            custom_open: None,
+            metadata_source: None,
+        }
+    }
+}
+
+impl Options {
+    /// Path of an opened file as reported by the streamer, see `with_path_prefix_strip()`.
+    pub(crate) fn strip_path_prefix(&self, path: PathBuf) -> PathBuf {
+        match &self.path_prefix_strip {
+            Some(prefix) => match path.strip_prefix(prefix) {
+                Ok(stripped) => stripped.to_path_buf(),
+                Err(_) => path,
+            },
+            None => path,
+        }
+    }
+
+    /// Path of `path` on the filesystem, see `with_path_mapper()`.
+    pub(crate) fn physical_path(&self, path: &Path) -> PathBuf {
+        match &self.path_mapper {
+            Some(mapper) => mapper(path.to_path_buf()),
+            None => path.to_path_buf(),
+        }
+    }
+}
+
+/// Configure a `DirectoryLinesStreamer` before scanning its directory.
+///
+/// `DirectoryLinesStreamer::from_dir(dir)` is equivalent to
+/// `DirectoryLinesStreamerBuilder::new(dir).build()`. The default builder has no directory,
+/// see `set_dir()`.
+#[derive(Debug, Default)]
+pub struct DirectoryLinesStreamerBuilder {
+    dir: PathBuf,
+    options: Options,
+}
+
+impl DirectoryLinesStreamerBuilder {
+    pub fn new<P>(input_dir: P) -> DirectoryLinesStreamerBuilder
+    where
+        P: Into<PathBuf>,
+    {
+        DirectoryLinesStreamerBuilder {
+            dir: input_dir.into(),
+            options: Options::default(),
+        }
+    }
+
+    /// Stream `dir` instead of the directory given to `new()`.
+    pub fn set_dir<P>(&mut self, dir: P)
+    where
+        P: Into<PathBuf>,
+    {
+        self.dir = dir.into();
+    }
+
+    /// Whether a directory is set and exists, so that `build()` can scan it.
+    pub fn is_ready(&self) -> bool {
+        !self.dir.as_os_str().is_empty() && self.dir.exists()
+    }
+
+    /// Split records on `newline` instead of `b'\n'` (for example `b'\0'`).
+    ///
+    /// The delimiter is kept at the end of the returned lines.
+    pub fn with_custom_newline(mut self, newline: u8) -> DirectoryLinesStreamerBuilder {
+        self.options.newline = newline;
+        self
+    }
+
+    /// Yield `separator` (followed by the newline delimiter) between the lines of two files
+    /// (default: none).
+    ///
+    /// Files without any line are not separated. While the separator is the last line
+    /// yielded, `current_file()` returns `<separator>`. Separators are not counted in
+    /// `line_number()`, nor passed to the line callback, and only the iterator yields them:
+    /// per-file methods like `lines_per_file()` or `checksum_per_file()` do not see them.
+    pub fn with_interfile_separator(mut self, separator: String) -> DirectoryLinesStreamerBuilder {
+        self.options.interfile_separator = Some(separator);
+        self
+    }
+
+    /// Yield `line` (followed by the newline delimiter) as the only line of empty files
+    /// (default: none).
+    ///
+    /// The placeholder is counted in `line_number()`, and `current_file()` returns the empty
+    /// file while it is the last line read.
+    pub fn with_empty_file_placeholder(mut self, line: String) -> DirectoryLinesStreamerBuilder {
+        self.options.empty_file_placeholder = Some(line);
+        self
+    }
+
+    /// Choose how lines that are not valid UTF-8 are handled (default: `Utf8Mode::Lossy`).
+    pub fn with_utf8_mode(mut self, utf8_mode: Utf8Mode) -> DirectoryLinesStreamerBuilder {
+        self.options.utf8_mode = utf8_mode;
+        self
+    }
+
+    /// Choose what happens to errors encountered while iterating (default: `ErrorPolicy::Log`).
+    pub fn with_error_policy(mut self, error_policy: ErrorPolicy) -> DirectoryLinesStreamerBuilder {
+        self.options.error_policy = error_policy;
+        self
+    }
+
+    /// Call `on_error(&error)` for every error encountered while iterating, before the
+    /// `ErrorPolicy` handles it.
+    pub fn with_on_error<F>(mut self, on_error: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(&DirectoryLinesStreamerError) + Send + Sync + 'static,
+    {
+        self.options.on_error = Some(Arc::new(on_error));
+        self
+    }
+
+    /// Panic instead of logging errors with `ErrorPolicy::Log`, to notice them right away
+    /// (default: `false`).
+    ///
+    /// Only meant for tests. Deleted files are still only logged as warnings.
+    pub fn with_eager_error_reporting(
+        mut self,
+        eager_error_reporting: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.eager_error_reporting = eager_error_reporting;
+        self
+    }
+
+    /// Read all files in memory during `build()` so that `next()` never waits on I/O.
+    ///
+    /// Memory usage grows with the total size of the directory's content.
+    pub fn with_preload(mut self, preload: bool) -> DirectoryLinesStreamerBuilder {
+        self.options.preload = preload;
+        self
+    }
+
+    /// Read the next files in memory from `n` background threads while the current file is
+    /// streamed (default: `0`, no background reading).
+    ///
+    /// Files are still yielded in order. Up to `n` files wait in memory for the streamer to
+    /// reach them, more if one file takes longer to read than the next ones. An error reading
+    /// a file is reported as failing to open it. Ignored with `with_preload(true)`.
+    ///
+    /// Each file is a snapshot of its content when a thread read it: lines appended later are
+    /// not streamed. Files are thus not preread with `with_watch_interval()` or
+    /// `with_file_rename_detection()`. FIFOs and other files that are not regular files are
+    /// only opened once the streamer reaches them.
+    pub fn with_concurrent_file_preread(mut self, n: usize) -> DirectoryLinesStreamerBuilder {
+        self.options.concurrent_file_preread = n;
+        self
+    }
+
+    /// Open the next files, up to `with_max_open_files()` including the first one, in
+    /// `build()` rather than when reaching them (default: `false`).
+    ///
+    /// Files failing to open are opened again, and reported, when reached. Ignored with
+    /// `with_preload(true)`.
+    pub fn with_cached_file_handles(
+        mut self,
+        cached_file_handles: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.cached_file_handles = cached_file_handles;
+        self
+    }
+
+    /// Maximum number of files kept open by `with_cached_file_handles(true)` (default: `64`).
+    pub fn with_max_open_files(mut self, n: usize) -> DirectoryLinesStreamerBuilder {
+        self.options.max_open_files = n;
+        self
+    }
+
+    /// Open files with additional platform-specific flags, like `OpenFlags::NOATIME`.
+    ///
+    /// Ignored on platforms other than Linux.
+    pub fn with_open_flags(mut self, open_flags: OpenFlags) -> DirectoryLinesStreamerBuilder {
+        self.options.open_flags = open_flags;
+        self
+    }
+
+    /// Tell the kernel that files are read sequentially, so that it reads further ahead
+    /// (default: `false`).
+    ///
+    /// Calls `posix_fadvise(POSIX_FADV_SEQUENTIAL)` on each file once opened. Ignored with
+    /// `with_custom_open()`, and on platforms other than Linux.
+    pub fn with_fadvise_sequential(
+        mut self,
+        fadvise_sequential: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.fadvise_sequential = fadvise_sequential;
+        self
+    }
+
+    /// Tell the kernel that whole files will be needed, so that it starts reading them in the
+    /// page cache right away (default: `false`).
+    ///
+    /// Calls `posix_fadvise(POSIX_FADV_WILLNEED)` on each file once opened. Ignored with
+    /// `with_custom_open()`, and on platforms other than Linux.
+    pub fn with_fadvise_willneed(
+        mut self,
+        fadvise_willneed: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.fadvise_willneed = fadvise_willneed;
+        self
+    }
+
+    /// Open each file with the `OpenOptions` returned by `factory(path)` (default:
+    /// `OpenOptions::new().read(true)`).
+    ///
+    /// `with_open_flags()` is then ignored.
+    pub fn with_open_options<F>(mut self, factory: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(&Path) -> OpenOptions + Send + Sync + 'static,
+    {
+        self.options.open_options = Some(Arc::new(factory));
+        self
+    }
+
+    /// Read each file from `custom_open(path)` instead of opening it, for example to read
+    /// from a virtual filesystem.
+    ///
+    /// `with_open_flags()` and `with_open_options()` are then ignored. Decompression and
+    /// timeouts still apply.
+    pub fn with_custom_open<F>(mut self, custom_open: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(&Path) -> io::Result<Box<dyn Read + Send>> + Send + Sync + 'static,
+    {
+        self.options.custom_open = Some(Arc::new(custom_open));
+        self
+    }
+
+    /// Get the metadata of files from `metadata_source(path)` instead of the filesystem, for
+    /// example along with `with_custom_open()`.
+    ///
+    /// `None` means that the metadata is unknown.
+    pub fn with_metadata_source<F>(mut self, metadata_source: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(&Path) -> Option<VirtualMetadata> + Send + Sync + 'static,
+    {
+        self.options.metadata_source = Some(Arc::new(metadata_source));
+        self
+    }
+
+    /// Also read named pipes (FIFOs) found in the directory (default: `false`).
+    ///
+    /// Opening a FIFO blocks until a writer connects to it; its lines are read until the
+    /// writer closes it.
+    pub fn with_allow_fifos(mut self, allow_fifos: bool) -> DirectoryLinesStreamerBuilder {
+        self.options.allow_fifos = allow_fifos;
+        self
+    }
+
+    /// Also read the files of subdirectories (default: `false`).
+    ///
+    /// Files from all directories are ordered by file name only, so that `a/messages.1` and
+    /// `b/messages.1` are read before `a/messages.2`. Subdirectories are scanned in sorted order.
+    /// On Unix, a directory reached again through a symbolic link is skipped.
+    pub fn with_recursive(mut self, recursive: bool) -> DirectoryLinesStreamerBuilder {
+        self.options.recursive = recursive;
+        self
+    }
+
+    /// Keep files with equal sort keys in scan order (default: `false`).
+    ///
+    /// Only matters in recursive mode, where files with the same name in different
+    /// directories then stay in directory order. Stable sorting allocates a buffer
+    /// and is slightly slower than the default unstable sort.
+    pub fn with_stable_sort(mut self, stable_sort: bool) -> DirectoryLinesStreamerBuilder {
+        self.options.stable_sort = stable_sort;
+        self
+    }
+
+    /// Ignore case when sorting file names, so that `App.log` and `app.log` are read one after
+    /// the other (default: `false`).
+    ///
+    /// Only the sort key is lowercased, paths are left unchanged.
+    pub fn with_case_insensitive_sort(
+        mut self,
+        case_insensitive_sort: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.case_insensitive_sort = case_insensitive_sort;
+        self
+    }
+
+    /// Sort symbolic links by the file name of their target instead of their own name
+    /// (default: `false`).
+    ///
+    /// Links that cannot be resolved, and other files, are sorted by their own name.
+    pub fn with_symlink_target_sort(
+        mut self,
+        symlink_target_sort: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.symlink_target_sort = symlink_target_sort;
+        self
+    }
+
+    /// Only stream the files named after `template` for a date of `[start, end]`, like
+    /// `app-{YYYY}-{MM}-{DD}.log` (default: all files).
+    ///
+    /// `{YYYY}`, `{YY}`, `{MM}` and `{DD}` are replaced by the year, the last two digits of
+    /// the year, the month and the day, all zero-padded. Only file names are compared.
+    #[cfg(feature = "chrono")]
+    pub fn with_file_name_template_filter(
+        mut self,
+        template: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.file_name_template_filter = Some(FileNameTemplate::new(template, start, end));
+        self
+    }
+
+    /// Read files with a higher `priority(path)` first, files with the same priority in
+    /// natural order.
+    ///
+    /// `priority` is called once per file when sorting them.
+    pub fn with_file_priority<F>(mut self, priority: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(&Path) -> i64 + Send + Sync + 'static,
+    {
+        self.options.file_priority = Some(Arc::new(priority));
+        self
+    }
+
+    /// Stream only the files returned by `selector`, out of the sorted listed files.
+    ///
+    /// `selector` is called each time the directory is listed, also while watching it. The
+    /// returned files are streamed in that order.
+    pub fn with_file_selector<F>(mut self, selector: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(&[PathBuf]) -> Vec<PathBuf> + Send + Sync + 'static,
+    {
+        self.options.file_selector = Some(Arc::new(selector));
+        self
+    }
+
+    /// Skip regular files with the same content as a previous file, like links to the same
+    /// file (default: `false`).
+    ///
+    /// Files of the same size are compared by the SHA-256 digest of their whole content. The
+    /// digests are kept while watching the directory, until a file's size or modification
+    /// time changes.
+    pub fn with_deduplicate_files(
+        mut self,
+        deduplicate_files: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.deduplicate_files = deduplicate_files;
+        self
+    }
+
+    /// Skip files that are links to a previous file, on the same device and inode
+    /// (default: `false`).
+    ///
+    /// Cheaper than `with_deduplicate_files()`. Ignored on platforms other than Unix.
+    pub fn with_inode_deduplication(
+        mut self,
+        inode_deduplication: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.inode_deduplication = inode_deduplication;
+        self
+    }
+
+    /// Stream exactly the files of `paths`, in this order, instead of sorting the files
+    /// of the directory (default: none).
+    ///
+    /// Paths are either as listed from the directory or relative to it. `build()` fails with
+    /// `DirectoryLinesStreamerError::FileNotFound` on the first path that is not a file of the
+    /// directory.
+    pub fn with_virtual_file_order_override(
+        mut self,
+        paths: Vec<PathBuf>,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.file_order_override = Some(paths);
+        self
+    }
+
+    /// Stream at most the first `n` files, in natural order, of each directory (default: no
+    /// limit).
+    ///
+    /// Mostly useful in recursive mode, to keep one busy subdirectory from taking over.
+    pub fn with_max_files_per_directory(self, n: usize) -> DirectoryLinesStreamerBuilder {
+        self.with_select_files_per_directory(SelectMode::First(n))
+    }
+
+    /// Choose which files of each directory are streamed when there are too many (default:
+    /// all of them).
+    ///
+    /// The selected files are then sorted as usual.
+    pub fn with_select_files_per_directory(
+        mut self,
+        select_mode: SelectMode,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.select_files_per_directory = Some(select_mode);
+        self
+    }
+
+    /// Stream at most the first `n` files, once sorted (default: `0`, no limit).
+    pub fn with_file_limit(mut self, n: usize) -> DirectoryLinesStreamerBuilder {
+        self.options.file_limit = n;
+        self
+    }
+
+    /// Stop adding files, once sorted, when they add up to more than `max_bytes` (default:
+    /// none).
+    ///
+    /// The file going over the limit is streamed entirely; files of unknown size count as
+    /// empty. No file is added if `max_bytes` is `0`, so that `build()` fails with
+    /// `DirectoryLinesStreamerError::EmptyDirectory`.
+    pub fn with_max_total_bytes(mut self, max_bytes: u64) -> DirectoryLinesStreamerBuilder {
+        self.options.max_total_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Skip the first `skip` files, once sorted (default: `0`).
+    ///
+    /// Together with `with_file_limit()`, streams one page of files: `files[skip..skip + n]`.
+    pub fn with_file_offset(mut self, skip: usize) -> DirectoryLinesStreamerBuilder {
+        self.options.file_offset = skip;
+        self
+    }
+
+    /// Sort files by `normalizer(file_name)` instead of their file name, for example to
+    /// compare Unicode names in the same normalization form.
+    ///
+    /// Paths are left unchanged. Applied before `with_case_insensitive_sort()`.
+    pub fn with_file_name_normalizer<F>(mut self, normalizer: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(&OsStr) -> OsString + Send + Sync + 'static,
+    {
+        self.options.file_name_normalizer = Some(Arc::new(normalizer));
+        self
+    }
+
+    /// Detect compressed files from their first bytes, not only from their extension
+    /// (default: `false`).
+    ///
+    /// Only formats enabled through cargo features (`lz4`) are decompressed.
+    pub fn with_magic_byte_detection(
+        mut self,
+        magic_byte_detection: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.magic_byte_detection = magic_byte_detection;
+        self
+    }
+
+    /// Remove `prefix` from the paths returned by `current_file()`, for shorter logs.
+    ///
+    /// Paths that do not start with `prefix` are kept as is.
+    pub fn with_path_prefix_strip<P>(mut self, prefix: P) -> DirectoryLinesStreamerBuilder
+    where
+        P: Into<PathBuf>,
+    {
+        self.options.path_prefix_strip = Some(prefix.into());
+        self
+    }
+
+    /// Report the stream as a single file: `current_file()` always returns `name`.
+    ///
+    /// `line_number()` already counts lines across all files. Reading is not affected.
+    pub fn with_virtual_concat_filename(mut self, name: &str) -> DirectoryLinesStreamerBuilder {
+        self.options.virtual_concat_filename = Some(Arc::new(PathBuf::from(name)));
+        self
+    }
+
+    /// Yield each element of lines holding a JSON array as a separate line (default: `false`).
+    ///
+    /// Other JSON lines are yielded as is. Lines that are not valid JSON are also yielded as
+    /// is, after reporting a `DirectoryLinesStreamerError::InvalidJson`.
+    #[cfg(feature = "serde_json")]
+    pub fn with_flatten_json_arrays(
+        mut self,
+        flatten_json_arrays: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.flatten_json_arrays = flatten_json_arrays;
+        self
+    }
+
+    /// Normalize lines to the Unicode normalization `form` (default: none).
+    ///
+    /// Applied after the conversion to UTF-8, before any other transformation.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn with_utf8_normalization(
+        mut self,
+        form: NormalizationForm,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.utf8_normalization = Some(form);
+        self
+    }
+
+    /// Set the I/O scheduling priority of the thread calling `build()`, once the first file is
+    /// opened (default: unchanged).
+    ///
+    /// The priority stays set on that thread after the streamer is dropped. Reads made from
+    /// other threads, like with `with_concurrent_file_preread()`, are not affected. Ignored on
+    /// platforms other than Linux.
+    #[cfg(feature = "ioprio")]
+    pub fn with_io_priority(mut self, priority: IoPriority) -> DirectoryLinesStreamerBuilder {
+        self.options.io_priority = Some(priority);
+        self
+    }
+
+    /// Read all lines into a single buffer, allocating only the returned `String`s
+    /// (default: `false`).
+    ///
+    /// Lines are then always copied out of the buffer, even when `String::from_utf8()` could
+    /// have taken ownership of it. The buffer belongs to the streamer, which stays `Send`.
+    pub fn with_line_buffer_reuse(
+        mut self,
+        line_buffer_reuse: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.line_buffer_reuse = line_buffer_reuse;
+        self
+    }
+
+    /// End lines with a NUL byte instead of their newline delimiter, for C string consumers
+    /// (default: `false`).
+    ///
+    /// Applied last, after the other transformations. See `as_cstring()`.
+    pub fn with_nul_terminated_lines(
+        mut self,
+        nul_terminated_lines: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.nul_terminated_lines = nul_terminated_lines;
+        self
+    }
+
+    /// Discard the first `n` lines of each file, like headers (default: `0`).
+    ///
+    /// Discarded lines still count in `line_number()`.
+    pub fn with_skip_first_lines(mut self, n: usize) -> DirectoryLinesStreamerBuilder {
+        self.options.skip_first_lines = n;
+        self
+    }
+
+    /// Discard the last line of files not ending with the newline delimiter, which may still
+    /// be being written (default: `false`).
+    ///
+    /// Discarded lines do not count in `line_number()`.
+    pub fn with_drop_incomplete_last_line(
+        mut self,
+        drop_incomplete_last_line: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.drop_incomplete_last_line = drop_incomplete_last_line;
+        self
+    }
+
+    /// Count the lines ending with `\n`, `\r\n`, a bare `\r` or nothing, see
+    /// `DirectoryLinesStreamer::line_ending_stats()` (default: `false`).
+    ///
+    /// Lines are counted as read from the files: skipped header lines and dropped incomplete
+    /// lines are not.
+    pub fn with_line_ending_statistics(
+        mut self,
+        line_ending_statistics: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.line_ending_statistics = line_ending_statistics;
+        self
+    }
+
+    /// Only yield the lines starting with `prefix`, with `prefix` removed (default: none).
+    pub fn with_record_prefix(mut self, prefix: String) -> DirectoryLinesStreamerBuilder {
+        self.options.record_prefix = Some(prefix);
+        self.options.record_prefix_filter = true;
+        self
+    }
+
+    /// Remove `prefix` from the lines starting with it, yielding the other lines unchanged
+    /// (default: none).
+    pub fn with_record_prefix_strip_only(
+        mut self,
+        prefix: String,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.record_prefix = Some(prefix);
+        self.options.record_prefix_filter = false;
+        self
+    }
+
+    /// Yield only the last `n` lines of each file.
+    ///
+    /// Up to `n` lines are buffered and only yielded once their file is fully read, so
+    /// `current_file()` and `line_number()` can already refer to the next file.
+    pub fn with_take_last_lines(mut self, n: usize) -> DirectoryLinesStreamerBuilder {
+        self.options.take_last_lines = Some(n);
+        self
+    }
+
+    /// Yield the lines of each file from the last one to the first one, files staying in order
+    /// (default: `false`).
+    ///
+    /// Each file is read in memory before its lines are yielded, so like with
+    /// `with_take_last_lines()`, `current_file()` and `line_number()` can already refer to the
+    /// next file.
+    pub fn with_reverse_line_order(
+        mut self,
+        reverse_line_order: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.reverse_line_order = reverse_line_order;
+        self
+    }
+
+    /// Give up on a file when reading from it blocks for more than `timeout`, reporting a
+    /// `DirectoryLinesStreamerError::ReadTimeout`.
+    ///
+    /// Reads then happen in a thread per file. A thread blocked in a read that never returns
+    /// cannot be stopped and is left behind.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> DirectoryLinesStreamerBuilder {
+        self.options.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Give up on a file when producing a single line takes more than `timeout`, reporting a
+    /// `DirectoryLinesStreamerError::LineReadTimeout`.
+    ///
+    /// Unlike `with_read_timeout()`, this also catches lines trickling in through many small
+    /// reads. Lines are then read in a thread per file, left behind if blocked on timeout.
+    pub fn with_per_line_timeout(mut self, timeout: Duration) -> DirectoryLinesStreamerBuilder {
+        self.options.per_line_timeout = Some(timeout);
+        self
+    }
+
+    /// Skip files that take more than `timeout` to open, reporting a
+    /// `DirectoryLinesStreamerError::OpenTimeout`.
+    ///
+    /// Files are then opened from a new thread each. A thread whose open timed out is left
+    /// behind until the open returns, so there is at most one such thread per file skipped.
+    /// `build()` only fails if every file times out.
+    pub fn with_file_open_timeout(mut self, timeout: Duration) -> DirectoryLinesStreamerBuilder {
+        self.options.open_timeout = Some(timeout);
+        self
+    }
+
+    /// Retry up to `retries` times to open a file that fails to open, waiting as `backoff`
+    /// says between attempts (default: no retries).
+    ///
+    /// Missing files are not retried, nor the first file when building.
+    pub fn with_open_retries(
+        mut self,
+        retries: u32,
+        backoff: BackoffStrategy,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.open_retries = retries;
+        self.options.open_backoff = backoff;
+        self
+    }
+
+    /// Instead of ending, poll the directory every `interval` for new files and stream them.
+    ///
+    /// Files are identified by path: a rewritten file is not read again. Stop watching with
+    /// `DirectoryLinesStreamer::watch_stopper()`. Ignored with `with_preload(true)`.
+    pub fn with_watch_interval(mut self, interval: Duration) -> DirectoryLinesStreamerBuilder {
+        self.options.watch_interval = Some(interval);
+        self
+    }
+
+    /// Detect the current file being renamed, like by a log rotation, and read the new file
+    /// created at its path from its start (default: `false`).
+    ///
+    /// The path is checked every `with_rename_check_interval()` lines, and at the end of the
+    /// file. The lines of the renamed file not read yet are skipped. Ignored on platforms other
+    /// than Unix.
+    pub fn with_file_rename_detection(
+        mut self,
+        file_rename_detection: bool,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.file_rename_detection = file_rename_detection;
+        self
+    }
+
+    /// Number of lines between checks of `with_file_rename_detection()` (default: `100`).
+    pub fn with_rename_check_interval(mut self, n: usize) -> DirectoryLinesStreamerBuilder {
+        self.options.rename_check_interval = n;
+        self
+    }
+
+    /// Resume streaming right after the line read at `position`.
+    ///
+    /// `build()` fails with `DirectoryLinesStreamerError::InvalidCheckpoint` if the file at
+    /// `position.file_index` is not `position.file` anymore (for example after a log rotation).
+    pub fn with_start_position(
+        mut self,
+        position: StreamPosition,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.start_position = Some(position);
+        self
+    }
+
+    /// Number lines from `n + 1` instead of 1, for example when the first `n` lines were
+    /// already processed (default: `0`).
+    ///
+    /// Ignored with `with_start_position()`, which continues from the position's line number.
+    pub fn with_line_number_offset(mut self, n: usize) -> DirectoryLinesStreamerBuilder {
+        self.options.line_number_offset = n;
+        self
+    }
+
+    /// Choose what `line_number()` returns (default: `LineNumberingMode::LineCount`).
+    pub fn with_line_numbering_mode(
+        mut self,
+        mode: LineNumberingMode,
+    ) -> DirectoryLinesStreamerBuilder {
+        self.options.line_numbering_mode = mode;
+        self
+    }
+
+    /// Split files in chunks of at least `bytes` bytes, ending at a line boundary
+    /// (default: none).
+    ///
+    /// At the start of each chunk but the first, the file transition callback is called with
+    /// the current file as both the closing and the opening file, and
+    /// `DirectoryLinesStreamer::lines_per_file()` and `fold_files()` report each chunk
+    /// separately. The file is read in one go, chunks are not reopened.
+    pub fn with_file_size_limit(mut self, bytes: u64) -> DirectoryLinesStreamerBuilder {
+        self.options.file_size_limit = Some(bytes);
+        self
+    }
+
+    /// Call `callback(closing_file, opening_file)` every time the streamer moves to a new file.
+    ///
+    /// `closing_file` is `None` when the first file is opened. The callback runs before the
+    /// new file is opened.
+    pub fn with_file_transition_callback<F>(mut self, callback: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(Option<&Path>, &Path) + Send + Sync + 'static,
+    {
+        self.options.file_transition_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Call `callback(current_file, line_number, line)` for every yielded line.
+    ///
+    /// The callback runs synchronously in `next()`, so it slows down every line: keep it short.
+    pub fn with_line_callback<F>(mut self, callback: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(&Path, usize, &str) + Send + Sync + 'static,
+    {
+        self.options.line_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Write a JSON line to `writer` every time a file is opened, and every time one is read
+    /// until its end.
+    ///
+    /// Opening records the path and the UTC time:
+    /// `{"event":"open","stream_id":0,"path":"...","timestamp":"2024-01-31T12:34:56.789Z"}`.
+    /// Reaching the end records the lines and the (decompressed) bytes read:
+    /// `{"event":"close","stream_id":0,"path":"...","lines_read":3,"bytes_read":42}`. Errors
+    /// writing to `writer` are logged.
+    pub fn with_audit_log<W>(mut self, writer: W) -> DirectoryLinesStreamerBuilder
+    where
+        W: Write + Send + 'static,
+    {
+        self.options.audit_log = Some(Arc::new(Mutex::new(Box::new(writer))));
+        self
+    }
+
+    /// Tag the streamer with `id`, to tell apart the lines of several streamers once merged
+    /// (default: `0`).
+    ///
+    /// Reported by `stream_id()`, in `LineMetadata` and in the audit log.
+    pub fn with_stream_id(mut self, id: u64) -> DirectoryLinesStreamerBuilder {
+        self.options.stream_id = id;
+        self
+    }
+
+    /// Open files at `mapper(path)` instead of `path`, for example to add a mount point.
+    ///
+    /// The mapped path is also the one inspected, for example for its size, inode or content
+    /// by `with_inode_deduplication()` and `with_deduplicate_files()`. Directories are still
+    /// listed at their original path. `current_file()` and errors keep reporting the
+    /// original path.
+    pub fn with_path_mapper<F>(mut self, mapper: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn(PathBuf) -> PathBuf + Send + Sync + 'static,
+    {
+        self.options.path_mapper = Some(Arc::new(mapper));
+        self
+    }
+
+    /// List the files to stream with `scanner` instead of reading the directory.
+    ///
+    /// The returned paths are streamed in that order, without sorting. The directory is only
+    /// used in errors and positions.
+    pub fn with_scanner<F>(mut self, scanner: F) -> DirectoryLinesStreamerBuilder
+    where
+        F: Fn() -> io::Result<Vec<PathBuf>> + Send + Sync + 'static,
+    {
+        self.options.scanner = Some(Arc::new(scanner));
+        self
+    }
+
+    pub fn build(self) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError> {
+        if self.options.scanner.is_some() || self.dir.exists() {
+            let mut files =
+                list_files(&self.dir, &self.options).map_err(DirectoryLinesStreamerError::Io)?;
+            if let Some(order) = &self.options.file_order_override {
+                files = override_file_order(&self.dir, files, order)?;
+            }
+            self.build_from_sorted_files(files)
+        } else {
+            Err(DirectoryLinesStreamerError::DirectoryDoesNotExists(
+                self.dir,
+            ))
+        }
+    }
+
+    /// Build a streamer over `files` instead of the content of the directory.
+    pub(crate) fn build_from_files(
+        self,
+        mut files: Vec<PathBuf>,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError> {
+        sort_files(&mut files, &self.options);
+        self.build_from_sorted_files(files)
+    }
+
+    /// Builder of a streamer over some of the files of another one, see
+    /// `DirectoryLinesStreamer::window_files()`.
+    pub(crate) fn from_options(dir: PathBuf, options: Options) -> DirectoryLinesStreamerBuilder {
+        DirectoryLinesStreamerBuilder { dir, options }
+    }
+
+    pub(crate) fn build_from_sorted_files(
+        self,
+        mut files: Vec<PathBuf>,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError> {
+        let DirectoryLinesStreamerBuilder { dir, options } = self;
+        // Files outside of the page are seen too: they must not be picked up while watching.
+        let seen_files: HashSet<PathBuf> = if options.watch_interval.is_some() {
+            files.iter().cloned().collect()
+        } else {
+            HashSet::new()
+        };
+        let nb_files = files.len();
+        files.drain(..options.file_offset.min(nb_files));
+        if options.file_limit != 0 && files.len() > options.file_limit {
+            log::warn!(
+                "Omitting {} files after the first {}",
+                files.len() - options.file_limit,
+                options.file_limit
+            );
+            files.truncate(options.file_limit);
+        }
+        if let Some(max_bytes) = options.max_total_bytes {
+            let mut total_bytes: u64 = 0;
+            let nb_files = files
+                .iter()
+                .take_while(|path| {
+                    let included = max_bytes > 0 && total_bytes <= max_bytes;
+                    let size = file_metadata(path, &options).map_or(0, |metadata| metadata.size);
+                    total_bytes += size;
+                    included
+                })
+                .count();
+            if nb_files < files.len() {
+                log::warn!(
+                    "Omitting {} files after the first {} bytes",
+                    files.len() - nb_files,
+                    max_bytes
+                );
+                files.truncate(nb_files);
+            }
+        }
+        let all_files = files.clone();
+        // We'll `pop()` the last file until we are done, so we want to invert the vec.
+        let mut files: Vec<PathBuf> = files.into_iter().rev().collect();
+        log::debug!("files: {:?}", files);
+
+        // Open the first file
+        if files.is_empty() {
+            Err(DirectoryLinesStreamerError::EmptyDirectory(dir))
+        } else {
+            if let Some(position) = &options.start_position {
+                skip_to_position(&mut files, position)?;
+            }
+            // Files that timed out are skipped, reported once the streamer is built
+            let mut timed_out = Vec::new();
+            let (opened_file_path, mut opened_file) = loop {
+                // Safe since we verified to contain at least one file, and only pop another
+                // one if there is any left
+                let opened_file_path = files.pop().unwrap();
+                if let Some(callback) = &options.file_transition_callback {
+                    callback(None, &opened_file_path);
+                }
+                log::debug!("Opening first file: {:?}", opened_file_path);
+                match open_reader(&opened_file_path, &options) {
+                    Ok(opened_file) => break (opened_file_path, opened_file),
+                    Err(error) if is_open_timeout(&error, &options) => {
+                        let error = DirectoryLinesStreamerError::OpenTimeout(opened_file_path);
+                        if files.is_empty() {
+                            return Err(error);
+                        }
+                        timed_out.push(error);
+                    }
+                    Err(error) => return Err(DirectoryLinesStreamerError::Io(error)),
+                }
+            };
+            #[cfg(feature = "ioprio")]
+            if let Some(priority) = options.io_priority {
+                set_io_priority(priority).map_err(DirectoryLinesStreamerError::Io)?;
+            }
+            let (line_id, file_position) = match &options.start_position {
+                // The file resumed from timed out: start at the next one opened
+                Some(position) if !timed_out.is_empty() => (
+                    position.line_number + 1,
+                    FilePosition::start_of(position.file_index + timed_out.len()),
+                ),
+                Some(position) => {
+                    // Offsets count decompressed bytes: only plain files are seeked.
+                    opened_file
+                        .skip_to(position.byte_offset)
+                        .map_err(DirectoryLinesStreamerError::Io)?;
+                    (
+                        position.line_number + 1,
+                        FilePosition {
+                            index: position.file_index,
+                            byte_offset: position.byte_offset,
+                            // Resuming inside a file: its header lines are behind.
+                            lines: if position.byte_offset > 0 {
+                                options.skip_first_lines
+                            } else {
+                                0
+                            },
+                            ..FilePosition::default()
+                        },
+                    )
+                }
+                None => (
+                    options.line_number_offset + 1,
+                    FilePosition::start_of(timed_out.len()),
+                ),
+            };
+
+            let opened_file_path = options.strip_path_prefix(opened_file_path);
+            let mut streamer = DirectoryLinesStreamer::from_parts(
+                dir,
+                files,
+                opened_file_path,
+                opened_file,
+                options,
+            );
+            streamer.line_id = line_id;
+            streamer.file_position = file_position;
+            streamer.seen_files = seen_files;
+            streamer.all_files = all_files;
+            streamer.total_bytes = streamer.compute_total_bytes().unwrap_or(0);
+            if streamer.options.concurrent_file_preread > 0
+                && !streamer.options.preload
+                && !follows_files(&streamer.options)
+            {
+                streamer.prereader = Some(Prereader::spawn(
+                    streamer.files.iter().rev().cloned(),
+                    streamer.options.concurrent_file_preread,
+                    &streamer.options,
+                ));
+            }
+            for error in timed_out {
+                streamer.emit_error(error);
+            }
+            streamer.audit_open();
+            streamer.record_opened_file_identity();
+            if streamer.options.cached_file_handles && !streamer.options.preload {
+                streamer.open_cached_files();
+            }
+            if streamer.options.preload {
+                streamer.preload();
+            }
+            Ok(streamer)
+        }
+    }
+}
+
+/// Whether the files may still change while streamed: a snapshot of their content does not
+/// do, see `with_concurrent_file_preread()`.
+fn follows_files(options: &Options) -> bool {
+    options.watch_interval.is_some() || options.file_rename_detection
+}
+
+/// List the files to stream, in order.
+pub(crate) fn list_files(dir: &Path, options: &Options) -> Result<Vec<PathBuf>, io::Error> {
+    let files = match &options.scanner {
+        Some(scanner) => scanner()?,
+        None => {
+            let mut files: Vec<PathBuf> = Vec::new();
+            scan_dir(dir, options, &mut files, &mut HashSet::new())?;
+            #[cfg(feature = "chrono")]
+            if let Some(template) = &options.file_name_template_filter {
+                files.retain(|path| path.file_name().is_some_and(|name| template.matches(name)));
+            }
+            if let Some(select_mode) = options.select_files_per_directory {
+                select_files_per_directory(&mut files, select_mode, options);
+            }
+            sort_files(&mut files, options);
+            #[cfg(unix)]
+            if options.inode_deduplication {
+                deduplicate_inodes(&mut files, options);
+            }
+            if options.deduplicate_files {
+                deduplicate_files(&mut files, options);
+            }
+            files
+        }
+    };
+    Ok(match &options.file_selector {
+        Some(selector) => selector(&files),
+        None => files,
+    })
+}
+
+/// The files of `order`, checking that they are among the `files` listed from `dir`.
+fn override_file_order(
+    dir: &Path,
+    files: Vec<PathBuf>,
+    order: &[PathBuf],
+) -> Result<Vec<PathBuf>, DirectoryLinesStreamerError> {
+    let listed: HashSet<PathBuf> = files.into_iter().collect();
+    order
+        .iter()
+        .map(|path| {
+            if listed.contains(path) {
+                Ok(path.clone())
+            } else if listed.contains(&dir.join(path)) {
+                Ok(dir.join(path))
+            } else {
+                Err(DirectoryLinesStreamerError::FileNotFound(path.clone()))
+            }
+        })
+        .collect()
+}
+
+/// Remove the files that are links to a previous file.
+#[cfg(unix)]
+fn deduplicate_inodes(files: &mut Vec<PathBuf>, options: &Options) {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut inodes: HashSet<(u64, u64)> = HashSet::new();
+    files.retain(|path| match fs::metadata(options.physical_path(path)) {
+        Ok(metadata) => {
+            let is_new = inodes.insert((metadata.dev(), metadata.ino()));
+            if !is_new {
+                log::debug!("Skipping link to a previous file: {:?}", path);
+            }
+            is_new
+        }
+        // Errors are reported when opening the file to read it.
+        Err(_) => true,
+    });
+}
+
+/// Remove the regular files whose content is the same as that of a previous file.
+fn deduplicate_files(files: &mut Vec<PathBuf>, options: &Options) {
+    // Size and modification time of the regular files, others are never duplicates
+    let metadata: Vec<Option<(u64, SystemTime)>> = files
+        .iter()
+        .map(|path| {
+            let metadata = fs::metadata(options.physical_path(path)).ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            Some((metadata.len(), metadata.modified().ok()?))
+        })
+        .collect();
+    // Only files of the same size need to be read
+    let mut sizes: HashMap<u64, usize> = HashMap::new();
+    for (size, _) in metadata.iter().flatten() {
+        *sizes.entry(*size).or_default() += 1;
+    }
+    let mut digests: HashSet<(u64, [u8; 32])> = HashSet::new();
+    let mut metadata = metadata.into_iter();
+    files.retain(|path| {
+        let (size, modified) = match metadata.next().flatten() {
+            Some(metadata) if sizes[&metadata.0] > 1 => metadata,
+            _ => return true,
+        };
+        match file_digest(path, size, modified, options) {
+            Ok(digest) => {
+                let is_new = digests.insert((size, digest));
+                if !is_new {
+                    log::debug!("Skipping duplicate file: {:?}", path);
+                }
+                is_new
+            }
+            // Errors are reported when opening the file to read it.
+            Err(_) => true,
+        }
+    });
+}
+
+/// SHA-256 digest of the whole content of `path`, from the cache if the file did not change.
+fn file_digest(
+    path: &Path,
+    size: u64,
+    modified: SystemTime,
+    options: &Options,
+) -> Result<[u8; 32], io::Error> {
+    let physical_path = options.physical_path(path);
+    if let Some((cached_size, cached_modified, digest)) =
+        options.digest_cache.lock().unwrap().get(&physical_path)
+    {
+        if (*cached_size, *cached_modified) == (size, modified) {
+            return Ok(*digest);
+        }
+    }
+    let mut hasher = Sha256::new();
+    io::copy(&mut open_source(&physical_path, options)?, &mut hasher)?;
+    let digest: [u8; 32] = hasher.finalize().into();
+    options
+        .digest_cache
+        .lock()
+        .unwrap()
+        .insert(physical_path, (size, modified, digest));
+    Ok(digest)
+}
+
+/// Push the files of `dir` to `files`, and those of its subdirectories in recursive mode.
+///
+/// Directories already in `visited` are skipped, so that symbolic link loops end.
+fn scan_dir(
+    dir: &Path,
+    options: &Options,
+    files: &mut Vec<PathBuf>,
+    visited: &mut HashSet<FileIdentity>,
+) -> Result<(), io::Error> {
+    if let Some(identity) = file_identity(dir) {
+        if !visited.insert(identity) {
+            log::warn!("Skipping {:?}: directory already scanned", dir);
+            return Ok(());
+        }
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        // Keep only valid entries
+        .filter_map(Result::ok)
+        // Convert to a PathBuf
+        .map(|dir_entry| dir_entry.path())
+        .collect();
+    if options.recursive {
+        // Scan subdirectories in a deterministic order
+        paths.sort_unstable_by(|a, b| compare_file_names(a, b, options));
+    }
+    for path in paths {
+        if options.recursive && path.is_dir() {
+            scan_dir(&path, options, files, visited)?;
+        } else if is_readable_file(&path, options) {
+            // Skip directories, devices, etc.
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Drop the files before `position` from `files` (sorted in reverse order).
+fn skip_to_position(
+    files: &mut Vec<PathBuf>,
+    position: &StreamPosition,
+) -> Result<(), DirectoryLinesStreamerError> {
+    if position.file_index >= files.len() {
+        return Err(DirectoryLinesStreamerError::InvalidCheckpoint(format!(
+            "no file at index {} (only {} files)",
+            position.file_index,
+            files.len()
+        )));
+    }
+    files.truncate(files.len() - position.file_index);
+    // Safe since we verified `files` contains more than `position.file_index` files
+    let file = files.last().unwrap();
+    if file.file_name() != position.file.file_name() {
+        return Err(DirectoryLinesStreamerError::InvalidCheckpoint(format!(
+            "expected {:?} at index {}, found {:?}",
+            position.file, position.file_index, file
+        )));
+    }
+    Ok(())
+}
+
+/// Whether the directory entry at `path` is something the streamer should read.
+///
+/// Symbolic links are followed. Entries that cannot be inspected are kept so that opening
+/// them reports the error.
+fn is_readable_file(path: &Path, options: &Options) -> bool {
+    let file_type = match fs::metadata(options.physical_path(path)) {
+        Ok(metadata) => metadata.file_type(),
+        Err(_) => return true,
+    };
+    if file_type.is_file() {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_fifo() {
+            return options.allow_fifos;
+        }
+    }
+    log::debug!("Skipping {:?}: not a regular file", path);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{fs::File, thread, time::SystemTime};
+
+    #[test]
+    fn default_builder() {
+        let mut builder = DirectoryLinesStreamerBuilder::default();
+        assert!(!builder.is_ready());
+        builder.set_dir("fixtures/non-empty-dir");
+        assert!(builder.is_ready());
+        assert_eq!(builder.build().unwrap().count(), 15);
+
+        match DirectoryLinesStreamerBuilder::default()
+            .build()
+            .unwrap_err()
+        {
+            DirectoryLinesStreamerError::DirectoryDoesNotExists(dir) => {
+                assert_eq!(dir, PathBuf::new())
+            }
+            error => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn line_number_offset() {
+        for preload in [false, true] {
+            let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+                .with_line_number_offset(5)
+                .with_preload(preload)
+                .build()
+                .unwrap();
+            assert_eq!(streamer.line_number(), 5);
+            assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+            assert_eq!(streamer.line_number(), 6);
+        }
+    }
+
+    #[test]
+    fn deduplicate_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "same line\n").unwrap();
+        fs::write(dir.path().join("messages.1"), "same line\n").unwrap();
+        fs::write(dir.path().join("messages.2"), "other line\n").unwrap();
+
+        let lines: Vec<String> = DirectoryLinesStreamer::builder(dir.path())
+            .with_deduplicate_files(true)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines, &["same line\n", "other line\n"]);
+
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        assert_eq!(streamer.count(), 3);
+
+        // Files are compared entirely, not only their first bytes
+        let head = "a".repeat(8191);
+        fs::write(dir.path().join("messages.3"), format!("{}\n1\n", head)).unwrap();
+        fs::write(dir.path().join("messages.4"), format!("{}\n2\n", head)).unwrap();
+        let streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_deduplicate_files(true)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.count(), 6);
+    }
+
+    #[test]
+    fn deduplicate_files_digest_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "same line\n").unwrap();
+        fs::write(dir.path().join("messages.1"), "same line\n").unwrap();
+        fs::write(dir.path().join("messages.2"), "other size\n").unwrap();
+
+        let opened = Arc::new(Mutex::new(Vec::new()));
+        let mut options = Options {
+            deduplicate_files: true,
+            ..Options::default()
+        };
+        let opened_by_open = opened.clone();
+        options.custom_open = Some(Arc::new(move |path: &Path| {
+            opened_by_open.lock().unwrap().push(path.to_path_buf());
+            Ok(Box::new(File::open(path)?) as Box<dyn Read + Send>)
+        }));
+
+        // Only the files of the same size are read, once
+        for _ in 0..2 {
+            let files = list_files(dir.path(), &options).unwrap();
+            assert_eq!(
+                files,
+                &[dir.path().join("messages"), dir.path().join("messages.2")]
+            );
+        }
+        assert_eq!(
+            *opened.lock().unwrap(),
+            &[dir.path().join("messages"), dir.path().join("messages.1")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inode_deduplication() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::copy(
+            "fixtures/non-empty-dir/messages",
+            dir.path().join("messages"),
+        )
+        .unwrap();
+        fs::hard_link(dir.path().join("messages"), dir.path().join("messages.1")).unwrap();
+        fs::copy(
+            "fixtures/non-empty-dir/messages.1",
+            dir.path().join("messages.2"),
+        )
+        .unwrap();
+
+        let lines: Vec<String> = DirectoryLinesStreamer::builder(dir.path())
+            .with_inode_deduplication(true)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[3], "line one from messages.1\n");
+
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        assert_eq!(streamer.count(), 9);
+    }
+
+    #[test]
+    fn file_limit() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_limit(2)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.count(), 2 * 3);
+
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_limit(0)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.count(), 5 * 3);
+
+        let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_offset(3)
+            .with_file_limit(1)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "line one from messages.10\n");
+
+        let error = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_offset(5)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            DirectoryLinesStreamerError::EmptyDirectory(_)
+        ));
+    }
+
+    #[test]
+    fn skip_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "line one\n").unwrap();
+        fs::create_dir(dir.path().join("messages.1")).unwrap();
+
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        let (lines, errors) = streamer.collect_errors();
+        assert_eq!(lines, &["line one\n"]);
+        assert!(errors.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    fn mkfifo(path: &Path) {
+        use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn fifos() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "line one from messages\n").unwrap();
+        let fifo_path = dir.path().join("messages.1");
+        mkfifo(&fifo_path);
+
+        // FIFOs are skipped by default: this would block otherwise.
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        assert_eq!(streamer.count(), 1);
+
+        let writer = thread::spawn(move || {
+            // Blocks until the streamer opens the FIFO for reading
+            fs::write(&fifo_path, "line one from fifo\nline two from fifo\n").unwrap();
+        });
+        // Not even read to be deduplicated
+        let streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_allow_fifos(true)
+            .with_deduplicate_files(true)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        writer.join().unwrap();
+
+        assert_eq!(
+            lines,
+            &[
+                "line one from messages\n",
+                "line one from fifo\n",
+                "line two from fifo\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn recursive_stable_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub_dir in &["a", "b", "c"] {
+            fs::create_dir(dir.path().join(sub_dir)).unwrap();
+            for file in &["messages", "messages.1"] {
+                fs::write(
+                    dir.path().join(sub_dir).join(file),
+                    format!("{}/{}\n", sub_dir, file),
+                )
+                .unwrap();
+            }
+        }
+
+        // Without recursion, the directory only contains subdirectories.
+        match DirectoryLinesStreamer::from_dir(dir.path()).unwrap_err() {
+            DirectoryLinesStreamerError::EmptyDirectory(_) => {}
+            _ => panic!("Unexpected error"),
+        }
+
+        let streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_recursive(true)
+            .with_stable_sort(true)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(
+            lines,
+            &[
+                "a/messages\n",
+                "b/messages\n",
+                "c/messages\n",
+                "a/messages.1\n",
+                "b/messages.1\n",
+                "c/messages.1\n"
+            ]
+        );
+
+        // The unstable sort still groups files by name, in any directory order.
+        let streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_recursive(true)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[..3].iter().all(|line| line.ends_with("/messages\n")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn recursive_symlink_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        fs::write(dir.path().join("a/messages"), "a/messages\n").unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("a/loop")).unwrap();
+
+        let lines: Vec<String> = DirectoryLinesStreamer::builder(dir.path())
+            .with_recursive(true)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines, &["a/messages\n"]);
+    }
+
+    #[test]
+    fn virtual_file_order_override() {
+        let dir = Path::new("fixtures/non-empty-dir");
+        let lines: Vec<String> = DirectoryLinesStreamer::builder(dir)
+            .with_virtual_file_order_override(vec![
+                PathBuf::from("messages.20"),
+                dir.join("messages"),
+                PathBuf::from("messages.2"),
+            ])
+            .build()
+            .unwrap()
+            .step_by(3)
+            .collect();
+        assert_eq!(
+            lines,
+            &[
+                "line one from messages.20\n",
+                "line one from messages\n",
+                "line one from messages.2\n"
+            ]
+        );
+
+        let error = DirectoryLinesStreamer::builder(dir)
+            .with_virtual_file_order_override(vec![
+                PathBuf::from("messages"),
+                PathBuf::from("messages.3"),
+            ])
+            .build()
+            .unwrap_err();
+        match error {
+            DirectoryLinesStreamerError::FileNotFound(path) => {
+                assert_eq!(path, PathBuf::from("messages.3"))
+            }
+            error => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn max_files_per_directory() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/unbalanced-dir")
+            .with_recursive(true)
+            .with_stable_sort(true)
+            .with_max_files_per_directory(3)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(
+            lines,
+            &[
+                "line one from big/messages\n",
+                "line one from small/messages\n",
+                "line one from big/messages.1\n",
+                "line one from small/messages.1\n",
+                "line one from big/messages.2\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn select_files_per_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        // messages.1 is the newest, messages.4 the oldest
+        for i in 1..=4 {
+            let path = dir.path().join(format!("messages.{}", i));
+            fs::write(&path, format!("messages.{}\n", i)).unwrap();
+            let modified = now - Duration::from_secs(60 * i);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        let select = |select_mode| -> Vec<String> {
+            DirectoryLinesStreamer::builder(dir.path())
+                .with_select_files_per_directory(select_mode)
+                .build()
+                .unwrap()
+                .collect()
+        };
+        assert_eq!(
+            select(SelectMode::Newest(2)),
+            &["messages.1\n", "messages.2\n"]
+        );
+        assert_eq!(
+            select(SelectMode::Oldest(2)),
+            &["messages.3\n", "messages.4\n"]
+        );
+        let random = select(SelectMode::Random(2, 42));
+        assert_eq!(random.len(), 2);
+        assert_eq!(select(SelectMode::Random(2, 42)), random);
+        assert_eq!(select(SelectMode::Random(10, 42)).len(), 4);
+    }
+
+    #[test]
+    fn case_insensitive_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in &["App.log", "Zed.log", "app.log", "b.log"] {
+            fs::write(dir.path().join(file), format!("{}\n", file)).unwrap();
+        }
+
+        let streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_case_insensitive_sort(true)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.map(|line| line.to_lowercase()).collect();
+        assert_eq!(lines, &["app.log\n", "app.log\n", "b.log\n", "zed.log\n"]);
+    }
+
+    #[test]
+    fn interfile_separator() {
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_interfile_separator("---".to_string())
+            .build()
+            .unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = streamer.next() {
+            if line == "---\n" {
+                assert_eq!(streamer.current_file(), Path::new(crate::SEPARATOR_FILE));
+            } else {
+                assert_ne!(streamer.current_file(), Path::new(crate::SEPARATOR_FILE));
+            }
+            lines.push(line);
+        }
+        assert_eq!(streamer.line_number(), 15);
+        assert_eq!(lines.len(), 15 + 4);
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(line == "---\n", i % 4 == 3, "line {}: {:?}", i, line);
+        }
+
+        // Files without lines are not separated
+        let build = || {
+            DirectoryLinesStreamer::builder("fixtures/with-empty-dir")
+                .with_interfile_separator("---".to_string())
+                .build()
+                .unwrap()
+        };
+        let lines: Vec<String> = build().collect();
+        assert_eq!(lines, &["one\n", "---\n", "three\n"]);
+
+        // Nor are the lines of each file
+        let expected = DirectoryLinesStreamer::from_dir("fixtures/with-empty-dir")
+            .unwrap()
+            .lines_per_file();
+        assert_eq!(build().lines_per_file(), expected);
+        let checksums = build().checksum_per_file();
+        let expected = DirectoryLinesStreamer::from_dir("fixtures/with-empty-dir")
+            .unwrap()
+            .checksum_per_file();
+        assert_eq!(checksums, expected);
+    }
+
+    #[test]
+    fn empty_file_placeholder() {
+        let lines: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/with-empty-dir")
+            .unwrap()
+            .collect();
+        assert_eq!(lines, &["one\n", "three\n"]);
+
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/with-empty-dir")
+            .with_empty_file_placeholder("<empty>".to_string())
+            .build()
+            .unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = streamer.next() {
+            let file_name = streamer.current_file().file_name().unwrap();
+            lines.push((line, file_name.to_string_lossy().to_string()));
+        }
+        let expected = &[
+            ("<empty>\n", "messages"),
+            ("one\n", "messages.1"),
+            ("<empty>\n", "messages.2"),
+            ("three\n", "messages.3"),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .iter()
+            .map(|(line, file)| (line.to_string(), file.to_string()))
+            .collect();
+        assert_eq!(lines, expected);
+        assert_eq!(streamer.line_number(), 4);
+    }
+
+    #[test]
+    fn file_priority() {
+        let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_priority(|path| match path.file_name().unwrap().to_str() {
+                Some("messages.10") => 2,
+                Some("messages.2") => 1,
+                _ => 0,
+            })
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(lines[0], "line one from messages.10\n");
+        assert_eq!(lines[3], "line one from messages.2\n");
+        assert_eq!(lines[6], "line one from messages\n");
+        assert_eq!(lines[9], "line one from messages.1\n");
+        assert_eq!(lines[12], "line one from messages.20\n");
+    }
+
+    #[test]
+    fn file_selector() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_selector(|files| {
+                files
+                    .iter()
+                    .filter(|path| path.to_string_lossy().ends_with('0'))
+                    .rev()
+                    .cloned()
+                    .collect()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            streamer.files_snapshot(),
+            &[
+                PathBuf::from("fixtures/non-empty-dir/messages.20"),
+                PathBuf::from("fixtures/non-empty-dir/messages.10"),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_target_sort() {
+        let targets = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        for (link, target) in &[
+            ("2024-01-01", "app.log.3"),
+            ("2024-01-02", "app.log.2"),
+            ("2024-01-03", "app.log.1"),
+        ] {
+            let target = targets.path().join(target);
+            fs::write(&target, format!("{}\n", link)).unwrap();
+            std::os::unix::fs::symlink(&target, dir.path().join(link)).unwrap();
+        }
+        fs::write(dir.path().join("app.log.0"), "app.log.0\n").unwrap();
+
+        let lines: Vec<String> = DirectoryLinesStreamer::from_dir(dir.path())
+            .unwrap()
+            .collect();
+        assert_eq!(
+            lines,
+            &[
+                "2024-01-01\n",
+                "2024-01-02\n",
+                "2024-01-03\n",
+                "app.log.0\n"
+            ]
+        );
+
+        let lines: Vec<String> = DirectoryLinesStreamer::builder(dir.path())
+            .with_symlink_target_sort(true)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(
+            lines,
+            &[
+                "app.log.0\n",
+                "2024-01-03\n",
+                "2024-01-02\n",
+                "2024-01-01\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn path_mapper() {
+        let mount_point = tempfile::tempdir().unwrap();
+        let mapped_dir = mount_point.path().join("fixtures/non-empty-dir");
+        fs::create_dir_all(&mapped_dir).unwrap();
+        for entry in fs::read_dir("fixtures/non-empty-dir").unwrap() {
+            let file_name = entry.unwrap().file_name();
+            let content = format!("mapped {}\n", file_name.to_str().unwrap());
+            fs::write(mapped_dir.join(file_name), content).unwrap();
+        }
+
+        let prefix = mount_point.path().to_path_buf();
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_path_mapper(move |path| prefix.join(path))
+            .build()
+            .unwrap();
+        assert_eq!(
+            Iterator::by_ref(&mut streamer).collect::<Vec<String>>(),
+            &[
+                "mapped messages\n",
+                "mapped messages.1\n",
+                "mapped messages.2\n",
+                "mapped messages.10\n",
+                "mapped messages.20\n"
+            ]
+        );
+        assert_eq!(
+            streamer.current_file(),
+            Path::new("fixtures/non-empty-dir/messages.20")
+        );
+        // Metadata is also looked up at the mapped path
+        assert_eq!(
+            streamer.current_file_metadata().unwrap().size,
+            "mapped messages.20\n".len() as u64
+        );
+
+        // As are the contents compared to deduplicate files
+        for file_name in ["messages.1", "messages.2"] {
+            fs::write(mapped_dir.join(file_name), "mapped duplicate\n").unwrap();
+        }
+        let prefix = mount_point.path().to_path_buf();
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_path_mapper(move |path| prefix.join(path))
+            .with_deduplicate_files(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            streamer.collect::<Vec<String>>(),
+            &[
+                "mapped messages\n",
+                "mapped duplicate\n",
+                "mapped messages.10\n",
+                "mapped messages.20\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn file_open_timeout() {
+        let streamer = DirectoryLinesStreamerBuilder::new("fixtures/non-empty-dir")
+            .with_file_open_timeout(Duration::from_millis(50))
+            .with_error_policy(ErrorPolicy::Collect)
+            .with_custom_open(|path| {
+                if path.ends_with("messages.1") {
+                    thread::sleep(Duration::from_millis(500));
+                }
+                Ok(Box::new(File::open(path)?))
+            })
+            .build()
+            .unwrap();
+
+        let (lines, errors) = streamer.collect_errors();
+        assert_eq!(lines.len(), 12);
+        assert!(lines
+            .iter()
+            .all(|line| !line.ends_with("from messages.1\n")));
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            DirectoryLinesStreamerError::OpenTimeout(file) => {
+                assert_eq!(file, Path::new("fixtures/non-empty-dir/messages.1"))
+            }
+            error => panic!("Unexpected error: {}", error),
+        }
+
+        // The first file is skipped too
+        let mut streamer = DirectoryLinesStreamerBuilder::new("fixtures/non-empty-dir")
+            .with_file_open_timeout(Duration::from_millis(50))
+            .with_error_policy(ErrorPolicy::Collect)
+            .with_custom_open(|path| {
+                if path.ends_with("messages") {
+                    thread::sleep(Duration::from_millis(500));
+                }
+                Ok(Box::new(File::open(path)?))
+            })
+            .build()
+            .unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one from messages.1\n");
+        assert_eq!(streamer.current_file_index(), 1);
+        match streamer.take_errors().as_slice() {
+            [DirectoryLinesStreamerError::OpenTimeout(file)] => {
+                assert_eq!(file, Path::new("fixtures/non-empty-dir/messages"))
+            }
+            errors => panic!("Unexpected errors: {:?}", errors),
+        }
+        assert_eq!(streamer.count(), 11);
+
+        // Unless every file times out
+        let result = DirectoryLinesStreamerBuilder::new("fixtures/non-empty-dir")
+            .with_file_open_timeout(Duration::from_millis(10))
+            .with_custom_open(|path| {
+                thread::sleep(Duration::from_millis(100));
+                Ok(Box::new(File::open(path)?))
+            })
+            .build();
+        match result {
+            Err(DirectoryLinesStreamerError::OpenTimeout(file)) => {
+                assert_eq!(file, Path::new("fixtures/non-empty-dir/messages.20"))
+            }
+            _ => panic!("Unexpected result"),
+        }
+    }
+
+    #[test]
+    fn scanner() {
+        let streamer = DirectoryLinesStreamerBuilder::new("not/a/dir")
+            .with_scanner(|| {
+                Ok(vec![
+                    PathBuf::from("fixtures/non-empty-dir/messages.20"),
+                    PathBuf::from("fixtures/non-empty-dir/messages"),
+                ])
+            })
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "line one from messages.20\n");
+        assert_eq!(lines[3], "line one from messages\n");
+
+        let error = DirectoryLinesStreamerBuilder::new("not/a/dir")
+            .with_scanner(|| Err(io::Error::other("scan failed")))
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, DirectoryLinesStreamerError::Io(_)));
+    }
+
+    #[test]
+    fn open_options() {
+        let opened = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let factory_opened = Arc::clone(&opened);
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_open_options(move |path| {
+                factory_opened.lock().unwrap().push(path.to_path_buf());
+                let mut open_options = OpenOptions::new();
+                open_options.read(true);
+                open_options
+            })
+            .build()
+            .unwrap();
+
+        // Advisory locks held by a producer do not prevent reading
+        #[cfg(target_os = "linux")]
+        let _locked = {
+            use std::os::unix::io::AsRawFd;
+
+            let file = File::open("fixtures/non-empty-dir/messages.1").unwrap();
+            assert_eq!(unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH) }, 0);
+            file
+        };
+
+        assert_eq!(streamer.count(), 15);
+        let opened = opened.lock().unwrap();
+        assert_eq!(opened.len(), 5);
+        assert_eq!(opened[1], Path::new("fixtures/non-empty-dir/messages.1"));
+    }
+
+    #[test]
+    fn on_error() {
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback_reported = Arc::clone(&reported);
+        let streamer = DirectoryLinesStreamerBuilder::new("fixtures/non-empty-dir")
+            .with_on_error(move |error| callback_reported.lock().unwrap().push(error.to_string()))
+            .with_custom_open(|path| {
+                if path.ends_with("messages.2") {
+                    Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"))
+                } else {
+                    Ok(Box::new(File::open(path)?))
+                }
+            })
+            .build()
+            .unwrap();
+
+        // Iteration continues after the error
+        assert_eq!(streamer.count(), 12);
+        assert_eq!(
+            *reported.lock().unwrap(),
+            &["error opening file \"fixtures/non-empty-dir/messages.2\": denied"]
+        );
+    }
+
+    #[test]
+    fn custom_open() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_custom_open(|path| {
+                let file_name = path.file_name().unwrap().to_string_lossy();
+                let content = format!("injected {}\nsecond line\n", file_name);
+                Ok(Box::new(io::Cursor::new(content)))
+            })
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "injected messages\n");
+        assert_eq!(lines[1], "second line\n");
+        assert_eq!(lines[8], "injected messages.20\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "error opening file")]
+    fn eager_error_reporting() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_eager_error_reporting(true)
+            .with_custom_open(|path| {
+                if path.ends_with("messages.1") {
+                    return Err(io::Error::other("fabricated"));
+                }
+                Ok(Box::new(File::open(path)?))
+            })
+            .build()
+            .unwrap();
+        streamer.for_each(drop);
+    }
+
+    #[test]
+    fn concurrent_file_preread() {
+        use std::time::Instant;
+
+        fn slow_builder() -> DirectoryLinesStreamerBuilder {
+            DirectoryLinesStreamer::builder("fixtures/non-empty-dir").with_custom_open(|path| {
+                thread::sleep(Duration::from_millis(100));
+                Ok(Box::new(File::open(path)?))
+            })
+        }
+
+        let start = Instant::now();
+        let expected: Vec<String> = slow_builder().build().unwrap().collect();
+        let sequential = start.elapsed();
+        assert!(sequential >= Duration::from_millis(500));
+
+        let start = Instant::now();
+        let lines: Vec<String> = slow_builder()
+            .with_concurrent_file_preread(4)
+            .build()
+            .unwrap()
+            .collect();
+        let concurrent = start.elapsed();
+        assert_eq!(lines, expected);
+        // The first file is opened by `build()`, the 4 others at the same time
+        assert!(concurrent < Duration::from_millis(400), "{:?}", concurrent);
+
+        // Not when the files are followed
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_concurrent_file_preread(4)
+            .with_file_rename_detection(true)
+            .build()
+            .unwrap();
+        assert!(streamer.prereader.is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn concurrent_file_preread_fifo() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "line one from messages\n").unwrap();
+        let fifo_path = dir.path().join("messages.1");
+        mkfifo(&fifo_path);
+        fs::write(dir.path().join("messages.2"), "line one from messages.2\n").unwrap();
+
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_allow_fifos(true)
+            .with_concurrent_file_preread(2)
+            .build()
+            .unwrap();
+        // Left to the streamer to open
+        let prereader = streamer.prereader.as_ref().unwrap();
+        assert!(!prereader.is_preread(&fifo_path));
+        assert!(prereader.is_preread(&dir.path().join("messages.2")));
+
+        assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+        let writer = thread::spawn(move || {
+            fs::write(&fifo_path, "line one from fifo\n").unwrap();
+        });
+        let lines: Vec<String> = streamer.collect();
+        writer.join().unwrap();
+        assert_eq!(
+            lines,
+            &["line one from fifo\n", "line one from messages.2\n"]
+        );
+    }
+
+    #[test]
+    fn cached_file_handles() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..100 {
+            fs::write(
+                dir.path().join(format!("file.{}", i)),
+                format!("line {}\n", i),
+            )
+            .unwrap();
+        }
+        let opens = Arc::new(AtomicUsize::new(0));
+        let counting_builder = || {
+            let opens = opens.clone();
+            DirectoryLinesStreamer::builder(dir.path()).with_custom_open(move |path| {
+                opens.fetch_add(1, Ordering::SeqCst);
+                Ok(Box::new(File::open(path)?))
+            })
+        };
+
+        let streamer = counting_builder()
+            .with_cached_file_handles(true)
+            .with_max_open_files(10)
+            .build()
+            .unwrap();
+        assert_eq!(opens.load(Ordering::SeqCst), 10);
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(opens.load(Ordering::SeqCst), 100);
+
+        let expected: Vec<String> = counting_builder().build().unwrap().collect();
+        assert_eq!(lines, expected);
+        assert_eq!(lines.len(), 100);
+        assert_eq!(lines[99], "line 99\n");
+    }
+
+    #[test]
+    fn open_retries() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        };
+
+        let failures = Arc::new(AtomicUsize::new(0));
+        let delays = Arc::new(Mutex::new(Vec::new()));
+        let mut builder = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_open_retries(
+                5,
+                BackoffStrategy::Exponential {
+                    base: Duration::from_millis(10),
+                    factor: 2.0,
+                    max: Duration::from_millis(50),
+                },
+            )
+            .with_custom_open({
+                let failures = failures.clone();
+                move |path| {
+                    // `messages.1` opens on the fifth attempt
+                    if path.ends_with("messages.1") && failures.fetch_add(1, Ordering::SeqCst) < 4 {
+                        return Err(io::Error::other("busy"));
+                    }
+                    Ok(Box::new(File::open(path)?))
+                }
+            });
+        builder.options.sleep = Some({
+            let delays = delays.clone();
+            Arc::new(move |delay| delays.lock().unwrap().push(delay))
+        });
+        let streamer = builder.build().unwrap();
+
+        assert_eq!(streamer.count(), 15);
+        assert_eq!(
+            *delays.lock().unwrap(),
+            &[
+                Duration::from_millis(10),
+                Duration::from_millis(20),
+                Duration::from_millis(40),
+                Duration::from_millis(50),
+            ]
+        );
+    }
+
+    #[test]
+    fn metadata_source() {
+        use std::time::{Duration, SystemTime};
+
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_custom_open(|_| Ok(Box::new(io::Cursor::new("injected\n"))))
+            .with_metadata_source(move |path| {
+                if path.ends_with("messages.1") {
+                    None
+                } else {
+                    Some(VirtualMetadata { size: 9, modified })
+                }
+            })
+            .build()
+            .unwrap();
+        streamer.next().unwrap();
+        assert_eq!(
+            streamer.current_file_metadata(),
+            Some(VirtualMetadata { size: 9, modified })
+        );
+        streamer.next().unwrap();
+        assert_eq!(streamer.current_file_metadata(), None);
+
+        // From the filesystem by default, even with stripped paths
+        let dir = fs::canonicalize("fixtures/non-empty-dir").unwrap();
+        let streamer = DirectoryLinesStreamer::builder(&dir)
+            .with_path_prefix_strip(&dir)
+            .build()
+            .unwrap();
+        let metadata = streamer.current_file_metadata().unwrap();
+        assert_eq!(
+            metadata.size,
+            fs::metadata(dir.join("messages")).unwrap().len()
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+
+use crate::DirectoryLinesStreamer;
+
+/// How many lines ahead on each side are searched for a common line when the streams diverge.
+pub const DIFF_CONTEXT_WINDOW: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    OnlyLeft(String),
+    OnlyRight(String),
+}
+
+/// Sequential (non-LCS) line diff of two streamers, see `DirectoryLinesStreamer::diff()`.
+#[derive(Debug)]
+pub struct DiffIterator {
+    left: DirectoryLinesStreamer,
+    right: DirectoryLinesStreamer,
+    left_buf: VecDeque<String>,
+    right_buf: VecDeque<String>,
+    pending: VecDeque<DiffLine>,
+    window: usize,
+}
+
+impl DiffIterator {
+    pub(crate) fn new(left: DirectoryLinesStreamer, right: DirectoryLinesStreamer) -> DiffIterator {
+        DiffIterator {
+            left,
+            right,
+            left_buf: VecDeque::new(),
+            right_buf: VecDeque::new(),
+            pending: VecDeque::new(),
+            window: DIFF_CONTEXT_WINDOW,
+        }
+    }
+
+    fn fill_buffers(&mut self) {
+        while self.left_buf.len() < self.window {
+            match self.left.next() {
+                Some(line) => self.left_buf.push_back(line),
+                None => break,
+            }
+        }
+        while self.right_buf.len() < self.window {
+            match self.right.next() {
+                Some(line) => self.right_buf.push_back(line),
+                None => break,
+            }
+        }
+    }
+
+    /// Find the closest pair of indices (smallest `i + j`) where both buffers contain the same line.
+    fn find_resync(&self) -> Option<(usize, usize)> {
+        let max_distance = self.left_buf.len() + self.right_buf.len();
+        for distance in 1..max_distance {
+            for i in 0..=distance {
+                let j = distance - i;
+                if let (Some(l), Some(r)) = (self.left_buf.get(i), self.right_buf.get(j)) {
+                    if l == r {
+                        return Some((i, j));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Iterator for DiffIterator {
+    type Item = DiffLine;
+
+    fn next(&mut self) -> Option<DiffLine> {
+        if let Some(diff_line) = self.pending.pop_front() {
+            return Some(diff_line);
+        }
+
+        self.fill_buffers();
+
+        match (self.left_buf.front(), self.right_buf.front()) {
+            (None, None) => None,
+            (Some(_), None) => self.left_buf.pop_front().map(DiffLine::OnlyLeft),
+            (None, Some(_)) => self.right_buf.pop_front().map(DiffLine::OnlyRight),
+            (Some(l), Some(r)) if l == r => {
+                self.right_buf.pop_front();
+                self.left_buf.pop_front().map(DiffLine::Same)
+            }
+            (Some(_), Some(_)) => {
+                // Streams diverged. Emit what is only on each side until both streams
+                // are back on a common line. If none is found inside the window, consider
+                // both current lines as changed.
+                let (nb_left, nb_right) = self.find_resync().unwrap_or((1, 1));
+                let only_left = self.left_buf.drain(..nb_left).map(DiffLine::OnlyLeft);
+                let only_right = self.right_buf.drain(..nb_right).map(DiffLine::OnlyRight);
+                self.pending.extend(only_left.chain(only_right));
+                self.pending.pop_front()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_identical() {
+        let left = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let right = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let diff: Vec<DiffLine> = left.diff(right).collect();
+        assert_eq!(diff.len(), 15);
+        assert!(diff.iter().all(|d| matches!(d, DiffLine::Same(_))));
+    }
+
+    #[test]
+    fn diff_modified() {
+        let left = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let right = DirectoryLinesStreamer::from_dir("fixtures/modified-dir").unwrap();
+        let diff: Vec<DiffLine> = left.diff(right).collect();
+
+        let nb_same = diff
+            .iter()
+            .filter(|d| matches!(d, DiffLine::Same(_)))
+            .count();
+        assert_eq!(nb_same, 14);
+        assert_eq!(diff.len(), 17);
+
+        assert_eq!(
+            diff[4],
+            DiffLine::OnlyLeft("line two from messages.1\n".to_string())
+        );
+        assert_eq!(
+            diff[5],
+            DiffLine::OnlyRight("line 2 from messages.1\n".to_string())
+        );
+        assert_eq!(
+            diff[9],
+            DiffLine::OnlyRight("extra line in messages.2\n".to_string())
+        );
+        assert_eq!(
+            diff[10],
+            DiffLine::Same("line three from messages.2\n".to_string())
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use alloc::{string::String, vec::Vec};
+
+use embedded_io::Read;
+
+const CHUNK_SIZE: usize = 256;
+
+/// Read lines from a single `embedded_io::Read` source, without the standard library.
+///
+/// Lines keep their trailing newline and are converted to UTF-8 lossily, like
+/// `DirectoryLinesStreamer` does by default. `&mut dyn Read<Error = E>` sources are
+/// supported through `embedded_io`'s blanket implementation.
+#[derive(Debug)]
+pub struct LinesReader<R> {
+    source: R,
+    newline: u8,
+    // Bytes read from `source` but not yet returned
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R> LinesReader<R>
+where
+    R: Read,
+{
+    pub fn new(source: R) -> LinesReader<R> {
+        LinesReader {
+            source,
+            newline: b'\n',
+            buf: Vec::new(),
+            eof: false,
+        }
+    }
+
+    /// Split records on `newline` instead of `b'\n'`.
+    pub fn with_custom_newline(mut self, newline: u8) -> LinesReader<R> {
+        self.newline = newline;
+        self
+    }
+}
+
+impl<R> Iterator for LinesReader<R>
+where
+    R: Read,
+{
+    type Item = Result<String, R::Error>;
+
+    fn next(&mut self) -> Option<Result<String, R::Error>> {
+        loop {
+            if let Some(position) = self.buf.iter().position(|&b| b == self.newline) {
+                let rest = self.buf.split_off(position + 1);
+                let line = core::mem::replace(&mut self.buf, rest);
+                return Some(Ok(String::from_utf8_lossy(&line).into_owned()));
+            }
+            if self.eof {
+                if self.buf.is_empty() {
+                    return None;
+                }
+                let line = core::mem::take(&mut self.buf);
+                return Some(Ok(String::from_utf8_lossy(&line).into_owned()));
+            }
+
+            let mut chunk = [0u8; CHUNK_SIZE];
+            match self.source.read(&mut chunk) {
+                Ok(0) => self.eof = true,
+                Ok(nb_bytes_read) => self.buf.extend_from_slice(&chunk[..nb_bytes_read]),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_reader() {
+        let source: &[u8] = b"line one\nline two\nno trailing newline";
+        let lines: Vec<String> = LinesReader::new(source).map(Result::unwrap).collect();
+        assert_eq!(lines, &["line one\n", "line two\n", "no trailing newline"]);
+    }
+
+    #[test]
+    fn lines_reader_dyn_source() {
+        let mut source: &[u8] = b"record one\0record two\0";
+        let source: &mut dyn Read<Error = core::convert::Infallible> = &mut source;
+        let lines: Vec<String> = LinesReader::new(source)
+            .with_custom_newline(b'\0')
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines, &["record one\0", "record two\0"]);
+    }
+}
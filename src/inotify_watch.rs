@@ -0,0 +1,181 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+
+use crate::{DirectoryLinesStreamer, DirectoryLinesStreamerError};
+
+/// Inotify watch of the directory of a streamer, see
+/// `DirectoryLinesStreamer::from_dir_with_inotify()`.
+#[derive(Debug)]
+pub(crate) struct InotifyWatch {
+    inotify: Inotify,
+    watch: WatchDescriptor,
+}
+
+impl InotifyWatch {
+    fn new(dir: &Path) -> io::Result<InotifyWatch> {
+        let inotify = Inotify::init()?;
+        let watch = inotify.watches().add(
+            dir,
+            WatchMask::CREATE | WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::MODIFY,
+        )?;
+        Ok(InotifyWatch { inotify, watch })
+    }
+}
+
+impl Drop for InotifyWatch {
+    fn drop(&mut self) {
+        if let Err(error) = self.inotify.watches().remove(self.watch.clone()) {
+            log::debug!("Failed to remove inotify watch: {}", error);
+        }
+    }
+}
+
+impl DirectoryLinesStreamer {
+    /// Like `from_dir()`, then wait for new files instead of returning `None` once all the
+    /// lines are read, using inotify.
+    ///
+    /// Files are added once closed after being written, or moved in the directory. Lines
+    /// appended to the last file are read too. `stop_watching()` takes effect on the next
+    /// event in the directory.
+    pub fn from_dir_with_inotify<P>(
+        input_dir: P,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        let dir = input_dir.into();
+        if !dir.exists() {
+            return Err(DirectoryLinesStreamerError::DirectoryDoesNotExists(dir));
+        }
+        // Watch before listing, so that no file added in between is missed
+        let watch = InotifyWatch::new(&dir).map_err(DirectoryLinesStreamerError::Io)?;
+        let mut streamer = DirectoryLinesStreamer::from_dir(dir)?;
+        // Files written while listing are reported too: they must not be read twice
+        streamer.seen_files = streamer.all_files.iter().cloned().collect();
+        streamer.inotify_watch = Some(watch);
+        Ok(streamer)
+    }
+
+    /// Block until new files are added or the current file is modified.
+    ///
+    /// Returns `false` when not watching with inotify, or once watching is stopped.
+    pub(crate) fn wait_for_inotify_events(&mut self) -> bool {
+        let mut buffer = [0; 4096];
+        loop {
+            let watch = match &mut self.inotify_watch {
+                Some(watch) => watch,
+                None => return false,
+            };
+            let events = match watch.inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(error) => {
+                    self.emit_error(DirectoryLinesStreamerError::Io(error));
+                    return false;
+                }
+            };
+            if self.watch_stopper.is_stopped() {
+                return false;
+            }
+            let current_file_name = self.opened_file_path.file_name();
+            let mut new_files: Vec<PathBuf> = Vec::new();
+            let mut modified = false;
+            for event in events {
+                let name = match event.name {
+                    Some(name) => name,
+                    None => continue,
+                };
+                if Some(name) == current_file_name {
+                    modified = true;
+                    continue;
+                }
+                let path = self.dir.join(name);
+                if event.mask.contains(EventMask::CREATE) {
+                    log::debug!("{:?} created, waiting for it to be written", path);
+                } else if event
+                    .mask
+                    .intersects(EventMask::CLOSE_WRITE | EventMask::MOVED_TO)
+                    && path.is_file()
+                    && self.seen_files.insert(path.clone())
+                {
+                    new_files.push(path);
+                }
+            }
+            if !new_files.is_empty() {
+                self.insert_new_files(new_files);
+                return true;
+            }
+            if modified {
+                return true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{fs, io::Write, thread, time::Duration};
+
+    #[test]
+    fn from_dir_with_inotify() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "line one from messages\n").unwrap();
+
+        let mut streamer = DirectoryLinesStreamer::from_dir_with_inotify(dir.path()).unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+
+        let stopper = streamer.watch_stopper();
+        let dir_path = dir.path().to_path_buf();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            let mut current = fs::OpenOptions::new()
+                .append(true)
+                .open(dir_path.join("messages"))
+                .unwrap();
+            current.write_all(b"line two from messages\n").unwrap();
+            fs::write(dir_path.join("messages.1"), "line one from messages.1\n").unwrap();
+            thread::sleep(Duration::from_millis(200));
+            stopper.stop_watching();
+            // Wake the streamer up
+            fs::write(dir_path.join("messages.2"), "line one from messages.2\n").unwrap();
+        });
+
+        assert_eq!(streamer.next().unwrap(), "line two from messages\n");
+        assert_eq!(streamer.next().unwrap(), "line one from messages.1\n");
+        assert_eq!(streamer.next(), None);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn from_dir_with_inotify_listed_files_are_not_read_again() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "line one from messages\n").unwrap();
+
+        let mut streamer = DirectoryLinesStreamer::from_dir_with_inotify(dir.path()).unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+
+        let stopper = streamer.watch_stopper();
+        let dir_path = dir.path().to_path_buf();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            // Reported as closed after writing, like a file written while listing
+            fs::OpenOptions::new()
+                .append(true)
+                .open(dir_path.join("messages"))
+                .unwrap();
+            fs::write(dir_path.join("messages.1"), "line one from messages.1\n").unwrap();
+            thread::sleep(Duration::from_millis(200));
+            stopper.stop_watching();
+            fs::write(dir_path.join("messages.2"), "line one from messages.2\n").unwrap();
+        });
+
+        assert_eq!(streamer.next().unwrap(), "line one from messages.1\n");
+        assert_eq!(streamer.next(), None);
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use std::io;
+
+/// I/O scheduling class and priority of the reading thread, see
+/// `DirectoryLinesStreamerBuilder::with_io_priority()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Best-effort scheduling, from `0` (highest priority) to `7` (lowest priority).
+    BestEffort(u8),
+    /// Only get disk time when no other program needs it.
+    Idle,
+}
+
+#[cfg(target_os = "linux")]
+impl IoPriority {
+    /// Value given to `ioprio_set(2)`: the class in the upper bits, the level in the lower ones.
+    fn ioprio(self) -> libc::c_int {
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        const IOPRIO_CLASS_BE: libc::c_int = 2;
+        const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+        match self {
+            IoPriority::BestEffort(level) => {
+                IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT | libc::c_int::from(level.min(7))
+            }
+            IoPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        }
+    }
+}
+
+/// `who` of `ioprio_set(2)` targeting a thread, the calling one for `0`.
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+/// Set the I/O priority of the calling thread.
+#[cfg(target_os = "linux")]
+pub(crate) fn set_io_priority(priority: IoPriority) -> io::Result<()> {
+    // Safe: the syscall only reads its integer arguments.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            priority.ioprio(),
+        )
+    };
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// I/O priorities are only supported on Linux.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_io_priority(_priority: IoPriority) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    use crate::DirectoryLinesStreamer;
+
+    fn io_priority() -> libc::c_long {
+        unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) }
+    }
+
+    #[test]
+    fn with_io_priority() {
+        // Each test runs in its own thread.
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_io_priority(IoPriority::BestEffort(6))
+            .build()
+            .unwrap();
+        assert_eq!(io_priority(), (2 << 13) | 6);
+        assert_eq!(streamer.count(), 15);
+
+        DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_io_priority(IoPriority::Idle)
+            .build()
+            .unwrap();
+        assert_eq!(io_priority(), 3 << 13);
+    }
+}
@@ -0,0 +1,77 @@
+use serde_json::Value;
+
+use crate::{strip_newline, DirectoryLinesStreamer, DirectoryLinesStreamerError};
+
+impl DirectoryLinesStreamer {
+    /// Split a line holding a JSON array in one line per element, see
+    /// `DirectoryLinesStreamerBuilder::with_flatten_json_arrays()`.
+    ///
+    /// The first element is returned, the others are queued in `pending_lines`.
+    pub(crate) fn flatten_json_array(&mut self, line: String) -> Option<String> {
+        let content = strip_newline(&line, self.options.newline);
+        match serde_json::from_str::<Value>(content) {
+            Ok(Value::Array(elements)) => {
+                // Keep the delimiter of the original line on each element
+                let delimiter = &line[content.len()..];
+                let mut lines = elements
+                    .iter()
+                    .map(|element| format!("{}{}", element, delimiter));
+                let first = lines.next();
+                self.pending_lines.extend(lines);
+                first
+            }
+            Ok(_) => Some(line),
+            Err(error) => {
+                self.emit_error(DirectoryLinesStreamerError::InvalidJson {
+                    file: self.opened_file_path.to_path_buf(),
+                    line_id: self.line_count(),
+                    error,
+                });
+                Some(line)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flatten_json_arrays() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/json-dir")
+            .with_flatten_json_arrays(true)
+            .build()
+            .unwrap();
+        let (lines, errors) = streamer.collect_errors();
+        assert_eq!(
+            lines,
+            &[
+                "{\"id\":1}\n",
+                "{\"id\":2}\n",
+                "{\"id\":3}\n",
+                "4\n",
+                "\"five\"\n",
+                "not json\n"
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            DirectoryLinesStreamerError::InvalidJson { line_id, .. } => assert_eq!(*line_id, 5),
+            _ => panic!("Unexpected error"),
+        }
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/json-dir").unwrap();
+        assert_eq!(streamer.count(), 5);
+    }
+
+    #[test]
+    fn skip_first_line_of_flattened_array() {
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/json-dir")
+            .with_flatten_json_arrays(true)
+            .build()
+            .unwrap();
+        streamer.skip_first_line();
+        assert_eq!(streamer.next().unwrap(), "{\"id\":2}\n");
+    }
+}
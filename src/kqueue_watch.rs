@@ -0,0 +1,156 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use kqueue::{EventData, EventFilter, FilterFlag, Watcher};
+
+use crate::{builder::list_files, DirectoryLinesStreamer, DirectoryLinesStreamerError};
+
+/// Kqueue watch of the directory of a streamer, and of its current file, see
+/// `DirectoryLinesStreamer::from_dir_with_kqueue()`.
+#[derive(Debug)]
+pub(crate) struct KqueueWatch {
+    watcher: Watcher,
+    // File watched for appended lines
+    watched_file: Option<PathBuf>,
+}
+
+impl KqueueWatch {
+    fn new(dir: &Path) -> io::Result<KqueueWatch> {
+        let mut watcher = Watcher::new()?;
+        watcher.add_filename(dir, EventFilter::EVFILT_VNODE, FilterFlag::NOTE_WRITE)?;
+        watcher.watch()?;
+        Ok(KqueueWatch {
+            watcher,
+            watched_file: None,
+        })
+    }
+
+    /// Watch `file` for appended lines, instead of the previously watched file.
+    fn watch_file(&mut self, file: &Path) -> io::Result<()> {
+        if self.watched_file.as_deref() == Some(file) {
+            return Ok(());
+        }
+        if let Some(previous) = self.watched_file.take() {
+            self.watcher
+                .remove_filename(&previous, EventFilter::EVFILT_VNODE)?;
+        }
+        self.watcher.add_filename(
+            file,
+            EventFilter::EVFILT_VNODE,
+            FilterFlag::NOTE_WRITE | FilterFlag::NOTE_EXTEND,
+        )?;
+        self.watcher.watch()?;
+        self.watched_file = Some(file.to_path_buf());
+        Ok(())
+    }
+}
+
+impl DirectoryLinesStreamer {
+    /// Like `from_dir()`, then wait for new files instead of returning `None` once all the
+    /// lines are read, using kqueue.
+    ///
+    /// Kqueue reports changes as soon as a file is created: rename complete files in the
+    /// directory so that they are never read half written. Lines appended to the last file are
+    /// read too. `stop_watching()` takes effect on the next event.
+    pub fn from_dir_with_kqueue<P>(
+        input_dir: P,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        let dir = input_dir.into();
+        if !dir.exists() {
+            return Err(DirectoryLinesStreamerError::DirectoryDoesNotExists(dir));
+        }
+        // Watch before listing, so that no file added in between is missed
+        let watch = KqueueWatch::new(&dir).map_err(DirectoryLinesStreamerError::Io)?;
+        let mut streamer = DirectoryLinesStreamer::from_dir(dir)?;
+        // The directory is listed again on events: listed files must not be read twice
+        streamer.seen_files = streamer.all_files.iter().cloned().collect();
+        streamer.kqueue_watch = Some(watch);
+        Ok(streamer)
+    }
+
+    /// Block until the directory or the current file changes, and push new files to `files`.
+    ///
+    /// Returns `false` when not watching with kqueue, or once watching is stopped.
+    pub(crate) fn wait_for_kqueue_events(&mut self) -> bool {
+        let watch = match &mut self.kqueue_watch {
+            Some(watch) => watch,
+            None => return false,
+        };
+        if let Err(error) = watch.watch_file(&self.opened_file_path) {
+            log::debug!("Failed to watch {:?}: {}", self.opened_file_path, error);
+        }
+        let event = match watch.watcher.poll_forever(None) {
+            Some(event) => event,
+            None => return false,
+        };
+        if self.watch_stopper.is_stopped() {
+            return false;
+        }
+        if let EventData::Error(error) = event.data {
+            self.emit_error(DirectoryLinesStreamerError::Io(error));
+            return false;
+        }
+        match list_files(&self.dir, &self.options) {
+            Ok(files) => {
+                let seen_files = &mut self.seen_files;
+                let new_files: Vec<PathBuf> = files
+                    .into_iter()
+                    .filter(|file| seen_files.insert(file.clone()))
+                    .collect();
+                if !new_files.is_empty() {
+                    self.insert_new_files(new_files);
+                }
+            }
+            Err(error) => self.emit_error(DirectoryLinesStreamerError::Io(error)),
+        }
+        // Read the current file again, or the new files
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{fs, io::Write, thread, time::Duration};
+
+    #[test]
+    fn from_dir_with_kqueue() {
+        let dir = tempfile::tempdir().unwrap();
+        let staging = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "line one from messages\n").unwrap();
+
+        let mut streamer = DirectoryLinesStreamer::from_dir_with_kqueue(dir.path()).unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+
+        let stopper = streamer.watch_stopper();
+        let dir_path = dir.path().to_path_buf();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            let mut current = fs::OpenOptions::new()
+                .append(true)
+                .open(dir_path.join("messages"))
+                .unwrap();
+            current.write_all(b"line two from messages\n").unwrap();
+            thread::sleep(Duration::from_millis(100));
+            // Rename a complete file, so that it is never read half written
+            let staged = staging.path().join("messages.1");
+            fs::write(&staged, "line one from messages.1\n").unwrap();
+            fs::rename(&staged, dir_path.join("messages.1")).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            stopper.stop_watching();
+            // Wake the streamer up
+            fs::write(dir_path.join("messages.2"), "").unwrap();
+        });
+
+        assert_eq!(streamer.next().unwrap(), "line two from messages\n");
+        assert_eq!(streamer.next().unwrap(), "line one from messages.1\n");
+        assert_eq!(streamer.next(), None);
+        writer.join().unwrap();
+    }
+}
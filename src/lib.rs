@@ -1,225 +1,4174 @@
+// Without the (default) `std` feature, only the `embedded_io` based `LinesReader` is available.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
 use std::{
-    fs::{self, File},
-    io::{self, BufRead, BufReader},
-    path::PathBuf,
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    env, error,
+    ffi::{CString, NulError},
+    fmt, fs,
+    hash::Hash,
+    io::{self, BufRead, BufReader, Read, Write},
+    mem,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    string::FromUtf8Error,
+    sync::Arc,
+    time::Duration,
+};
+
+#[cfg(feature = "std")]
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "chrono")]
+use chrono::NaiveDate;
+
+#[cfg(feature = "chrono")]
+use crate::template::file_name_date_in_range;
+#[cfg(feature = "std")]
+use crate::{
+    builder::{list_files, Options},
+    open::{
+        file_metadata, is_line_timeout, is_open_timeout, open_reader, open_reader_with_retries,
+        FileReader,
+    },
+    position::FilePosition,
+    preread::Prereader,
+    rename::FileIdentity,
+    rng::SplitMix64,
+};
+
+#[cfg(feature = "std")]
+mod adapters;
+#[cfg(feature = "tokio")]
+mod async_stream;
+#[cfg(feature = "std")]
+mod audit;
+#[cfg(feature = "std")]
+mod backoff;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod diff;
+#[cfg(feature = "embedded-io")]
+mod embedded;
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+mod inotify_watch;
+#[cfg(feature = "ioprio")]
+mod ioprio;
+#[cfg(feature = "serde_json")]
+mod json;
+#[cfg(all(
+    feature = "kqueue",
+    any(
+        target_os = "macos",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    )
+))]
+mod kqueue_watch;
+#[cfg(feature = "std")]
+mod line_endings;
+#[cfg(feature = "unicode-normalization")]
+mod normalization;
+#[cfg(feature = "std")]
+mod open;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "std")]
+mod position;
+#[cfg(feature = "std")]
+mod preread;
+#[cfg(feature = "std")]
+mod rename;
+#[cfg(feature = "std")]
+mod rng;
+#[cfg(feature = "std")]
+mod sort;
+#[cfg(feature = "chrono")]
+mod template;
+#[cfg(all(feature = "std", any(test, feature = "test-helpers")))]
+mod testing;
+#[cfg(feature = "std")]
+mod watch;
+#[cfg(feature = "bytes")]
+mod zero_copy;
+
+#[cfg(feature = "std")]
+pub use adapters::{
+    ByteLimited, ContextLine, ContextLines, DirectoryBytesStreamer, FlatMapLines,
+    GroupConsecutiveBy, Interleaved, InterleavedN, LineMetadata, LinesWithMetadata, LinesWithPath,
+    MultiPassStreamer, PrefetchedLinesStreamer, RateLimited, ScanLines, TakeUntilSentinel,
+    TakeWhileFile, TryLines, WindowFiles, ZipWithPrevious,
 };
+#[cfg(feature = "std")]
+pub use backoff::BackoffStrategy;
+#[cfg(feature = "std")]
+pub use builder::DirectoryLinesStreamerBuilder;
+#[cfg(feature = "std")]
+pub use diff::{DiffIterator, DiffLine, DIFF_CONTEXT_WINDOW};
+#[cfg(feature = "embedded-io")]
+pub use embedded::LinesReader;
+#[cfg(feature = "ioprio")]
+pub use ioprio::IoPriority;
+#[cfg(feature = "std")]
+pub use line_endings::LineEndingStats;
+#[cfg(feature = "unicode-normalization")]
+pub use normalization::NormalizationForm;
+#[cfg(feature = "std")]
+pub use open::{OpenFlags, VirtualMetadata};
+#[cfg(feature = "std")]
+pub use position::StreamPosition;
+#[cfg(feature = "std")]
+pub use sort::{SelectMode, SortOrder};
+#[cfg(all(feature = "std", any(test, feature = "test-helpers")))]
+pub use testing::{sort_files_natural, CompareError};
+#[cfg(feature = "std")]
+pub use watch::WatchStopper;
+#[cfg(feature = "bytes")]
+pub use zero_copy::ZeroCopyLines;
+
+/// `current_file()` while the last line read is a separator, see
+/// `DirectoryLinesStreamerBuilder::with_interfile_separator()`.
+#[cfg(feature = "std")]
+pub const SEPARATOR_FILE: &str = "<separator>";
 
-use failure::Fail;
-use log;
+/// Convert a line yielded with `DirectoryLinesStreamerBuilder::with_nul_terminated_lines()`
+/// to a `CString`, without its NUL terminator.
+///
+/// Fails if the line holds another NUL byte.
+#[cfg(feature = "std")]
+pub fn as_cstring(line: &str) -> Result<CString, NulError> {
+    CString::new(line.strip_suffix('\0').unwrap_or(line))
+}
 
-#[derive(Debug, Fail)]
+#[cfg(feature = "std")]
+#[derive(Debug)]
 pub enum DirectoryLinesStreamerError {
-    #[fail(display = "directory {:?} does not exists", _0)]
     DirectoryDoesNotExists(PathBuf),
-    #[fail(display = "{}", _0)]
-    Io(#[cause] io::Error),
-    #[fail(display = "directory {:?} is empty", _0)]
+    Io(io::Error),
     EmptyDirectory(PathBuf),
+    EnvVarNotSet(String),
+    EnvVarNotUtf8(String),
+    OpenFile {
+        file: PathBuf,
+        error: io::Error,
+    },
+    ReadLine {
+        file: PathBuf,
+        line_id: usize,
+        error: io::Error,
+    },
+    InvalidCheckpoint(String),
+    ReadTimeout(PathBuf, Duration),
+    LineReadTimeout(PathBuf, usize, Duration),
+    OpenTimeout(PathBuf),
+    FileDeletedBeforeRead(PathBuf),
+    FileNotFound(PathBuf),
+    InvalidArgument(String),
+    #[cfg(feature = "serde_json")]
+    InvalidJson {
+        file: PathBuf,
+        line_id: usize,
+        error: serde_json::Error,
+    },
+    InvalidUtf8 {
+        file: PathBuf,
+        line_id: usize,
+        error: FromUtf8Error,
+    },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for DirectoryLinesStreamerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DirectoryLinesStreamerError::*;
+
+        match self {
+            DirectoryDoesNotExists(dir) => write!(f, "directory {:?} does not exists", dir),
+            Io(error) => write!(f, "{}", error),
+            EmptyDirectory(dir) => write!(f, "directory {:?} is empty", dir),
+            EnvVarNotSet(var) => write!(f, "environment variable {:?} is not set", var),
+            EnvVarNotUtf8(var) => write!(f, "environment variable {:?} is not valid UTF-8", var),
+            OpenFile { file, error } => write!(f, "error opening file {:?}: {}", file, error),
+            ReadLine {
+                file,
+                line_id,
+                error,
+            } => write!(f, "error reading line {} of {:?}: {}", line_id, file, error),
+            InvalidCheckpoint(reason) => write!(f, "invalid checkpoint: {}", reason),
+            ReadTimeout(file, timeout) => {
+                write!(f, "reading {:?} took more than {:?}", file, timeout)
+            }
+            LineReadTimeout(file, line_id, timeout) => write!(
+                f,
+                "reading line {} of {:?} took more than {:?}",
+                line_id, file, timeout
+            ),
+            OpenTimeout(file) => write!(f, "opening {:?} timed out", file),
+            FileDeletedBeforeRead(file) => {
+                write!(f, "file {:?} was deleted before it could be read", file)
+            }
+            FileNotFound(file) => write!(f, "file {:?} is not in the directory", file),
+            InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            #[cfg(feature = "serde_json")]
+            InvalidJson { file, line_id, .. } => {
+                write!(f, "line {} of {:?} is not valid JSON", line_id, file)
+            }
+            InvalidUtf8 { file, line_id, .. } => {
+                write!(f, "line {} of {:?} is not valid UTF-8", line_id, file)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for DirectoryLinesStreamerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use DirectoryLinesStreamerError::*;
+
+        match self {
+            Io(error) | OpenFile { error, .. } | ReadLine { error, .. } => Some(error),
+            #[cfg(feature = "serde_json")]
+            InvalidJson { error, .. } => Some(error),
+            InvalidUtf8 { error, .. } => Some(error),
+            DirectoryDoesNotExists(_)
+            | EmptyDirectory(_)
+            | EnvVarNotSet(_)
+            | EnvVarNotUtf8(_)
+            | InvalidCheckpoint(_)
+            | ReadTimeout(_, _)
+            | LineReadTimeout(_, _, _)
+            | OpenTimeout(_)
+            | FileDeletedBeforeRead(_)
+            | FileNotFound(_)
+            | InvalidArgument(_) => None,
+        }
+    }
+}
+
+/// Index of a file, with the offset of one of its chunks.
+#[cfg(feature = "std")]
+type FileChunk = (usize, u64);
+
+/// What happens to the errors encountered while iterating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Report errors through `log::error!()`.
+    Log,
+    /// Keep errors until they are retrieved with `DirectoryLinesStreamer::take_errors()`.
+    Collect,
+}
+
+/// What `DirectoryLinesStreamer::line_number()` returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineNumberingMode {
+    /// Line number (1-based, across all files) of the last line read.
+    LineCount,
+    /// Offset (in bytes) of the start of the last line read, in its file.
+    ByteOffset,
+    /// Offset (in characters) of the start of the last line read, in its file.
+    ///
+    /// Characters are counted from where reading started in the file.
+    CharOffset,
+}
+
+/// How lines that are not valid UTF-8 are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Utf8Mode {
+    /// Replace invalid bytes with U+FFFD REPLACEMENT CHARACTER (`�`).
+    Lossy,
+    /// Report a `DirectoryLinesStreamerError::InvalidUtf8` and drop the line.
+    Strict,
+    /// Silently drop the line.
+    Skip,
+}
+
+/// File whose SHA-256 digest is not the expected one, see
+/// `DirectoryLinesStreamer::compare_checksums()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumMismatch {
+    /// The content of the file has another digest.
+    Differs {
+        actual: [u8; 32],
+        expected: [u8; 32],
+    },
+    /// The file is not one of the streamed files.
+    NotFound { expected: [u8; 32] },
+    /// The file is one of the streamed files, but could not be read.
+    Unreadable { expected: [u8; 32] },
 }
 
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub struct DirectoryLinesStreamer {
     dir: PathBuf,
+    // Files to stream, as listed at construction (and while watching), without stripping
+    all_files: Vec<PathBuf>,
     files: Vec<PathBuf>,
-    opened_file_path: PathBuf,
-    opened_file: BufReader<File>,
+    // Shared with the paths yielded by `lines_with_path()`
+    opened_file_path: Arc<PathBuf>,
+    opened_file: BufReader<FileReader>,
     line_id: usize,
+    file_position: FilePosition,
+    options: Options,
+    // Errors collected under `ErrorPolicy::Collect`
+    errors: VecDeque<DirectoryLinesStreamerError>,
+    // Lines of every file, grouped by file, when `with_preload(true)` is used
+    preloaded: Option<VecDeque<PreloadedFile>>,
+    // Line being consumed through the `io::Read` / `io::BufRead` implementations
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    // Lines produced by a transformation, to finish and yield before reading further
+    pending_lines: VecDeque<String>,
+    // Next line as yielded, see `peek_first_line()`
+    peeked_line: Option<String>,
+    // Bytes of the last line read
+    line_buf: Vec<u8>,
+    // Last lines of the current file, see `with_take_last_lines()` and `with_reverse_line_order()`
+    last_lines: VecDeque<Vec<u8>>,
+    // Last lines of a finished file, to yield before reading further
+    released_lines: VecDeque<Vec<u8>>,
+    // Files listed so far, see `with_watch_interval()`
+    seen_files: HashSet<PathBuf>,
+    watch_stopper: WatchStopper,
+    // `false` until the directory is scanned, see `from_dir_lazy()`
+    scan_done: bool,
+    // Sizes of the files, see `remaining_bytes_estimate()`
+    file_sizes: RefCell<HashMap<PathBuf, Option<u64>>>,
+    total_bytes: u64,
+    // The last line yielded is the separator, see `with_interfile_separator()`
+    at_separator: bool,
+    // Index of the file the last line yielded was read from
+    last_line_file: Option<usize>,
+    // The last line read ended with the newline delimiter, see `LineMetadata`
+    raw_line_ends_with_newline: bool,
+    // No line was read from the opened file yet, see `with_empty_file_placeholder()`
+    opened_file_empty: bool,
+    // The opening of the current file is recorded, but not its end, see `with_audit_log()`
+    audit_file_open: bool,
+    // File opened at `opened_file_path`, see `with_file_rename_detection()`
+    opened_file_identity: Option<FileIdentity>,
+    // See `with_line_ending_statistics()`
+    line_ending_stats: LineEndingStats,
+    // See `with_concurrent_file_preread()`
+    prereader: Option<Prereader>,
+    // Files opened in advance, see `with_cached_file_handles()`
+    cached_files: HashMap<PathBuf, FileReader>,
+    // See `from_dir_with_inotify()`
+    #[cfg(all(feature = "inotify", target_os = "linux"))]
+    inotify_watch: Option<inotify_watch::InotifyWatch>,
+    // See `from_dir_with_kqueue()`
+    #[cfg(all(
+        feature = "kqueue",
+        any(
+            target_os = "macos",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd"
+        )
+    ))]
+    kqueue_watch: Option<kqueue_watch::KqueueWatch>,
 }
 
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct PreloadedFile {
+    path: Arc<PathBuf>,
+    index: usize,
+    lines: VecDeque<Vec<u8>>,
+}
+
+#[cfg(feature = "std")]
 impl DirectoryLinesStreamer {
-    pub fn from_dir<P>(input_dir: P) -> Result<DirectoryLinesStreamer, failure::Error>
+    pub fn from_dir<P>(input_dir: P) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        DirectoryLinesStreamerBuilder::new(input_dir).build()
+    }
+
+    /// Like `from_dir()`, also returning the files that will be read, see `files_snapshot()`.
+    ///
+    /// Files created later are never read; files deleted before being opened are reported as
+    /// `DirectoryLinesStreamerError::FileDeletedBeforeRead`.
+    pub fn from_dir_with_snapshot<P>(
+        input_dir: P,
+    ) -> Result<(DirectoryLinesStreamer, Vec<PathBuf>), DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        let streamer = DirectoryLinesStreamer::from_dir(input_dir)?;
+        let files = streamer.files_snapshot();
+        Ok((streamer, files))
+    }
+
+    /// Like `from_dir()`, reading files with a higher `priority(path)` first, see
+    /// `DirectoryLinesStreamerBuilder::with_file_priority()`.
+    pub fn from_dir_with_priority<P, F>(
+        input_dir: P,
+        priority: F,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+        F: Fn(&Path) -> i64 + Send + Sync + 'static,
+    {
+        DirectoryLinesStreamerBuilder::new(input_dir)
+            .with_file_priority(priority)
+            .build()
+    }
+
+    /// Like `from_dir()`, without the files after the ones adding up to `max_bytes`, see
+    /// `DirectoryLinesStreamerBuilder::with_max_total_bytes()`.
+    pub fn from_dir_with_max_total_bytes<P>(
+        input_dir: P,
+        max_bytes: u64,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        DirectoryLinesStreamerBuilder::new(input_dir)
+            .with_max_total_bytes(max_bytes)
+            .build()
+    }
+
+    /// Like `from_dir()`, streaming only the latest version of each rotated file: `app.log`
+    /// out of `app.log`, `app.log.1` and `app.log.2.gz`.
+    ///
+    /// Files are grouped by name without their `.N` or `.N.gz` suffix; the file with the
+    /// smallest `N` of each group is kept, no suffix counting as `0`.
+    pub fn from_dir_with_latest_version_only<P>(
+        input_dir: P,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        DirectoryLinesStreamerBuilder::new(input_dir)
+            .with_file_selector(|files| select_versions(files, VersionSelector::Latest))
+            .build()
+    }
+
+    /// Like `from_dir_with_latest_version_only()`, keeping the oldest version of each rotated
+    /// file instead, with the largest `N`: `app.log.2.gz` out of `app.log`, `app.log.1` and
+    /// `app.log.2.gz`.
+    ///
+    /// Active logs, without suffix, are only streamed when they were never rotated.
+    pub fn from_dir_with_oldest_version_only<P>(
+        input_dir: P,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        DirectoryLinesStreamerBuilder::new(input_dir)
+            .with_file_selector(|files| select_versions(files, VersionSelector::Oldest))
+            .build()
+    }
+
+    /// Like `from_dir()`, reading the `.gz` archive of a file only when the plain file is
+    /// missing: `app.log` and `app.log.1.gz` out of `app.log`, `app.log.1.gz`, `app.log.2` and
+    /// `app.log.2.gz`.
+    ///
+    /// Files with the `.gz` extension are decompressed.
+    #[cfg(feature = "gzip")]
+    pub fn from_dir_with_archive_fallback<P>(
+        input_dir: P,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        DirectoryLinesStreamerBuilder::new(input_dir)
+            .with_file_selector(|files| {
+                let listed: HashSet<&Path> = files.iter().map(PathBuf::as_path).collect();
+                files
+                    .iter()
+                    .filter(|path| {
+                        path.extension().is_none_or(|extension| extension != "gz")
+                            || !listed.contains(path.with_extension("").as_path())
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .build()
+    }
+
+    /// Like `from_dir()`, streaming only the files named after a date of `[start, end]`.
+    ///
+    /// The whole file name is parsed with `date_pattern`, a `chrono` format string like
+    /// `app-%Y-%m-%d.log`; files it does not parse are not streamed.
+    #[cfg(feature = "chrono")]
+    pub fn from_dir_date_range<P>(
+        input_dir: P,
+        date_pattern: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        let date_pattern = date_pattern.to_string();
+        DirectoryLinesStreamerBuilder::new(input_dir)
+            .with_file_selector(move |files| {
+                files
+                    .iter()
+                    .filter(|path| file_name_date_in_range(path, &date_pattern, start, end))
+                    .cloned()
+                    .collect()
+            })
+            .build()
+    }
+
+    /// Like `from_dir()`, streaming only the files owned by `node_id`, out of `total_nodes`
+    /// nodes sharing the directory.
+    ///
+    /// A file belongs to the node `fnv1a(file_name) % total_nodes`, so that every file is
+    /// streamed by exactly one node, whatever the platform. A node owning no file gets
+    /// `DirectoryLinesStreamerError::EmptyDirectory`.
+    ///
+    /// Returns `DirectoryLinesStreamerError::InvalidArgument` if `total_nodes` is `0`, or if
+    /// `node_id` is not lower than `total_nodes`.
+    pub fn from_dir_with_consistent_hash<P>(
+        input_dir: P,
+        node_id: u64,
+        total_nodes: u64,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        if node_id >= total_nodes {
+            return Err(DirectoryLinesStreamerError::InvalidArgument(format!(
+                "node_id {} must be lower than total_nodes {}",
+                node_id, total_nodes
+            )));
+        }
+        DirectoryLinesStreamerBuilder::new(input_dir)
+            .with_file_selector(move |files| {
+                files
+                    .iter()
+                    .filter(|path| {
+                        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                        fnv1a(file_name.as_bytes()) % total_nodes == node_id
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .build()
+    }
+
+    /// Like `from_dir()`, but first check that every file can be opened.
+    ///
+    /// Returns every file that cannot be opened with its error; other construction errors are
+    /// returned with the path of `input_dir`. Files opened by the check are closed right away.
+    pub fn from_dir_checked<P>(
+        input_dir: P,
+    ) -> Result<DirectoryLinesStreamer, Vec<(PathBuf, io::Error)>>
     where
         P: Into<PathBuf>,
     {
         let dir = input_dir.into();
-        if dir.exists() {
-            let dir_entries = fs::read_dir(&dir)?;
-
-            let mut files: Vec<PathBuf> = dir_entries
-                // Keep only valid entries
-                .filter_map(Result::ok)
-                // Convert to a PathBuf
-                .map(|dir_entry| dir_entry.path())
-                // Collect into a Vec<_>
-                .collect();
-            // Sort the files using the `alphanumeric_sort` crate, which will place `file-2` before `file-11`.
-            alphanumeric_sort::sort_path_slice(&mut files);
-            // We'll `pop()` the last file until we are done, so we want to invert the vec.
-            let mut files: Vec<PathBuf> = files.into_iter().rev().collect();
-            log::debug!("files: {:?}", files);
-
-            // Open the first file
-            if files.is_empty() {
-                Err(DirectoryLinesStreamerError::EmptyDirectory(dir).into())
-            } else {
-                // Safe since we verified to contain at least one file
-                let opened_file_path = files.pop().unwrap();
-
-                log::debug!("Opening first file: {:?}", opened_file_path);
-                let opened_file = BufReader::new(File::open(&opened_file_path)?);
-
-                Ok(DirectoryLinesStreamer {
-                    dir,
-                    files,
-                    opened_file_path,
-                    opened_file,
-                    line_id: 1,
-                })
-            }
-        } else {
-            Err(DirectoryLinesStreamerError::DirectoryDoesNotExists(dir).into())
+        let files =
+            list_files(&dir, &Options::default()).map_err(|error| vec![(dir.clone(), error)])?;
+        let errors: Vec<(PathBuf, io::Error)> = files
+            .into_iter()
+            .filter_map(|file| match fs::File::open(&file) {
+                Ok(_) => None,
+                Err(error) => Some((file, error)),
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Err(errors);
         }
+        DirectoryLinesStreamer::from_dir(dir.clone())
+            .map_err(|error| vec![(dir, io::Error::other(error))])
     }
-}
 
-impl Iterator for DirectoryLinesStreamer {
-    type Item = String;
+    /// Like `from_dir()`, but only check that `input_dir` exists: the directory is scanned
+    /// when the first line is read.
+    ///
+    /// Scanning errors are then reported according to the `ErrorPolicy`, and the streamer
+    /// yields no lines.
+    pub fn from_dir_lazy<P>(
+        input_dir: P,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        let dir = input_dir.into();
+        if !dir.exists() {
+            return Err(DirectoryLinesStreamerError::DirectoryDoesNotExists(dir));
+        }
+        let mut streamer = DirectoryLinesStreamer::from_parts(
+            dir.clone(),
+            Vec::new(),
+            dir,
+            FileReader::new(io::empty()),
+            Options::default(),
+        );
+        streamer.all_files.clear();
+        streamer.scan_done = false;
+        Ok(streamer)
+    }
 
-    fn next(&mut self) -> Option<String> {
-        read_next_line_from_files(
-            &mut self.files,
-            &mut self.opened_file,
-            &mut self.opened_file_path,
-            &mut self.line_id,
+    /// Stream the files of `entries`, already read from `dir` by the caller.
+    ///
+    /// `fs::read_dir()` is not called: `dir` is only used in errors.
+    pub fn from_dir_entries<P>(
+        dir: P,
+        entries: Vec<fs::DirEntry>,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+    {
+        let files = entries.iter().map(fs::DirEntry::path).collect();
+        DirectoryLinesStreamerBuilder::new(dir).build_from_files(files)
+    }
+
+    /// Stream the directory named by the environment variable `var` (for example `LOG_DIR`).
+    pub fn from_env_var(var: &str) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError> {
+        let dir = env::var(var).map_err(|e| match e {
+            env::VarError::NotPresent => DirectoryLinesStreamerError::EnvVarNotSet(var.to_string()),
+            env::VarError::NotUnicode(_) => {
+                DirectoryLinesStreamerError::EnvVarNotUtf8(var.to_string())
+            }
+        })?;
+        DirectoryLinesStreamer::from_dir(dir)
+    }
+
+    /// Stream the lines of standard input, as a single file named `<stdin>`.
+    pub fn from_stdin() -> DirectoryLinesStreamer {
+        DirectoryLinesStreamer::from_input(io::stdin())
+    }
+
+    /// Stream the lines of `input` like those of standard input, see `from_stdin()`.
+    fn from_input<R>(input: R) -> DirectoryLinesStreamer
+    where
+        R: Read + Send + 'static,
+    {
+        let path = PathBuf::from("<stdin>");
+        DirectoryLinesStreamer::from_parts(
+            path.clone(),
+            Vec::new(),
+            path,
+            FileReader::new(input),
+            Options::default(),
         )
     }
-}
 
-fn read_next_line_from_files(
-    files: &mut Vec<PathBuf>,
-    opened_file: &mut BufReader<File>,
-    opened_file_path: &mut PathBuf,
-    line_id: &mut usize,
-) -> Option<String> {
-    loop {
-        let line = read_line_from_file(opened_file, opened_file_path, *line_id);
-        *line_id += 1;
-        if line.is_some() {
-            return line;
-        } else {
-            // EOF reached. Try next file
-            let next_file = files.pop()?;
-            log::debug!("Opening next file: {:?}", next_file);
-            if let Ok(f) = File::open(&next_file)
-                .map_err(|e| log::error!("Error opening file {:?}: {:?}", next_file, e))
-            {
-                *opened_file = BufReader::new(f);
-                *opened_file_path = next_file;
+    /// Stream `lines` from memory, as a single file named `<memory>`.
+    ///
+    /// A newline is appended to lines not ending with one.
+    #[cfg(any(test, feature = "test-helpers"))]
+    pub fn from_lines(lines: Vec<String>) -> DirectoryLinesStreamer {
+        let mut content = Vec::new();
+        for line in lines {
+            let terminated = line.ends_with('\n');
+            content.extend(line.into_bytes());
+            if !terminated {
+                content.push(b'\n');
             }
         }
+        let path = PathBuf::from("<memory>");
+        DirectoryLinesStreamer::from_parts(
+            path.clone(),
+            Vec::new(),
+            path,
+            FileReader::new(io::Cursor::new(content)),
+            Options::default(),
+        )
     }
-}
 
-fn read_line_from_file(
-    f: &mut BufReader<File>,
-    file_path: &PathBuf,
-    line_id: usize,
-) -> Option<String> {
-    let mut buf: Vec<u8> = Vec::new();
-    // Read bytes until a newline character is found (0xA).
-    let nb_bytes_read_result = f.read_until(b'\n', &mut buf);
-    // Convert to UTF-8 to get a string, replacing bad characters
-    // with U+FFFD REPLACEMENT CHARACTER (`�`)
-    let line = String::from_utf8_lossy(&buf).to_string();
+    /// Streamer reading `opened_file` first, then `files` (in reverse order).
+    pub(crate) fn from_parts(
+        dir: PathBuf,
+        files: Vec<PathBuf>,
+        opened_file_path: PathBuf,
+        opened_file: FileReader,
+        options: Options,
+    ) -> DirectoryLinesStreamer {
+        let all_files = std::iter::once(opened_file_path.clone())
+            .chain(files.iter().rev().cloned())
+            .collect();
+        DirectoryLinesStreamer {
+            dir,
+            all_files,
+            files,
+            opened_file_path: Arc::new(opened_file_path),
+            opened_file: BufReader::new(opened_file),
+            line_id: 1,
+            file_position: FilePosition::default(),
+            options,
+            errors: VecDeque::new(),
+            preloaded: None,
+            read_buf: Vec::new(),
+            read_pos: 0,
+            pending_lines: VecDeque::new(),
+            peeked_line: None,
+            line_buf: Vec::new(),
+            last_lines: VecDeque::new(),
+            released_lines: VecDeque::new(),
+            seen_files: HashSet::new(),
+            watch_stopper: WatchStopper::default(),
+            scan_done: true,
+            file_sizes: RefCell::new(HashMap::new()),
+            total_bytes: 0,
+            at_separator: false,
+            last_line_file: None,
+            raw_line_ends_with_newline: false,
+            opened_file_empty: true,
+            audit_file_open: false,
+            opened_file_identity: None,
+            line_ending_stats: LineEndingStats::default(),
+            prereader: None,
+            cached_files: HashMap::new(),
+            #[cfg(all(feature = "inotify", target_os = "linux"))]
+            inotify_watch: None,
+            #[cfg(all(
+                feature = "kqueue",
+                any(
+                    target_os = "macos",
+                    target_os = "freebsd",
+                    target_os = "openbsd",
+                    target_os = "netbsd"
+                )
+            ))]
+            kqueue_watch: None,
+        }
+    }
 
-    match nb_bytes_read_result {
-        Ok(nb_bytes_read) => {
-            if nb_bytes_read == 0 {
-                // EOF reached
-                None
-            } else {
-                Some(line)
-            }
+    /// Start configuring a streamer over `input_dir`, see `DirectoryLinesStreamerBuilder`.
+    pub fn builder<P>(input_dir: P) -> DirectoryLinesStreamerBuilder
+    where
+        P: Into<PathBuf>,
+    {
+        DirectoryLinesStreamerBuilder::new(input_dir)
+    }
+
+    /// Path of the file the last line was read from.
+    pub fn current_file(&self) -> &Path {
+        if self.at_separator {
+            return Path::new(SEPARATOR_FILE);
         }
-        Err(e) => {
-            // I/O errors happened. Report it and continue.
-            log::error!("Error reading line {} of {:?}: {:?}", line_id, file_path, e);
-            Some(line)
+        match &self.options.virtual_concat_filename {
+            Some(name) => name,
+            None => &self.opened_file_path,
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Yield the bytes of each line as read, newline delimiter included, skipping the UTF-8
+    /// conversion: the bytes are not validated, and lines that are not valid UTF-8 are not
+    /// copied to replace their invalid sequences.
+    ///
+    /// The `Utf8Mode` and the line transformations are not applied. The rest of a line
+    /// partly read through `BufRead`, the line of `peek_first_line()` and the lines added by
+    /// `extend()` are yielded first. The callback of `with_line_callback()` is called with each
+    /// line converted to UTF-8, copying invalid lines.
+    pub fn into_bytes_streamer(self) -> DirectoryBytesStreamer {
+        DirectoryBytesStreamer::new(self)
+    }
 
-    #[test]
-    fn streamer_failure() {
-        match DirectoryLinesStreamer::from_dir("fixtures/non-existent-dir")
-            .unwrap_err()
-            .downcast_ref()
-            .unwrap()
-        {
-            DirectoryLinesStreamerError::DirectoryDoesNotExists(dir) => {
-                assert_eq!(dir, &PathBuf::from("fixtures/non-existent-dir"))
-            }
-            _ => panic!("Unexpected error"),
+    /// Yield each line along with `current_file()`.
+    ///
+    /// Lines of the same file share the same path allocation.
+    pub fn lines_with_path(self) -> LinesWithPath {
+        LinesWithPath::new(self)
+    }
+
+    /// Yield each line along with its `LineMetadata`, to tell incomplete last lines apart
+    /// without `DirectoryLinesStreamerBuilder::with_drop_incomplete_last_line()`.
+    pub fn lines_with_metadata(self) -> LinesWithMetadata {
+        LinesWithMetadata::new(self)
+    }
+
+    /// Sorted list of all the files to stream, as listed when the streamer was built
+    /// (files already read included), followed by those found while watching.
+    pub fn files_snapshot(&self) -> Vec<PathBuf> {
+        self.all_files
+            .iter()
+            .map(|path| self.options.strip_path_prefix(path.clone()))
+            .collect()
+    }
+
+    /// Estimate how many bytes are left to read: what remains of the current file, and the
+    /// size of the next files.
+    ///
+    /// Files whose size is unknown count as empty. Sizes are only looked up once per file.
+    pub fn remaining_bytes_estimate(&self) -> u64 {
+        if !self.scan_done {
+            // Nothing is known before scanning
+            return 0;
         }
+        let current_remaining = self
+            .file_size(self.current_physical_path())
+            .unwrap_or(0)
+            .saturating_sub(self.file_position.byte_offset);
+        let next_files: u64 = self
+            .files
+            .iter()
+            .map(|path| self.file_size(path).unwrap_or(0))
+            .sum();
+        current_remaining + next_files
     }
 
-    #[test]
-    fn streamer_empty() {
-        match DirectoryLinesStreamer::from_dir("fixtures/empty-dir")
-            .unwrap_err()
-            .downcast_ref()
-            .unwrap()
-        {
-            DirectoryLinesStreamerError::EmptyDirectory(dir) => {
-                assert_eq!(dir, &PathBuf::from("fixtures/empty-dir"))
-            }
-            _ => panic!("Unexpected error"),
+    /// Total size of the files to stream, computed when the streamer was built; 0 if the size
+    /// of a file is unknown.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Bytes read so far; files that could not be read count as entirely read.
+    ///
+    /// Along with `total_bytes()`, gives the progress of the streamer.
+    pub fn bytes_read(&self) -> u64 {
+        let previous_files: u64 = self
+            .all_files
+            .iter()
+            .take(self.file_position.index)
+            .map(|path| self.file_size(path).unwrap_or(0))
+            .sum();
+        previous_files + self.file_position.byte_offset
+    }
+
+    /// Sum of the sizes of `all_files`, if all of them are known.
+    pub(crate) fn compute_total_bytes(&self) -> Option<u64> {
+        self.all_files.iter().map(|path| self.file_size(path)).sum()
+    }
+
+    /// Path of the current file before `with_path_prefix_strip()` is applied.
+    fn current_physical_path(&self) -> &Path {
+        self.all_files
+            .get(self.file_position.index)
+            .map_or(&self.opened_file_path, |path| path)
+    }
+
+    /// Size of `path`, only looked up once.
+    fn file_size(&self, path: &Path) -> Option<u64> {
+        let mut file_sizes = self.file_sizes.borrow_mut();
+        *file_sizes
+            .entry(path.to_path_buf())
+            .or_insert_with_key(|path| {
+                file_metadata(path, &self.options).map(|metadata| metadata.size)
+            })
+    }
+
+    /// Size and modification time of the file the last line was read from, if known.
+    ///
+    /// See `DirectoryLinesStreamerBuilder::with_metadata_source()`.
+    pub fn current_file_metadata(&self) -> Option<VirtualMetadata> {
+        file_metadata(self.current_physical_path(), &self.options)
+    }
+
+    /// Line number (1-based, across all files) of the last line read; 0 before the first line.
+    ///
+    /// Offsets are returned instead with
+    /// `DirectoryLinesStreamerBuilder::with_line_numbering_mode()`.
+    pub fn line_number(&self) -> usize {
+        match self.options.line_numbering_mode {
+            LineNumberingMode::LineCount => self.line_count(),
+            LineNumberingMode::ByteOffset => self.file_position.line_start as usize,
+            LineNumberingMode::CharOffset => self.file_position.line_start_chars as usize,
         }
     }
 
-    #[test]
-    fn streamer_success() {
-        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
-        let expected_lines = &[
-            "line one from messages\n",
-            "line two from messages\n",
-            "line three from messages\n",
-            "line one from messages.1\n",
-            "line two from messages.1\n",
-            "line three from messages.1\n",
-            "line one from messages.2\n",
-            "line two from messages.2\n",
-            "line three from messages.2\n",
-            "line one from messages.10\n",
-            "line two from messages.10\n",
-            "line three from messages.10\n",
-            "line one from messages.20\n",
-            "line two from messages.20\n",
-            "line three from messages.20\n",
-        ];
-        for (ref line, expected_line) in streamer.zip(expected_lines) {
-            assert_eq!(line, expected_line);
+    /// Number of lines read so far (plus the line number offset).
+    pub(crate) fn line_count(&self) -> usize {
+        self.line_id - 1
+    }
+
+    /// Index (0-based) of the current file in `files_snapshot()`.
+    pub fn current_file_index(&self) -> usize {
+        self.file_position.index
+    }
+
+    /// Identifier given with `DirectoryLinesStreamerBuilder::with_stream_id()`, `0` by default.
+    pub fn stream_id(&self) -> u64 {
+        self.options.stream_id
+    }
+
+    /// Order the files are read in.
+    ///
+    /// Natural order handles both numeric suffixes and timestamp prefixes, so there is nothing
+    /// to detect from the file names yet.
+    pub fn detected_sort_order(&self) -> SortOrder {
+        SortOrder::AlphanumericAscending
+    }
+
+    /// Number of files to stream, see `files_snapshot()`.
+    pub fn total_files(&self) -> usize {
+        self.all_files.len()
+    }
+
+    /// File that will be opened after the current one, if any.
+    ///
+    /// Always `None` with `with_preload(true)`, since all files are already read.
+    pub fn next_file(&self) -> Option<&Path> {
+        let next_file = self.files.last()?;
+        Some(match &self.options.path_prefix_strip {
+            Some(prefix) => next_file.strip_prefix(prefix).unwrap_or(next_file),
+            None => next_file,
+        })
+    }
+
+    /// Read the first line, for example a header; same as `next()`.
+    pub fn first_line(&mut self) -> Option<String> {
+        self.next()
+    }
+
+    /// Return the next line (the first one on a new streamer) without consuming it.
+    ///
+    /// The line is already read from its file: `current_file()` and `line_number()` refer
+    /// to it, and it is passed to the callback of `with_line_callback()`.
+    pub fn peek_first_line(&mut self) -> Option<&str> {
+        if self.peeked_line.is_none() {
+            self.peeked_line = self.next();
         }
+        self.peeked_line.as_deref()
     }
 
-    #[test]
-    fn collect() {
-        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
-        let lines: Vec<String> = streamer.collect();
+    /// Discard the next line `next()` would yield, without passing it to the callback of
+    /// `with_line_callback()` unless it was peeked.
+    ///
+    /// Lines dropped by the `Utf8Mode` or expanded by the transformations are handled as by
+    /// `next()`: the next line kept is discarded, or the first line it expanded to.
+    pub fn skip_first_line(&mut self) {
+        if self.peeked_line.take().is_none() {
+            self.next_transformed_line();
+        }
+    }
 
-        let expected_lines = &[
-            "line one from messages\n",
-            "line two from messages\n",
-            "line three from messages\n",
-            "line one from messages.1\n",
-            "line two from messages.1\n",
-            "line three from messages.1\n",
-            "line one from messages.2\n",
-            "line two from messages.2\n",
-            "line three from messages.2\n",
-            "line one from messages.10\n",
-            "line two from messages.10\n",
-            "line three from messages.10\n",
-            "line one from messages.20\n",
-            "line two from messages.20\n",
-            "line three from messages.20\n",
-        ];
-        for (ref line, expected_line) in lines.iter().zip(expected_lines) {
-            assert_eq!(line, expected_line);
+    /// Line number (1-based) of the last line read inside its file, skipped header lines
+    /// included; 0 before the first line.
+    ///
+    /// Only lines read by this streamer are counted, so it is not exact after resuming from a
+    /// position.
+    pub fn line_number_in_file(&self) -> usize {
+        self.file_position.lines
+    }
+
+    /// Current position, to resume streaming later with
+    /// `DirectoryLinesStreamerBuilder::with_start_position()`.
+    pub fn position(&self) -> StreamPosition {
+        StreamPosition {
+            dir: self.dir.clone(),
+            file_index: self.file_position.index,
+            file: self.opened_file_path.to_path_buf(),
+            byte_offset: self.file_position.byte_offset,
+            line_number: self.line_count(),
+        }
+    }
+
+    /// Save the current position as JSON to `path`, see `restore_from_checkpoint_file()`.
+    ///
+    /// The file is replaced atomically: a failed write keeps the previous checkpoint.
+    #[cfg(feature = "serde_json")]
+    pub fn checkpoint_file<P>(&self, path: P) -> Result<(), io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let checkpoint = position::Checkpoint {
+            version: position::CHECKPOINT_VERSION,
+            position: self.position(),
+        };
+        checkpoint.write(path.as_ref())
+    }
+
+    /// Stream `dir` from the position saved in `checkpoint` by `checkpoint_file()`.
+    #[cfg(feature = "serde_json")]
+    pub fn restore_from_checkpoint_file<P, Q>(
+        dir: P,
+        checkpoint: Q,
+    ) -> Result<DirectoryLinesStreamer, DirectoryLinesStreamerError>
+    where
+        P: Into<PathBuf>,
+        Q: AsRef<Path>,
+    {
+        let content =
+            std::fs::read(checkpoint.as_ref()).map_err(DirectoryLinesStreamerError::Io)?;
+        let checkpoint: position::Checkpoint = serde_json::from_slice(&content).map_err(|e| {
+            DirectoryLinesStreamerError::InvalidCheckpoint(format!(
+                "{:?}: {}",
+                checkpoint.as_ref(),
+                e
+            ))
+        })?;
+        if checkpoint.version != position::CHECKPOINT_VERSION {
+            return Err(DirectoryLinesStreamerError::InvalidCheckpoint(format!(
+                "unsupported version {}",
+                checkpoint.version
+            )));
         }
+        DirectoryLinesStreamerBuilder::new(dir)
+            .with_start_position(checkpoint.position)
+            .build()
+    }
+
+    /// Compare this streamer's lines with `other`'s, see `DiffIterator`.
+    pub fn diff(self, other: DirectoryLinesStreamer) -> DiffIterator {
+        DiffIterator::new(self, other)
+    }
+
+    /// Yield lines only while the file they are read from matches `predicate`.
+    ///
+    /// The predicate is evaluated once per file, files without lines included; iteration
+    /// stops at the first rejected file.
+    pub fn take_while_file<F>(self, predicate: F) -> TakeWhileFile<F>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        TakeWhileFile::new(self, predicate)
+    }
+
+    /// Yield lines up to and including the first line equal to `sentinel`.
+    ///
+    /// Lines are compared without their trailing newline. The lines after `sentinel`, like
+    /// those after a `---DONE---` footer, are never read.
+    pub fn take_until_sentinel(self, sentinel: &str) -> TakeUntilSentinel {
+        TakeUntilSentinel::new(self, sentinel, true)
+    }
+
+    /// Yield lines up to, but excluding, the first line equal to `sentinel`, discarding it
+    /// and the lines after it.
+    pub fn take_until_sentinel_exclusive(self, sentinel: &str) -> TakeUntilSentinel {
+        TakeUntilSentinel::new(self, sentinel, false)
+    }
+
+    /// Group consecutive lines of the same file for which `f` returns the same key.
+    pub fn group_consecutive_by<K, F>(self, f: F) -> GroupConsecutiveBy<K, F>
+    where
+        K: PartialEq,
+        F: Fn(&str) -> K,
+    {
+        GroupConsecutiveBy::new(self, f)
+    }
+
+    /// Yield the lines for which `is_match` returns `true`, with up to `before` lines before
+    /// them and `after` lines after them, like `grep --context`.
+    ///
+    /// Overlapping contexts are yielded once; other lines are dropped.
+    pub fn context_lines<F>(self, before: usize, after: usize, is_match: F) -> ContextLines<F>
+    where
+        F: Fn(&str) -> bool,
+    {
+        ContextLines::new(self, before, after, is_match)
+    }
+
+    /// Split the files in contiguous windows of `n` files, each streamed by its own streamer
+    /// with the same settings; the last window can have fewer files.
+    ///
+    /// Windows whose first file cannot be opened are logged and skipped. Panics if `n` is 0.
+    pub fn window_files(self, n: usize) -> WindowFiles {
+        WindowFiles::new(self, n)
+    }
+
+    /// Stream the same files `passes` times in a row, yielding each line with the index
+    /// (0-based) of its pass.
+    ///
+    /// The first pass continues this streamer; the next ones reopen the files it was built
+    /// with, which are not listed again.
+    pub fn multi_pass(self, passes: usize) -> MultiPassStreamer {
+        MultiPassStreamer::new(self, passes)
+    }
+
+    /// Yield each line with the line before it, `None` for the first line.
+    ///
+    /// The last line of a file is the previous line of the first line of the next file.
+    pub fn zip_with_previous(self) -> ZipWithPrevious {
+        ZipWithPrevious::new(self, false)
+    }
+
+    /// Like `zip_with_previous()`, with `None` as previous line of the first line of each file.
+    pub fn zip_with_previous_reset_on_file_boundary(self) -> ZipWithPrevious {
+        ZipWithPrevious::new(self, true)
+    }
+
+    /// Alternate lines from this streamer and `other`, starting with this one.
+    ///
+    /// Once one of them is exhausted, the remaining lines of the other are yielded.
+    pub fn interleave(self, other: DirectoryLinesStreamer) -> Interleaved {
+        Interleaved::new(self, other)
+    }
+
+    /// Take one line from each of `streamers` in turn, in order.
+    ///
+    /// Exhausted streamers are skipped, until all of them are.
+    pub fn interleave_n(streamers: Vec<DirectoryLinesStreamer>) -> InterleavedN {
+        InterleavedN::new(streamers)
+    }
+
+    /// Like `Iterator::scan()`: yield `f(&mut state, line)` for each line, with `state`
+    /// starting at `initial`, until `f` returns `None`.
+    pub fn scan_lines<S, B, F>(self, initial: S, f: F) -> ScanLines<S, F>
+    where
+        F: FnMut(&mut S, String) -> Option<B>,
+    {
+        ScanLines::new(self, initial, f)
+    }
+
+    /// Like `Iterator::flat_map()`: yield every line returned by `f(line)` for each line.
+    pub fn flat_map_lines<B, I, F>(self, f: F) -> FlatMapLines<F, I>
+    where
+        F: Fn(String) -> I,
+        I: IntoIterator<Item = B>,
+        B: Into<String>,
+    {
+        FlatMapLines::new(self, f)
+    }
+
+    /// Stop once the lines yielded total more than `max_bytes` bytes.
+    ///
+    /// Lines are not truncated: the last line can go over `max_bytes`. No line is yielded if
+    /// `max_bytes` is `0`.
+    pub fn limit_bytes(self, max_bytes: u64) -> ByteLimited {
+        ByteLimited::new(self, max_bytes)
+    }
+
+    /// Yield at most `lines_per_second` lines per second, sleeping between lines as needed.
+    ///
+    /// Returns `DirectoryLinesStreamerError::InvalidArgument` if `lines_per_second` is not
+    /// strictly positive, or so small that the time between two lines cannot be represented.
+    pub fn rate_limit(
+        self,
+        lines_per_second: f64,
+    ) -> Result<RateLimited, DirectoryLinesStreamerError> {
+        RateLimited::new(self, lines_per_second)
+    }
+
+    /// Yield errors alongside lines instead of only logging them.
+    pub fn try_lines(mut self) -> TryLines {
+        self.options.error_policy = ErrorPolicy::Collect;
+        TryLines::new(self)
+    }
+
+    /// Return (and forget) the errors collected so far under `ErrorPolicy::Collect`.
+    pub fn take_errors(&mut self) -> Vec<DirectoryLinesStreamerError> {
+        self.errors.drain(..).collect()
+    }
+
+    /// Read all lines, collecting the errors encountered on the way instead of logging them.
+    pub fn collect_errors(mut self) -> (Vec<String>, Vec<DirectoryLinesStreamerError>) {
+        self.options.error_policy = ErrorPolicy::Collect;
+        let lines: Vec<String> = Iterator::by_ref(&mut self).collect();
+        (lines, self.take_errors())
+    }
+
+    /// Return the first `n` lines (across all files).
+    ///
+    /// Reading stops as soon as `n` lines were read: the current file is closed and the
+    /// remaining ones are never opened.
+    pub fn head(self, n: usize) -> Vec<String> {
+        // `take()` never pulls more than `n` lines; the streamer (and its opened file)
+        // is dropped on return.
+        Iterator::take(self, n).collect()
+    }
+
+    /// Read all the lines in memory, keeping their metadata, see `lines_with_metadata()`.
+    pub fn prefetch_all(self) -> PrefetchedLinesStreamer {
+        PrefetchedLinesStreamer::new(self)
+    }
+
+    /// Draw `k` lines at random, with replacement, for example for bootstrapping.
+    ///
+    /// All the lines are first read in memory. The same `seed` draws the same lines from the
+    /// same input. Returns no lines if there are none to draw from.
+    pub fn sample_with_replacement(self, k: usize, seed: u64) -> Vec<String> {
+        let lines: Vec<String> = self.prefetch_all().collect();
+        if lines.is_empty() {
+            return Vec::new();
+        }
+        let mut rng = SplitMix64::new(seed);
+        (0..k)
+            .map(|_| lines[rng.below(lines.len())].clone())
+            .collect()
+    }
+
+    /// Return the last `n` lines (across all files), keeping at most `n` lines in memory.
+    pub fn tail(self, n: usize) -> Vec<String> {
+        if n == 0 {
+            return Vec::new();
+        }
+        // Grown as needed: `n` can be larger than the number of lines
+        let mut last_lines: VecDeque<String> = VecDeque::with_capacity(n.min(1024));
+        for line in self {
+            if last_lines.len() == n {
+                last_lines.pop_front();
+            }
+            last_lines.push_back(line);
+        }
+        last_lines.into()
+    }
+
+    /// Read the files from `threads` threads (at least one), each reading a contiguous part of
+    /// them, and return all the lines in order, like `collect()` would.
+    ///
+    /// Only the files are shared between threads: use it on a streamer no line was read from.
+    /// Parts whose first file cannot be opened are logged and skipped, see `window_files()`.
+    pub fn concurrent_collect(self, threads: usize) -> Vec<String> {
+        let files_per_thread = self.total_files().div_ceil(threads.max(1)).max(1);
+        let options = self.options.clone();
+        let mut windows = self.window_files(files_per_thread);
+        let mut handles: Vec<std::thread::JoinHandle<Vec<String>>> = Vec::new();
+        while let Some((builder, window)) = windows.next_window() {
+            let options = options.clone();
+            // Opening the first file is part of the work done in parallel
+            handles.push(std::thread::spawn(move || {
+                match builder.build_from_sorted_files(window) {
+                    Ok(streamer) => streamer.collect(),
+                    Err(error) => {
+                        log::debug!("Skipping window of files");
+                        log_error(&options, error);
+                        Vec::new()
+                    }
+                }
+            }));
+        }
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    }
+
+    /// Write every line to `writer`, returning the number of bytes written.
+    pub fn into_sink<W>(self, writer: W) -> io::Result<u64>
+    where
+        W: Write,
+    {
+        self.into_sink_with_separator(writer, &[])
+    }
+
+    /// Like `into_sink()`, writing `separator` between lines.
+    pub fn into_sink_with_separator<W>(self, mut writer: W, separator: &[u8]) -> io::Result<u64>
+    where
+        W: Write,
+    {
+        let mut nb_bytes = 0;
+        for (i, line) in self.enumerate() {
+            if i > 0 {
+                writer.write_all(separator)?;
+                nb_bytes += separator.len() as u64;
+            }
+            writer.write_all(line.as_bytes())?;
+            nb_bytes += line.len() as u64;
+        }
+        writer.flush()?;
+        Ok(nb_bytes)
+    }
+
+    /// Compute the SHA-256 digest of the bytes of each file to stream, read from the start.
+    ///
+    /// The line options, like `with_skip_first_lines()` or `with_record_prefix()`, do not
+    /// change the digests; files with the `.gz` extension are decompressed. Files that could
+    /// not be read are reported according to the `ErrorPolicy`, and are not part of the
+    /// returned map.
+    pub fn checksum_per_file(mut self) -> HashMap<PathBuf, [u8; 32]> {
+        if !self.scan_done {
+            self.scan_lazily();
+        }
+        let mut checksums: HashMap<PathBuf, [u8; 32]> = HashMap::new();
+        for path in mem::take(&mut self.all_files) {
+            let mut hasher = Sha256::new();
+            match open_reader(&path, &self.options)
+                .and_then(|mut reader| io::copy(&mut reader, &mut hasher))
+            {
+                Ok(_) => {
+                    checksums.insert(
+                        self.options.strip_path_prefix(path),
+                        hasher.finalize().into(),
+                    );
+                }
+                Err(error) => {
+                    self.emit_error(DirectoryLinesStreamerError::OpenFile { file: path, error })
+                }
+            }
+        }
+        checksums
+    }
+
+    /// Compare the SHA-256 digest of the files to stream to the `expected` ones, see
+    /// `checksum_per_file()`.
+    ///
+    /// Returns the mismatches, sorted by path. Streamed files missing from `expected` are not
+    /// checked; the errors of the files that could not be read are reported according to the
+    /// `ErrorPolicy`.
+    pub fn compare_checksums(
+        self,
+        expected: HashMap<PathBuf, [u8; 32]>,
+    ) -> Vec<(PathBuf, ChecksumMismatch)> {
+        let streamed: HashSet<PathBuf> = self.files_snapshot().into_iter().collect();
+        let checksums = self.checksum_per_file();
+        let mut mismatches: Vec<(PathBuf, ChecksumMismatch)> = expected
+            .into_iter()
+            .filter_map(|(path, expected)| {
+                let actual = match checksums.get(&path) {
+                    Some(actual) => *actual,
+                    // Files that could not be read are not in `checksums`.
+                    None if streamed.contains(&path) => {
+                        return Some((path, ChecksumMismatch::Unreadable { expected }))
+                    }
+                    None => return Some((path, ChecksumMismatch::NotFound { expected })),
+                };
+                (actual != expected)
+                    .then_some((path, ChecksumMismatch::Differs { actual, expected }))
+            })
+            .collect();
+        mismatches.sort_by(|(a, _), (b, _)| a.cmp(b));
+        mismatches
+    }
+
+    /// Fold the lines of each file separately, starting from `initial` for every file.
+    ///
+    /// Returns each file's path with its final accumulator, in reading order. Files without
+    /// any line are not part of the result. Files split by
+    /// `DirectoryLinesStreamerBuilder::with_file_size_limit()` are folded chunk by chunk, a
+    /// file appearing once per chunk.
+    pub fn fold_files<B, F>(mut self, initial: B, f: F) -> Vec<(PathBuf, B)>
+    where
+        B: Clone,
+        F: Fn(B, String) -> B,
+    {
+        let mut folded: Vec<(PathBuf, B)> = Vec::new();
+        // `initial` is only cloned when a file starts
+        let mut current: Option<(FileChunk, PathBuf, B)> = None;
+        while let Some(line) = self.next_file_line() {
+            let chunk = self.current_chunk();
+            let (path, accumulator) = match current.take() {
+                Some((current_chunk, path, accumulator)) if current_chunk == chunk => {
+                    (path, accumulator)
+                }
+                previous => {
+                    folded.extend(previous.map(|(_, path, accumulator)| (path, accumulator)));
+                    (self.opened_file_path.to_path_buf(), initial.clone())
+                }
+            };
+            current = Some((chunk, path, f(accumulator, line)));
+        }
+        folded.extend(current.map(|(_, path, accumulator)| (path, accumulator)));
+        folded
+    }
+
+    /// Fold the lines until `f` returns `ControlFlow::Break`.
+    ///
+    /// Returns the final accumulator, with the line `f` broke on, or `None` if all the lines
+    /// were folded.
+    pub fn fold_until<B, F>(self, init: B, mut f: F) -> (B, Option<String>)
+    where
+        F: FnMut(B, String) -> ControlFlow<B, B>,
+    {
+        let mut accumulator = init;
+        for line in self {
+            match f(accumulator, line.clone()) {
+                ControlFlow::Continue(next) => accumulator = next,
+                ControlFlow::Break(last) => return (last, Some(line)),
+            }
+        }
+        (accumulator, None)
+    }
+
+    /// Number of lines of each file, in reading order.
+    ///
+    /// Files without any line, like empty files, are counted with `0`. When resuming from a
+    /// position, the files before it are not part of the result. Files split by
+    /// `DirectoryLinesStreamerBuilder::with_file_size_limit()` are counted chunk by chunk, a
+    /// file appearing once per chunk.
+    pub fn lines_per_file(mut self) -> Vec<(PathBuf, usize)> {
+        let first_index = self.file_position.index;
+        let mut counts: Vec<(FileChunk, usize)> = Vec::new();
+        while self.next_file_line().is_some() {
+            let chunk = self.current_chunk();
+            match counts.last_mut() {
+                Some((last_chunk, count)) if *last_chunk == chunk => *count += 1,
+                _ => counts.push((chunk, 1)),
+            }
+        }
+        let mut counts = counts.into_iter().peekable();
+        let mut lines_per_file = Vec::new();
+        for (index, path) in self.files_snapshot().into_iter().enumerate() {
+            if index < first_index {
+                continue;
+            }
+            let nb_chunks = lines_per_file.len();
+            while let Some((_, count)) =
+                counts.next_if(|((chunk_index, _), _)| *chunk_index == index)
+            {
+                lines_per_file.push((path.clone(), count));
+            }
+            if lines_per_file.len() == nb_chunks {
+                lines_per_file.push((path, 0));
+            }
+        }
+        lines_per_file
+    }
+
+    /// Index of the file the last line was read from, with the offset of its chunk, see
+    /// `DirectoryLinesStreamerBuilder::with_file_size_limit()`.
+    fn current_chunk(&self) -> FileChunk {
+        (self.file_position.index, self.file_position.chunk_start)
+    }
+
+    /// Split each line in a pair with `f`, and collect each side separately.
+    pub fn unzip_lines<A, B, F>(self, f: F) -> (Vec<A>, Vec<B>)
+    where
+        F: Fn(String) -> (A, B),
+    {
+        self.map(f).unzip()
+    }
+
+    /// Split each line, without its newline delimiter, at the first `delimiter`, and collect
+    /// the left and right parts separately.
+    ///
+    /// Lines without `delimiter` are entirely collected on the left, with an empty right part.
+    /// Only ASCII delimiters split lines.
+    pub fn unzip_on_delimiter(self, delimiter: u8) -> (Vec<String>, Vec<String>) {
+        let newline = self.options.newline;
+        self.unzip_lines(|line| {
+            let content = strip_newline(&line, newline);
+            match content
+                .as_bytes()
+                .iter()
+                .position(|byte| *byte == delimiter)
+            {
+                // `delimiter` cannot be inside a UTF-8 sequence if it is ASCII
+                Some(index) if delimiter.is_ascii() => (
+                    content[..index].to_string(),
+                    content[index + 1..].to_string(),
+                ),
+                _ => (content.to_string(), String::new()),
+            }
+        })
+    }
+
+    /// Group the lines by the key `f` extracts from them, keeping their order in each group.
+    pub fn collect_as_map<K, F>(self, f: F) -> HashMap<K, Vec<String>>
+    where
+        K: Hash + Eq,
+        F: Fn(&str) -> K,
+    {
+        let mut groups: HashMap<K, Vec<String>> = HashMap::new();
+        for line in self {
+            groups.entry(f(&line)).or_default().push(line);
+        }
+        groups
+    }
+
+    /// Like `collect_as_map()`, with the groups sorted by key.
+    pub fn collect_as_btree_map<K, F>(self, f: F) -> BTreeMap<K, Vec<String>>
+    where
+        K: Ord,
+        F: Fn(&str) -> K,
+    {
+        let mut groups: BTreeMap<K, Vec<String>> = BTreeMap::new();
+        for line in self {
+            groups.entry(f(&line)).or_default().push(line);
+        }
+        groups
+    }
+
+    /// Group the lines by the lowercase extension of the file they were read from, `""` for
+    /// files without extension.
+    pub fn collect_by_extension(self) -> HashMap<String, Vec<String>> {
+        self.collect_by_file(|path| {
+            path.extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_lowercase()
+        })
+    }
+
+    /// Group the lines by the name of the file they were read from.
+    pub fn collect_by_basename(self) -> HashMap<String, Vec<String>> {
+        self.collect_by_file(|path| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into()
+        })
+    }
+
+    /// Group the lines by the key `f` extracts from the path of the file they were read from.
+    fn collect_by_file<F>(mut self, f: F) -> HashMap<String, Vec<String>>
+    where
+        F: Fn(&Path) -> String,
+    {
+        let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+        while let Some(line) = self.next_file_line() {
+            groups
+                .entry(f(&self.opened_file_path))
+                .or_default()
+                .push(line);
+        }
+        groups
+    }
+
+    /// Count lines by length (in bytes, newline included).
+    ///
+    /// `buckets` are sorted upper bounds: a line is counted in the first bucket it is less than
+    /// or equal to. Lines longer than the last bound are not counted; use `usize::MAX` as the
+    /// last bound to count every line.
+    pub fn line_histogram(mut self, buckets: &[usize]) -> Vec<u64> {
+        let mut counts = vec![0; buckets.len()];
+        while let Some(buf) = self.next_raw_line() {
+            if let Some(bucket) = buckets.iter().position(|&bound| buf.len() <= bound) {
+                counts[bucket] += 1;
+            }
+        }
+        counts
+    }
+
+    /// Total length of the lines, in bytes as read from the files (newlines included).
+    ///
+    /// Lines are not converted to UTF-8, so invalid lines are counted too.
+    pub fn sum_line_lengths(mut self) -> u64 {
+        let mut sum = 0;
+        while self.read_raw_line() {
+            sum += self.line_buf.len() as u64;
+        }
+        sum
+    }
+
+    /// Total length of the lines, in bytes once converted to UTF-8 according to the `Utf8Mode`.
+    pub fn sum_line_lengths_utf8(self) -> u64 {
+        self.map(|line| line.len() as u64).sum()
+    }
+
+    /// Set of the distinct lines.
+    pub fn distinct_lines(self) -> HashSet<String> {
+        self.collect()
+    }
+
+    /// Number of distinct lines. Memory usage grows with the number of distinct lines, see
+    /// `count_distinct_approx()` for large datasets.
+    pub fn count_distinct_lines(self) -> usize {
+        self.distinct_lines().len()
+    }
+
+    /// Estimate the number of distinct lines with a HyperLogLog counter, in constant memory.
+    ///
+    /// The estimate is deterministic for a given input. Panics if `error_rate` is not
+    /// between 0 and 1 (exclusive).
+    #[cfg(feature = "hyperloglog")]
+    pub fn count_distinct_approx(self, error_rate: f64) -> u64 {
+        let mut counter = hyperloglog::HyperLogLog::new_deterministic(error_rate, 0);
+        for line in self {
+            counter.insert(&line);
+        }
+        counter.len().round() as u64
+    }
+
+    /// Next line of `DirectoryBytesStreamer`, from the lines already taken out of the files
+    /// first.
+    pub(crate) fn next_line_bytes(&mut self) -> Option<Vec<u8>> {
+        if let Some(line) = self.next_taken_line_bytes() {
+            return Some(line);
+        }
+        let line = self.next_raw_line()?;
+        if let Some(callback) = &self.options.line_callback {
+            callback(
+                &self.opened_file_path,
+                self.line_number(),
+                &String::from_utf8_lossy(&line),
+            );
+        }
+        Some(line)
+    }
+
+    /// Bytes of the next line already taken out of the files: the rest of a line partly read
+    /// through `BufRead`, the line of `peek_first_line()`, or a pending line.
+    pub(crate) fn next_taken_line_bytes(&mut self) -> Option<Vec<u8>> {
+        if self.read_pos < self.read_buf.len() {
+            let rest = self.read_buf.split_off(self.read_pos);
+            self.read_buf.clear();
+            self.read_pos = 0;
+            return Some(rest);
+        }
+        // Already finished
+        if let Some(line) = self.peeked_line.take() {
+            return Some(line.into_bytes());
+        }
+        let line = self.pending_lines.pop_front()?;
+        Some(self.finish_line(line).into_bytes())
+    }
+
+    /// Read the next line's bytes, before any UTF-8 conversion.
+    fn next_raw_line(&mut self) -> Option<Vec<u8>> {
+        if self.read_raw_line() {
+            Some(mem::take(&mut self.line_buf))
+        } else {
+            None
+        }
+    }
+
+    /// Read the next line's bytes in `line_buf`, returning `false` once all files are read.
+    fn read_raw_line(&mut self) -> bool {
+        let read = if self.options.take_last_lines.is_some() || self.options.reverse_line_order {
+            self.read_buffered_raw_line()
+        } else {
+            self.read_source_line()
+        };
+        self.raw_line_ends_with_newline =
+            read && self.line_buf.last() == Some(&self.options.newline);
+        read
+    }
+
+    /// Like `read_raw_line()`, but buffering the lines of each file until it is fully read:
+    /// to keep only the last ones, or to reverse them.
+    fn read_buffered_raw_line(&mut self) -> bool {
+        let n = self.options.take_last_lines.unwrap_or(usize::MAX);
+        loop {
+            if let Some(buf) = self.released_lines.pop_front() {
+                self.line_buf = buf;
+                return true;
+            }
+            let file_index = self.file_position.index;
+            if !self.read_source_line() {
+                if self.last_lines.is_empty() {
+                    return false;
+                }
+                // Release the last lines of the last file
+                self.release_last_lines();
+                continue;
+            }
+            if self.file_position.index != file_index {
+                // The previous file is finished: release its last lines
+                self.release_last_lines();
+            }
+            self.last_lines.push_back(mem::take(&mut self.line_buf));
+            if self.last_lines.len() > n {
+                self.last_lines.pop_front();
+            }
+        }
+    }
+
+    fn release_last_lines(&mut self) {
+        mem::swap(&mut self.released_lines, &mut self.last_lines);
+        if self.options.reverse_line_order {
+            self.released_lines.make_contiguous().reverse();
+        }
+    }
+
+    /// Read the next line of the files (or of the preloaded lines) in `line_buf`.
+    fn read_source_line(&mut self) -> bool {
+        let read = self.read_source_line_uncounted();
+        if read && self.options.line_ending_statistics {
+            self.line_ending_stats.record(&self.line_buf);
+        }
+        read
+    }
+
+    /// `read_source_line()`, without counting line endings.
+    fn read_source_line_uncounted(&mut self) -> bool {
+        if !self.scan_done {
+            self.scan_lazily();
+        }
+        let count_chars = self.counts_chars();
+        match &mut self.preloaded {
+            Some(preloaded) => match next_preloaded_line(
+                preloaded,
+                &mut self.opened_file_path,
+                &mut self.line_id,
+                &mut self.file_position,
+                count_chars,
+            ) {
+                Some(buf) => {
+                    self.line_buf = buf;
+                    true
+                }
+                None => false,
+            },
+            None => loop {
+                if self.read_next_line_from_files() {
+                    return true;
+                }
+                if !self.watch_for_new_files() {
+                    return false;
+                }
+            },
+        }
+    }
+
+    /// Read the next line of the files in `line_buf`, opening the next files as needed.
+    fn read_next_line_from_files(&mut self) -> bool {
+        loop {
+            if self.rename_check_due() {
+                self.detect_file_rename();
+            }
+            if read_line_from_file(
+                &mut self.opened_file,
+                &self.opened_file_path,
+                self.line_id,
+                &self.options,
+                &mut self.errors,
+                &mut self.line_buf,
+            ) {
+                self.opened_file_empty = false;
+                if self.options.drop_incomplete_last_line
+                    && self.line_buf.last() != Some(&self.options.newline)
+                {
+                    // Only the last line of a file can lack the delimiter
+                    // The position stays before it, to read it once complete when resuming.
+                    log::debug!(
+                        "Dropping incomplete last line of {:?}",
+                        self.opened_file_path
+                    );
+                    continue;
+                }
+                self.line_id += 1;
+                self.file_position
+                    .advance(&self.line_buf, self.counts_chars());
+                if let Some(limit) = self.options.file_size_limit {
+                    self.split_file(limit);
+                }
+                // Skipped header lines are counted, but never converted to `String`.
+                if self.file_position.lines > self.options.skip_first_lines
+                    && self.strip_record_prefix()
+                {
+                    return true;
+                }
+            } else {
+                if let Some(placeholder) = &self.options.empty_file_placeholder {
+                    if self.opened_file_empty && self.file_position.byte_offset == 0 {
+                        self.opened_file_empty = false;
+                        self.line_buf.clear();
+                        self.line_buf.extend_from_slice(placeholder.as_bytes());
+                        self.line_buf.push(self.options.newline);
+                        self.line_id += 1;
+                        self.file_position.lines += 1;
+                        return true;
+                    }
+                }
+                // EOF reached. A new file may have replaced a renamed one, else try next file
+                if self.detect_file_rename() {
+                    continue;
+                }
+                self.audit_close();
+                let next_file = match self.files.pop() {
+                    Some(next_file) => next_file,
+                    None => return false,
+                };
+                self.file_position = FilePosition::start_of(self.file_position.index + 1);
+                if let Some(callback) = &self.options.file_transition_callback {
+                    callback(Some(&self.opened_file_path), &next_file);
+                }
+                log::debug!("Opening next file: {:?}", next_file);
+                let opened = match &mut self.prereader {
+                    Some(prereader) => prereader.take(&next_file),
+                    None => self.cached_files.remove(&next_file).map(Ok),
+                };
+                match opened.unwrap_or_else(|| open_reader_with_retries(&next_file, &self.options))
+                {
+                    Ok(f) => {
+                        self.opened_file = BufReader::new(f);
+                        self.opened_file_empty = true;
+                        self.opened_file_path = Arc::new(self.options.strip_path_prefix(next_file));
+                        self.audit_open();
+                        self.record_opened_file_identity();
+                    }
+                    Err(error) if is_open_timeout(&error, &self.options) => {
+                        self.emit_error(DirectoryLinesStreamerError::OpenTimeout(next_file))
+                    }
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => self.emit_error(
+                        DirectoryLinesStreamerError::FileDeletedBeforeRead(next_file),
+                    ),
+                    Err(error) => self.emit_error(DirectoryLinesStreamerError::OpenFile {
+                        file: next_file,
+                        error,
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Open the next files in advance, see `with_cached_file_handles()`.
+    pub(crate) fn open_cached_files(&mut self) {
+        let nb_files = self.options.max_open_files.saturating_sub(1);
+        for file in self.files.iter().rev().take(nb_files) {
+            match open_reader_with_retries(file, &self.options) {
+                Ok(reader) => {
+                    self.cached_files.insert(file.clone(), reader);
+                }
+                Err(error) => log::debug!("Failed to open {:?} in advance: {}", file, error),
+            }
+        }
+    }
+
+    /// Scan the directory of a streamer built by `from_dir_lazy()`.
+    ///
+    /// Only the files and the first opened file are taken from the scan: the options, the
+    /// errors and the watch stopper of the streamer are kept.
+    fn scan_lazily(&mut self) {
+        self.scan_done = true;
+        let scanned = list_files(&self.dir, &self.options)
+            .map_err(DirectoryLinesStreamerError::Io)
+            .and_then(|files| {
+                DirectoryLinesStreamerBuilder::from_options(self.dir.clone(), self.options.clone())
+                    .build_from_sorted_files(files)
+            });
+        let scanned = match scanned {
+            Ok(scanned) => scanned,
+            Err(error) => return self.emit_error(error),
+        };
+        self.files = scanned.files;
+        self.all_files = scanned.all_files;
+        self.seen_files = scanned.seen_files;
+        self.opened_file_path = scanned.opened_file_path;
+        self.opened_file = scanned.opened_file;
+        self.opened_file_identity = scanned.opened_file_identity;
+        self.audit_file_open = scanned.audit_file_open;
+        self.line_id = scanned.line_id;
+        self.file_position = scanned.file_position;
+        self.total_bytes = scanned.total_bytes;
+        self.preloaded = scanned.preloaded;
+        self.prereader = scanned.prereader;
+        self.cached_files = scanned.cached_files;
+        // Files that timed out while opening the first one
+        self.errors.extend(scanned.errors);
+    }
+
+    /// Start a new chunk of the current file with the line just read if the current chunk
+    /// already reached `limit` bytes.
+    fn split_file(&mut self, limit: u64) {
+        let file_position = &mut self.file_position;
+        if file_position.line_start - file_position.chunk_start >= limit {
+            file_position.chunk_start = file_position.line_start;
+            log::debug!(
+                "Splitting {:?} at offset {}",
+                self.opened_file_path,
+                file_position.line_start
+            );
+            if let Some(callback) = &self.options.file_transition_callback {
+                callback(Some(&self.opened_file_path), &self.opened_file_path);
+            }
+        }
+    }
+
+    /// Remove the record prefix from `line_buf`, returning `false` if the line is filtered out.
+    fn strip_record_prefix(&mut self) -> bool {
+        let prefix = match &self.options.record_prefix {
+            Some(prefix) => prefix.as_bytes(),
+            None => return true,
+        };
+        if self.line_buf.starts_with(prefix) {
+            self.line_buf.drain(..prefix.len());
+            true
+        } else {
+            !self.options.record_prefix_filter
+        }
+    }
+
+    fn counts_chars(&self) -> bool {
+        self.options.line_numbering_mode == LineNumberingMode::CharOffset
+    }
+
+    fn emit_error(&mut self, error: DirectoryLinesStreamerError) {
+        emit_error(&self.options, &mut self.errors, error);
+    }
+
+    /// Read every line of every file in memory; `next()` will then only pop them.
+    fn preload(&mut self) {
+        let line_id = self.line_id;
+        let mut preloaded: VecDeque<PreloadedFile> = VecDeque::new();
+        while self.read_source_line() {
+            let buf = mem::take(&mut self.line_buf);
+            match preloaded.back_mut() {
+                Some(file) if file.index == self.file_position.index => file.lines.push_back(buf),
+                _ => preloaded.push_back(PreloadedFile {
+                    path: self.opened_file_path.clone(),
+                    index: self.file_position.index,
+                    lines: VecDeque::from(vec![buf]),
+                }),
+            }
+        }
+        // Present the preloaded lines as if they were read for the first time.
+        if let Some(file) = preloaded.front() {
+            self.opened_file_path = file.path.clone();
+            self.file_position = FilePosition::start_of(file.index);
+        }
+        self.line_id = line_id;
+        self.preloaded = Some(preloaded);
+    }
+}
+
+#[cfg(all(feature = "std", any(test, feature = "test-helpers")))]
+impl From<Vec<String>> for DirectoryLinesStreamer {
+    fn from(lines: Vec<String>) -> DirectoryLinesStreamer {
+        DirectoryLinesStreamer::from_lines(lines)
+    }
+}
+
+/// Inject lines, yielded by `next()` before any other line is read.
+#[cfg(feature = "std")]
+impl Extend<String> for DirectoryLinesStreamer {
+    fn extend<I>(&mut self, lines: I)
+    where
+        I: IntoIterator<Item = String>,
+    {
+        self.pending_lines.extend(lines);
+    }
+}
+
+#[cfg(feature = "std")]
+impl Iterator for DirectoryLinesStreamer {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if let Some(line) = self.peeked_line.take() {
+            return Some(line);
+        }
+        let line = self.next_transformed_line()?;
+        if let Some(separator) = self.separator_due() {
+            // Yielded next, like a line the transformations expanded to
+            self.pending_lines.push_front(line);
+            self.at_separator = true;
+            return Some(self.terminate_line(separator));
+        }
+        self.at_separator = false;
+        Some(self.finish_line(line))
+    }
+}
+
+#[cfg(feature = "std")]
+impl DirectoryLinesStreamer {
+    /// Next line of the files, without the separators of `with_interfile_separator()`.
+    fn next_file_line(&mut self) -> Option<String> {
+        let line = self.next_transformed_line()?;
+        Some(self.finish_line(line))
+    }
+
+    /// Next line of the files once transformed, from `pending_lines` first.
+    fn next_transformed_line(&mut self) -> Option<String> {
+        loop {
+            if let Some(line) = self.pending_lines.pop_front() {
+                return Some(line);
+            }
+            let line = self.next_line()?;
+            if let Some(line) = self.transform_line(line) {
+                return Some(line);
+            }
+        }
+    }
+
+    /// The separator to yield before a line of the current file, if the last line yielded
+    /// was read from another file, see `with_interfile_separator()`.
+    fn separator_due(&mut self) -> Option<String> {
+        let separator = self.options.interfile_separator.as_ref()?;
+        let index = self.file_position.index;
+        let previous = self.last_line_file.replace(index)?;
+        if previous == index {
+            return None;
+        }
+        let mut separator = separator.clone().into_bytes();
+        separator.push(self.options.newline);
+        Some(String::from_utf8_lossy(&separator).into_owned())
+    }
+
+    /// `line` as yielded, see `with_nul_terminated_lines()`.
+    fn terminate_line(&self, line: String) -> String {
+        if self.options.nul_terminated_lines {
+            nul_terminate(line, self.options.newline)
+        } else {
+            line
+        }
+    }
+
+    /// Terminate a transformed `line` and pass it to the line callback.
+    fn finish_line(&self, line: String) -> String {
+        let line = self.terminate_line(line);
+        if let Some(callback) = &self.options.line_callback {
+            callback(&self.opened_file_path, self.line_number(), &line);
+        }
+        line
+    }
+
+    /// Read the next line, converted to UTF-8 according to the `Utf8Mode`.
+    fn next_line(&mut self) -> Option<String> {
+        loop {
+            let decoded = if self.options.line_buffer_reuse {
+                if !self.read_raw_line() {
+                    return None;
+                }
+                decode_line(Cow::Borrowed(&self.line_buf), self.options.utf8_mode)
+            } else {
+                decode_line(Cow::Owned(self.next_raw_line()?), self.options.utf8_mode)
+            };
+            match decoded {
+                Ok(Some(line)) => return Some(line),
+                // Skipped line
+                Ok(None) => {}
+                Err(error) => self.emit_error(DirectoryLinesStreamerError::InvalidUtf8 {
+                    file: self.opened_file_path.to_path_buf(),
+                    line_id: self.line_count(),
+                    error,
+                }),
+            }
+        }
+    }
+
+    /// Apply the configured transformations to `line`. A line can be expanded into
+    /// `pending_lines`, or dropped by returning `None`.
+    fn transform_line(&mut self, line: String) -> Option<String> {
+        #[cfg(feature = "unicode-normalization")]
+        let line = match self.options.utf8_normalization {
+            Some(form) => normalization::normalize(line, form),
+            None => line,
+        };
+        #[cfg(feature = "serde_json")]
+        {
+            if self.options.flatten_json_arrays {
+                return self.flatten_json_array(line);
+            }
+        }
+        Some(line)
+    }
+}
+
+/// Bytes of the successive lines, as returned by the `Iterator` implementation.
+///
+/// `Read` and `Iterator` both provide `by_ref()`, `take()` and `chain()`: when `Read` is in
+/// scope, call them as `Iterator::take(streamer, n)`.
+#[cfg(feature = "std")]
+impl Read for DirectoryLinesStreamer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let nb_bytes = available.len().min(buf.len());
+        buf[..nb_bytes].copy_from_slice(&available[..nb_bytes]);
+        self.consume(nb_bytes);
+        Ok(nb_bytes)
+    }
+}
+
+#[cfg(feature = "std")]
+impl BufRead for DirectoryLinesStreamer {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Lines converted to `""` are skipped: an empty slice means the end of the lines.
+        while self.read_pos >= self.read_buf.len() {
+            match self.next() {
+                Some(line) => {
+                    self.read_buf = line.into_bytes();
+                    self.read_pos = 0;
+                }
+                None => return Ok(&[]),
+            }
+        }
+        Ok(&self.read_buf[self.read_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.read_pos = (self.read_pos + amt).min(self.read_buf.len());
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<DirectoryLinesStreamer> for Box<dyn Iterator<Item = String>> {
+    fn from(streamer: DirectoryLinesStreamer) -> Box<dyn Iterator<Item = String>> {
+        Box::new(streamer)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<DirectoryLinesStreamer> for Box<dyn Iterator<Item = String> + Send> {
+    fn from(streamer: DirectoryLinesStreamer) -> Box<dyn Iterator<Item = String> + Send> {
+        Box::new(streamer)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<DirectoryLinesStreamer>
+    for Box<dyn Iterator<Item = Result<String, DirectoryLinesStreamerError>>>
+{
+    fn from(
+        streamer: DirectoryLinesStreamer,
+    ) -> Box<dyn Iterator<Item = Result<String, DirectoryLinesStreamerError>>> {
+        Box::new(streamer.try_lines())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<DirectoryLinesStreamer>
+    for Box<dyn Iterator<Item = Result<String, DirectoryLinesStreamerError>> + Send>
+{
+    fn from(
+        streamer: DirectoryLinesStreamer,
+    ) -> Box<dyn Iterator<Item = Result<String, DirectoryLinesStreamerError>> + Send> {
+        Box::new(streamer.try_lines())
+    }
+}
+
+#[cfg(feature = "std")]
+fn next_preloaded_line(
+    preloaded: &mut VecDeque<PreloadedFile>,
+    opened_file_path: &mut Arc<PathBuf>,
+    line_id: &mut usize,
+    file_position: &mut FilePosition,
+    count_chars: bool,
+) -> Option<Vec<u8>> {
+    loop {
+        let file = preloaded.front_mut()?;
+        if let Some(buf) = file.lines.pop_front() {
+            if file.index != file_position.index {
+                *opened_file_path = file.path.clone();
+                *file_position = FilePosition::start_of(file.index);
+            }
+            *line_id += 1;
+            file_position.advance(&buf, count_chars);
+            return Some(buf);
+        }
+        preloaded.pop_front();
+    }
+}
+
+#[cfg(feature = "std")]
+fn read_line_from_file(
+    f: &mut BufReader<FileReader>,
+    file_path: &Path,
+    line_id: usize,
+    options: &Options,
+    errors: &mut VecDeque<DirectoryLinesStreamerError>,
+    buf: &mut Vec<u8>,
+) -> bool {
+    buf.clear();
+    // Read bytes until a newline character is found (0xA by default).
+    let nb_bytes_read_result = f.read_until(options.newline, buf);
+
+    match nb_bytes_read_result {
+        // EOF reached when nothing was read
+        Ok(nb_bytes_read) => nb_bytes_read != 0,
+        Err(error) => {
+            // I/O errors happened. Report it and continue with what was read, if anything;
+            // otherwise give up on this file.
+            let error = match (options.per_line_timeout, options.read_timeout) {
+                (Some(timeout), _) if is_line_timeout(&error) => {
+                    DirectoryLinesStreamerError::LineReadTimeout(
+                        file_path.to_path_buf(),
+                        line_id,
+                        timeout,
+                    )
+                }
+                (_, Some(timeout)) if error.kind() == io::ErrorKind::TimedOut => {
+                    DirectoryLinesStreamerError::ReadTimeout(file_path.to_path_buf(), timeout)
+                }
+                _ => DirectoryLinesStreamerError::ReadLine {
+                    file: file_path.to_path_buf(),
+                    line_id,
+                    error,
+                },
+            };
+            emit_error(options, errors, error);
+            !buf.is_empty()
+        }
+    }
+}
+
+/// Version of each rotated file kept by `select_versions()`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+enum VersionSelector {
+    /// Smallest rotation number
+    Latest,
+    /// Largest rotation number
+    Oldest,
+}
+
+#[cfg(feature = "std")]
+impl VersionSelector {
+    /// Whether `version` should replace the `selected` one.
+    fn prefers(self, version: u64, selected: u64) -> bool {
+        match self {
+            VersionSelector::Latest => version < selected,
+            VersionSelector::Oldest => version > selected,
+        }
+    }
+}
+
+/// One version of each rotated file of `files`, chosen by `selector`, in the order of `files`.
+#[cfg(feature = "std")]
+fn select_versions(files: &[PathBuf], selector: VersionSelector) -> Vec<PathBuf> {
+    // Selected version of each base name, by directory
+    let mut selected: HashMap<(Option<&Path>, String), (u64, &Path)> = HashMap::new();
+    for path in files {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let (base_name, version) = split_version_suffix(&file_name);
+        selected
+            .entry((path.parent(), base_name.to_string()))
+            .and_modify(|(selected_version, selected_path)| {
+                if selector.prefers(version, *selected_version) {
+                    *selected_version = version;
+                    *selected_path = path;
+                }
+            })
+            .or_insert((version, path));
+    }
+    let kept: HashSet<&Path> = selected.into_values().map(|(_, path)| path).collect();
+    files
+        .iter()
+        .filter(|path| kept.contains(path.as_path()))
+        .cloned()
+        .collect()
+}
+
+/// 64-bit FNV-1a hash of `bytes`.
+#[cfg(feature = "std")]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Split `file_name` in its base name and its rotation number: `("app.log", 2)` for
+/// `app.log.2.gz`, `("app.log", 0)` for `app.log`.
+#[cfg(feature = "std")]
+fn split_version_suffix(file_name: &str) -> (&str, u64) {
+    let name = file_name.strip_suffix(".gz").unwrap_or(file_name);
+    match name.rsplit_once('.') {
+        Some((base_name, suffix))
+            if !suffix.is_empty() && suffix.bytes().all(|c| c.is_ascii_digit()) =>
+        {
+            (base_name, suffix.parse().unwrap_or(u64::MAX))
+        }
+        _ => (file_name, 0),
+    }
+}
+
+/// Convert a line according to `utf8_mode`, `Ok(None)` meaning the line is skipped.
+#[cfg(feature = "std")]
+fn decode_line(buf: Cow<'_, [u8]>, utf8_mode: Utf8Mode) -> Result<Option<String>, FromUtf8Error> {
+    match utf8_mode {
+        // Convert to UTF-8 to get a string, replacing bad characters
+        // with U+FFFD REPLACEMENT CHARACTER (`�`)
+        Utf8Mode::Lossy => Ok(Some(String::from_utf8_lossy(&buf).into_owned())),
+        Utf8Mode::Strict => String::from_utf8(buf.into_owned()).map(Some),
+        Utf8Mode::Skip => Ok(String::from_utf8(buf.into_owned()).ok()),
+    }
+}
+
+#[cfg(feature = "std")]
+fn emit_error(
+    options: &Options,
+    errors: &mut VecDeque<DirectoryLinesStreamerError>,
+    error: DirectoryLinesStreamerError,
+) {
+    match options.error_policy {
+        ErrorPolicy::Log => log_error(options, error),
+        ErrorPolicy::Collect => {
+            if let Some(on_error) = &options.on_error {
+                on_error(&error);
+            }
+            errors.push_back(error)
+        }
+    }
+}
+
+/// Report `error` like `ErrorPolicy::Log` does, where it cannot be collected: for example
+/// when there is no streamer to collect it.
+#[cfg(feature = "std")]
+fn log_error(options: &Options, error: DirectoryLinesStreamerError) {
+    if let Some(on_error) = &options.on_error {
+        on_error(&error);
+    }
+    match error {
+        // Files disappearing while streaming is expected, for example after a log rotation.
+        DirectoryLinesStreamerError::FileDeletedBeforeRead(_) => log::warn!("{}", error),
+        _ if options.eager_error_reporting => panic!("{}", error),
+        _ => log::error!("{}", error),
+    }
+}
+
+/// Replace the trailing record delimiter of `line`, if any, by a NUL byte.
+#[cfg(feature = "std")]
+fn nul_terminate(mut line: String, newline: u8) -> String {
+    line.truncate(strip_newline(&line, newline).len());
+    line.push('\0');
+    line
+}
+
+/// Remove the trailing record delimiter from `line`, if any.
+#[cfg(feature = "std")]
+fn strip_newline(line: &str, newline: u8) -> &str {
+    if newline.is_ascii() {
+        line.strip_suffix(char::from(newline)).unwrap_or(line)
+    } else {
+        line
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use std::{
+        cell::Cell,
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    /// Temporary directory with the same content as `fixtures/non-empty-dir`.
+    fn messages_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "messages",
+            "messages.1",
+            "messages.2",
+            "messages.10",
+            "messages.20",
+        ] {
+            let content = format!(
+                "line one from {name}\nline two from {name}\nline three from {name}\n",
+                name = name
+            );
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        dir
+    }
+
+    /// Write `content` to `path`, compressed if its extension is `.gz` and the `gzip` feature
+    /// is enabled, so that it is read back as `content` either way.
+    fn write_log_file(path: &Path, content: &[u8]) {
+        #[cfg(feature = "gzip")]
+        if path.extension().is_some_and(|extension| extension == "gz") {
+            use std::io::Write;
+
+            let mut encoder = flate2::write::GzEncoder::new(
+                fs::File::create(path).unwrap(),
+                flate2::Compression::default(),
+            );
+            encoder.write_all(content).unwrap();
+            encoder.finish().unwrap();
+            return;
+        }
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn streamer_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing_dir = dir.path().join("non-existent-dir");
+        match DirectoryLinesStreamer::from_dir(&missing_dir).unwrap_err() {
+            DirectoryLinesStreamerError::DirectoryDoesNotExists(dir) => {
+                assert_eq!(dir, missing_dir)
+            }
+            _ => panic!("Unexpected error"),
+        }
+    }
+
+    #[test]
+    fn streamer_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        match DirectoryLinesStreamer::from_dir(dir.path()).unwrap_err() {
+            DirectoryLinesStreamerError::EmptyDirectory(empty_dir) => {
+                assert_eq!(empty_dir, dir.path())
+            }
+            _ => panic!("Unexpected error"),
+        }
+    }
+
+    #[test]
+    fn streamer_success() {
+        let dir = messages_dir();
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        let expected_lines = &[
+            "line one from messages\n",
+            "line two from messages\n",
+            "line three from messages\n",
+            "line one from messages.1\n",
+            "line two from messages.1\n",
+            "line three from messages.1\n",
+            "line one from messages.2\n",
+            "line two from messages.2\n",
+            "line three from messages.2\n",
+            "line one from messages.10\n",
+            "line two from messages.10\n",
+            "line three from messages.10\n",
+            "line one from messages.20\n",
+            "line two from messages.20\n",
+            "line three from messages.20\n",
+        ];
+        for (ref line, expected_line) in streamer.zip(expected_lines) {
+            assert_eq!(line, expected_line);
+        }
+    }
+
+    #[test]
+    fn streamer_from_dir_entries() {
+        let entries: Vec<fs::DirEntry> = fs::read_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let streamer =
+            DirectoryLinesStreamer::from_dir_entries("fixtures/non-empty-dir", entries).unwrap();
+        let expected_lines = &[
+            "line one from messages\n",
+            "line two from messages\n",
+            "line three from messages\n",
+            "line one from messages.1\n",
+            "line two from messages.1\n",
+            "line three from messages.1\n",
+            "line one from messages.2\n",
+            "line two from messages.2\n",
+            "line three from messages.2\n",
+            "line one from messages.10\n",
+            "line two from messages.10\n",
+            "line three from messages.10\n",
+            "line one from messages.20\n",
+            "line two from messages.20\n",
+            "line three from messages.20\n",
+        ];
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(lines, expected_lines);
+    }
+
+    #[test]
+    fn for_loops() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        for line in &mut streamer {
+            if line == "line three from messages\n" {
+                break;
+            }
+        }
+        assert_eq!(streamer.line_number(), 3);
+
+        let mut lines = Vec::new();
+        for line in streamer {
+            lines.push(line);
+            if lines.len() == 2 {
+                break;
+            }
+        }
+        assert_eq!(
+            lines,
+            &["line one from messages.1\n", "line two from messages.1\n"]
+        );
+    }
+
+    #[test]
+    fn from_lines() {
+        let streamer =
+            DirectoryLinesStreamer::from(vec!["line one\n".to_string(), "line two".to_string()]);
+        assert_eq!(streamer.current_file(), Path::new("<memory>"));
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(lines, &["line one\n", "line two\n"]);
+
+        assert_eq!(DirectoryLinesStreamer::from_lines(Vec::new()).next(), None);
+    }
+
+    #[test]
+    fn from_dir_lazy() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut streamer = DirectoryLinesStreamer::from_dir_lazy(dir.path()).unwrap();
+        // Created after the streamer, before the first line is read
+        fs::write(dir.path().join("messages"), "line one from messages\n").unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+        assert_eq!(streamer.current_file(), dir.path().join("messages"));
+        assert_eq!(streamer.next(), None);
+
+        // Still empty when the first line is read
+        let dir = tempfile::tempdir().unwrap();
+        let mut streamer = DirectoryLinesStreamer::from_dir_lazy(dir.path()).unwrap();
+        assert_eq!(streamer.next(), None);
+
+        // Settings made before the first line are kept
+        fs::write(dir.path().join("messages"), "line one from messages\n").unwrap();
+        fs::write(dir.path().join("messages.1"), "line one from messages.1\n").unwrap();
+        let mut lines = DirectoryLinesStreamer::from_dir_lazy(dir.path())
+            .unwrap()
+            .try_lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "line one from messages\n");
+        fs::remove_file(dir.path().join("messages.1")).unwrap();
+        match lines.next() {
+            Some(Err(DirectoryLinesStreamerError::FileDeletedBeforeRead(file))) => {
+                assert_eq!(file, dir.path().join("messages.1"))
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert!(lines.next().is_none());
+
+        match DirectoryLinesStreamer::from_dir_lazy("not/a/dir").unwrap_err() {
+            DirectoryLinesStreamerError::DirectoryDoesNotExists(_) => {}
+            error => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn from_dir_with_snapshot() {
+        let dir = messages_dir();
+        let (streamer, snapshot) =
+            DirectoryLinesStreamer::from_dir_with_snapshot(dir.path()).unwrap();
+        assert_eq!(snapshot, streamer.files_snapshot());
+        assert_eq!(snapshot.len(), 5);
+
+        // Changes after construction do not alter the snapshot
+        fs::write(dir.path().join("messages.30"), "new\n").unwrap();
+        fs::remove_file(dir.path().join("messages.10")).unwrap();
+        assert_eq!(streamer.files_snapshot(), snapshot);
+        let (lines, errors) = streamer.collect_errors();
+        assert_eq!(lines.len(), 12);
+        match errors.as_slice() {
+            [DirectoryLinesStreamerError::FileDeletedBeforeRead(path)] => {
+                assert_eq!(*path, dir.path().join("messages.10"))
+            }
+            errors => panic!("Unexpected errors: {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn from_dir_with_priority() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app.log"), "app\n").unwrap();
+        fs::write(dir.path().join("error.log"), "error\n").unwrap();
+        fs::write(dir.path().join("other.log"), "other\n").unwrap();
+        let lines: Vec<String> =
+            DirectoryLinesStreamer::from_dir_with_priority(dir.path(), |path| {
+                (path.file_name().unwrap() == "error.log") as i64
+            })
+            .unwrap()
+            .collect();
+        assert_eq!(lines, &["error\n", "app\n", "other\n"]);
+    }
+
+    #[test]
+    fn from_dir_with_max_total_bytes() {
+        let dir = Path::new("fixtures/non-empty-dir");
+        let two_files = fs::metadata(dir.join("messages")).unwrap().len()
+            + fs::metadata(dir.join("messages.1")).unwrap().len();
+        let streamer =
+            DirectoryLinesStreamer::from_dir_with_max_total_bytes(dir, two_files - 1).unwrap();
+        assert_eq!(streamer.total_files(), 2);
+        assert_eq!(streamer.total_bytes(), two_files);
+        assert_eq!(streamer.count(), 6);
+        // Reaching the limit exactly is not exceeding it
+        let streamer =
+            DirectoryLinesStreamer::from_dir_with_max_total_bytes(dir, two_files).unwrap();
+        assert_eq!(streamer.total_files(), 3);
+
+        match DirectoryLinesStreamer::from_dir_with_max_total_bytes(dir, 0) {
+            Err(DirectoryLinesStreamerError::EmptyDirectory(_)) => {}
+            _ => panic!("Unexpected result"),
+        }
+    }
+
+    #[test]
+    fn from_dir_with_latest_version_only() {
+        let lines: Vec<String> =
+            DirectoryLinesStreamer::from_dir_with_latest_version_only("fixtures/non-empty-dir")
+                .unwrap()
+                .collect();
+        assert_eq!(
+            lines,
+            &[
+                "line one from messages\n",
+                "line two from messages\n",
+                "line three from messages\n"
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "app.log.3.gz",
+            "app.log.2.gz",
+            "error.log.1",
+            "error.log.2.gz",
+        ] {
+            write_log_file(&dir.path().join(name), format!("{}\n", name).as_bytes());
+        }
+        let lines: Vec<String> =
+            DirectoryLinesStreamer::from_dir_with_latest_version_only(dir.path())
+                .unwrap()
+                .collect();
+        assert_eq!(lines, &["app.log.2.gz\n", "error.log.1\n"]);
+    }
+
+    #[test]
+    fn from_dir_with_consistent_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..30 {
+            fs::write(dir.path().join(format!("messages.{}", i)), "line\n").unwrap();
+        }
+        let mut all_files: Vec<PathBuf> = Vec::new();
+        for node_id in 0..3 {
+            let streamer =
+                DirectoryLinesStreamer::from_dir_with_consistent_hash(dir.path(), node_id, 3)
+                    .unwrap();
+            let files = streamer.files_snapshot();
+            assert!(files.len() < 30);
+            all_files.extend(files);
+        }
+        let mut expected = DirectoryLinesStreamer::from_dir(dir.path())
+            .unwrap()
+            .files_snapshot();
+        expected.sort();
+        // Disjoint: no file appears twice
+        all_files.sort();
+        assert_eq!(all_files, expected);
+        // Known FNV-1a test vector
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn from_dir_with_consistent_hash_invalid_nodes() {
+        for (node_id, total_nodes) in [(0, 0), (3, 3), (4, 3)] {
+            match DirectoryLinesStreamer::from_dir_with_consistent_hash(
+                "fixtures/non-empty-dir",
+                node_id,
+                total_nodes,
+            ) {
+                Err(DirectoryLinesStreamerError::InvalidArgument(_)) => {}
+                other => panic!("Unexpected {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn from_dir_with_oldest_version_only() {
+        let streamer =
+            DirectoryLinesStreamer::from_dir_with_oldest_version_only("fixtures/non-empty-dir")
+                .unwrap();
+        assert_eq!(
+            streamer.files_snapshot(),
+            &[PathBuf::from("fixtures/non-empty-dir/messages.20")]
+        );
+        assert_eq!(streamer.count(), 3);
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["app.log", "app.log.3.gz", "app.log.2.gz", "error.log"] {
+            write_log_file(&dir.path().join(name), format!("{}\n", name).as_bytes());
+        }
+        let lines: Vec<String> =
+            DirectoryLinesStreamer::from_dir_with_oldest_version_only(dir.path())
+                .unwrap()
+                .collect();
+        assert_eq!(lines, &["app.log.3.gz\n", "error.log\n"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_dir_checked() {
+        let dir = messages_dir();
+        assert_eq!(
+            DirectoryLinesStreamer::from_dir_checked(dir.path())
+                .unwrap()
+                .count(),
+            15
+        );
+
+        // Dangling links cannot be opened, even by root
+        std::os::unix::fs::symlink("missing", dir.path().join("messages.5")).unwrap();
+        std::os::unix::fs::symlink("missing", dir.path().join("messages.6")).unwrap();
+        let errors = DirectoryLinesStreamer::from_dir_checked(dir.path()).unwrap_err();
+        let failed: Vec<&Path> = errors.iter().map(|(path, _)| path.as_path()).collect();
+        assert_eq!(
+            failed,
+            &[dir.path().join("messages.5"), dir.path().join("messages.6")]
+        );
+        assert!(errors
+            .iter()
+            .all(|(_, error)| error.kind() == io::ErrorKind::NotFound));
+
+        let errors =
+            DirectoryLinesStreamer::from_dir_checked("fixtures/non-existing-dir").unwrap_err();
+        assert_eq!(errors[0].0, Path::new("fixtures/non-existing-dir"));
+    }
+
+    #[test]
+    fn files_snapshot() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let snapshot = streamer.files_snapshot();
+        let file_names: Vec<&str> = snapshot
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            file_names,
+            &[
+                "messages",
+                "messages.1",
+                "messages.2",
+                "messages.10",
+                "messages.20"
+            ]
+        );
+
+        Iterator::take(&mut streamer, 7).count();
+        assert_eq!(streamer.files_snapshot(), snapshot);
+        Iterator::by_ref(&mut streamer).count();
+        assert_eq!(streamer.files_snapshot(), snapshot);
+    }
+
+    #[test]
+    fn remaining_bytes_estimate() {
+        let total: u64 = fs::read_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum();
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.remaining_bytes_estimate(), total);
+
+        let mut previous = total;
+        while let Some(line) = streamer.next() {
+            let remaining = streamer.remaining_bytes_estimate();
+            assert_eq!(remaining, previous - line.len() as u64);
+            previous = remaining;
+        }
+        assert_eq!(streamer.remaining_bytes_estimate(), 0);
+    }
+
+    #[test]
+    fn progress() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let total_bytes = streamer.total_bytes();
+        assert_eq!(total_bytes, streamer.remaining_bytes_estimate());
+        assert_eq!(streamer.bytes_read(), 0);
+
+        let mut previous = 0.0;
+        while streamer.next().is_some() {
+            let progress = streamer.bytes_read() as f64 / total_bytes as f64;
+            assert!(progress > previous, "{} <= {}", progress, previous);
+            previous = progress;
+        }
+        assert_eq!(previous, 1.0);
+
+        assert_eq!(
+            DirectoryLinesStreamer::from_lines(Vec::new()).total_bytes(),
+            0
+        );
+    }
+
+    #[test]
+    fn line_number_in_file() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.line_number_in_file(), 0);
+        for i in 0..15 {
+            streamer.next().unwrap();
+            assert_eq!(streamer.line_number_in_file(), i % 3 + 1);
+            assert_eq!(streamer.line_number(), i + 1);
+        }
+
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_skip_first_lines(1)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.next().unwrap(), "line two from messages\n");
+        assert_eq!(streamer.line_number_in_file(), 2);
+    }
+
+    #[test]
+    fn current_file_index() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.total_files(), 5);
+        assert_eq!(streamer.current_file_index(), 0);
+        assert_eq!(
+            streamer.next_file(),
+            Some(Path::new("fixtures/non-empty-dir/messages.1"))
+        );
+        for i in 0..15 {
+            streamer.next().unwrap();
+            assert_eq!(streamer.current_file_index(), i / 3);
+        }
+        assert_eq!(streamer.current_file_index(), streamer.total_files() - 1);
+        assert_eq!(streamer.next_file(), None);
+    }
+
+    #[test]
+    fn unzip_on_delimiter() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/tsv-dir").unwrap();
+        let (keys, values) = streamer.unzip_on_delimiter(b'\t');
+        assert_eq!(keys, &["alpha", "beta", "no delimiter", "gamma"]);
+        assert_eq!(values, &["1", "2\textra", "", ""]);
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/tsv-dir").unwrap();
+        let (lengths, lines): (Vec<usize>, Vec<String>) =
+            streamer.unzip_lines(|line| (line.len(), line));
+        assert_eq!(lengths, &[8, 13, 13, 7]);
+        assert_eq!(lines[0], "alpha\t1\n");
+    }
+
+    #[test]
+    fn detected_sort_order() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(
+            streamer.detected_sort_order(),
+            SortOrder::AlphanumericAscending
+        );
+    }
+
+    #[test]
+    fn fold_until() {
+        let (count, line) = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .fold_until(0, |count, line| {
+                if line.contains("messages.2") {
+                    ControlFlow::Break(count)
+                } else {
+                    ControlFlow::Continue(count + 1)
+                }
+            });
+        assert_eq!(count, 6);
+        assert_eq!(line.as_deref(), Some("line one from messages.2\n"));
+
+        let (count, line) = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .fold_until(0, |count, _| ControlFlow::Continue(count + 1));
+        assert_eq!(count, 15);
+        assert_eq!(line, None);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn from_dir_with_archive_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        for entry in fs::read_dir("fixtures/non-empty-dir").unwrap() {
+            let path = entry.unwrap().path();
+            let file_name = path.file_name().unwrap().to_os_string();
+            let content = fs::read(&path).unwrap();
+            // Only archived, archived as well, or only plain
+            let (plain, archived) = match file_name.to_str().unwrap() {
+                "messages.1" | "messages.20" => (false, true),
+                "messages.2" => (true, true),
+                _ => (true, false),
+            };
+            if plain {
+                fs::write(dir.path().join(&file_name), &content).unwrap();
+            }
+            if archived {
+                let mut archive_name = file_name;
+                archive_name.push(".gz");
+                write_log_file(&dir.path().join(archive_name), &content);
+            }
+        }
+
+        let lines: Vec<String> = DirectoryLinesStreamer::from_dir_with_archive_fallback(dir.path())
+            .unwrap()
+            .collect();
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        assert_eq!(lines, expected);
+
+        // Without fallback, both versions of `messages.2` are read
+        assert_eq!(
+            DirectoryLinesStreamer::from_dir(dir.path())
+                .unwrap()
+                .count(),
+            expected.len() + 3
+        );
+    }
+
+    #[test]
+    fn collect_by_extension() {
+        let total_lines = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .count();
+        let groups = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect_by_extension();
+        let mut keys: Vec<&str> = groups.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, &["", "1", "10", "2", "20"]);
+        assert_eq!(groups[""][0], "line one from messages\n");
+        assert_eq!(groups.values().map(Vec::len).sum::<usize>(), total_lines);
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.LOG"), "a\n").unwrap();
+        fs::write(dir.path().join("b.log"), "b\n").unwrap();
+        fs::write(dir.path().join("c.txt"), "c\n").unwrap();
+        let groups = DirectoryLinesStreamer::from_dir(dir.path())
+            .unwrap()
+            .collect_by_extension();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups["log"], &["a\n", "b\n"]);
+        assert_eq!(groups["txt"], &["c\n"]);
+
+        let groups = DirectoryLinesStreamer::from_dir(dir.path())
+            .unwrap()
+            .collect_by_basename();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups["a.LOG"], &["a\n"]);
+        assert_eq!(groups.values().map(Vec::len).sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn nul_terminated_lines() {
+        let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_nul_terminated_lines(true)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(lines[0], "line one from messages\0");
+        for line in &lines {
+            assert!(line.ends_with('\0'));
+            assert!(CString::new(line.trim_end_matches('\0')).is_ok());
+        }
+        assert_eq!(
+            as_cstring(&lines[0]).unwrap().as_bytes(),
+            b"line one from messages"
+        );
+        assert!(as_cstring("nul\0inside\0").is_err());
+    }
+
+    #[test]
+    fn sample_with_replacement() {
+        let sample = |k, seed| {
+            DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+                .unwrap()
+                .sample_with_replacement(k, seed)
+        };
+        let lines: HashSet<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        let drawn = sample(100, 7);
+        assert_eq!(drawn.len(), 100);
+        assert!(drawn.iter().all(|line| lines.contains(line)));
+        assert_eq!(sample(100, 7), drawn);
+        assert_ne!(sample(100, 8), drawn);
+        // With replacement, some lines are drawn several times
+        assert!(drawn.iter().collect::<HashSet<_>>().len() < drawn.len());
+        assert!(sample(0, 7).is_empty());
+        assert!(DirectoryLinesStreamer::from_lines(Vec::new())
+            .sample_with_replacement(10, 7)
+            .is_empty());
+    }
+
+    #[test]
+    fn collect_as_map() {
+        // Fixture lines all start with "line": group them by their second word instead.
+        let second_word = |line: &str| line.split(' ').nth(1).unwrap_or_default().to_string();
+        let groups = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect_as_map(second_word);
+        let mut keys: Vec<&str> = groups.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, &["one", "three", "two"]);
+        assert!(groups.values().all(|lines| lines.len() == 5));
+        assert_eq!(groups["one"][0], "line one from messages\n");
+        assert_eq!(groups["one"][4], "line one from messages.20\n");
+
+        let lines = vec!["INFO a", "WARN b", "INFO c", "ERROR d"];
+        let groups =
+            DirectoryLinesStreamer::from_lines(lines.into_iter().map(String::from).collect())
+                .collect_as_btree_map(|line| line.split(' ').next().unwrap().to_string());
+        let counts: Vec<(&str, usize)> = groups
+            .iter()
+            .map(|(level, lines)| (level.as_str(), lines.len()))
+            .collect();
+        assert_eq!(counts, &[("ERROR", 1), ("INFO", 2), ("WARN", 1)]);
+        assert_eq!(groups["INFO"], &["INFO a\n", "INFO c\n"]);
+    }
+
+    #[test]
+    fn lines_per_file() {
+        let counts = DirectoryLinesStreamer::from_dir("fixtures/with-empty-dir")
+            .unwrap()
+            .lines_per_file();
+        let dir = Path::new("fixtures/with-empty-dir");
+        assert_eq!(
+            counts,
+            &[
+                (dir.join("messages"), 0),
+                (dir.join("messages.1"), 1),
+                (dir.join("messages.2"), 0),
+                (dir.join("messages.3"), 1),
+            ]
+        );
+
+        let counts = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .lines_per_file();
+        let dir = Path::new("fixtures/non-empty-dir");
+        assert_eq!(
+            counts,
+            &[
+                (dir.join("messages"), 3),
+                (dir.join("messages.1"), 3),
+                (dir.join("messages.2"), 3),
+                (dir.join("messages.10"), 3),
+                (dir.join("messages.20"), 3),
+            ]
+        );
+        let total: usize = counts.iter().map(|(_, count)| count).sum();
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(total, streamer.count());
+    }
+
+    #[test]
+    fn collect() {
+        let dir = messages_dir();
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        let lines: Vec<String> = streamer.collect();
+
+        let expected_lines = &[
+            "line one from messages\n",
+            "line two from messages\n",
+            "line three from messages\n",
+            "line one from messages.1\n",
+            "line two from messages.1\n",
+            "line three from messages.1\n",
+            "line one from messages.2\n",
+            "line two from messages.2\n",
+            "line three from messages.2\n",
+            "line one from messages.10\n",
+            "line two from messages.10\n",
+            "line three from messages.10\n",
+            "line one from messages.20\n",
+            "line two from messages.20\n",
+            "line three from messages.20\n",
+        ];
+        for (ref line, expected_line) in lines.iter().zip(expected_lines) {
+            assert_eq!(line, expected_line);
+        }
+    }
+
+    #[test]
+    fn custom_newline() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/nul-delimited-dir")
+            .with_custom_newline(b'\0')
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+
+        assert_eq!(
+            lines,
+            &[
+                "record one\0",
+                "record two\nstill record two\0",
+                "record three\0",
+            ]
+        );
+    }
+
+    #[test]
+    fn from_env_var() {
+        env::set_var("DIR_LINES_STREAMER_TEST_DIR", "fixtures/non-empty-dir");
+        let streamer = DirectoryLinesStreamer::from_env_var("DIR_LINES_STREAMER_TEST_DIR").unwrap();
+        assert_eq!(streamer.count(), 15);
+    }
+
+    #[test]
+    fn from_env_var_not_set() {
+        match DirectoryLinesStreamer::from_env_var("DIR_LINES_STREAMER_TEST_UNSET").unwrap_err() {
+            DirectoryLinesStreamerError::EnvVarNotSet(var) => {
+                assert_eq!(var, "DIR_LINES_STREAMER_TEST_UNSET")
+            }
+            _ => panic!("Unexpected error"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_env_var_not_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        env::set_var(
+            "DIR_LINES_STREAMER_TEST_NOT_UTF8",
+            OsStr::from_bytes(b"fixtures/\xff"),
+        );
+        match DirectoryLinesStreamer::from_env_var("DIR_LINES_STREAMER_TEST_NOT_UTF8").unwrap_err()
+        {
+            DirectoryLinesStreamerError::EnvVarNotUtf8(var) => {
+                assert_eq!(var, "DIR_LINES_STREAMER_TEST_NOT_UTF8")
+            }
+            _ => panic!("Unexpected error"),
+        }
+    }
+
+    #[test]
+    fn utf8_mode_lossy() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/invalid-utf8-dir").unwrap();
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(
+            lines,
+            &[
+                "valid line one\n",
+                "invalid \u{FFFD} line two\n",
+                "valid line three\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn utf8_mode_strict() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/invalid-utf8-dir")
+            .with_utf8_mode(Utf8Mode::Strict)
+            .build()
+            .unwrap();
+        let results: Vec<Result<String, DirectoryLinesStreamerError>> =
+            streamer.try_lines().collect();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), "valid line one\n");
+        match &results[1] {
+            Err(DirectoryLinesStreamerError::InvalidUtf8 { file, line_id, .. }) => {
+                assert_eq!(file, &PathBuf::from("fixtures/invalid-utf8-dir/messages"));
+                assert_eq!(*line_id, 2);
+            }
+            _ => panic!("Unexpected result"),
+        }
+        assert_eq!(results[2].as_ref().unwrap(), "valid line three\n");
+
+        // Without `try_lines()` the error is only logged.
+        let streamer = DirectoryLinesStreamer::builder("fixtures/invalid-utf8-dir")
+            .with_utf8_mode(Utf8Mode::Strict)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.count(), 2);
+    }
+
+    #[test]
+    fn utf8_mode_skip() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/invalid-utf8-dir")
+            .with_utf8_mode(Utf8Mode::Skip)
+            .build()
+            .unwrap();
+        let results: Vec<Result<String, DirectoryLinesStreamerError>> =
+            streamer.try_lines().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(Result::is_ok));
+    }
+
+    #[test]
+    fn into_boxed_iterator() {
+        fn count_lines(lines: Box<dyn Iterator<Item = String>>) -> usize {
+            lines.count()
+        }
+        fn count_lines_send(lines: Box<dyn Iterator<Item = String> + Send>) -> usize {
+            std::thread::spawn(move || lines.count()).join().unwrap()
+        }
+        fn count_ok(
+            lines: Box<dyn Iterator<Item = Result<String, DirectoryLinesStreamerError>>>,
+        ) -> usize {
+            lines.filter(Result::is_ok).count()
+        }
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(count_lines(streamer.into()), 15);
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(count_lines_send(streamer.into()), 15);
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(count_ok(streamer.into()), 15);
+    }
+
+    #[test]
+    fn file_transition_callback() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let callback_output = Arc::clone(&output);
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_transition_callback(move |closing, opening| {
+                let closing = closing.map(|p| p.file_name().unwrap().to_string_lossy().to_string());
+                let opening = opening.file_name().unwrap().to_string_lossy();
+                callback_output
+                    .lock()
+                    .unwrap()
+                    .push(format!("--- {:?} -> {}\n", closing, opening));
+            })
+            .build()
+            .unwrap();
+        for line in streamer {
+            output.lock().unwrap().push(line);
+        }
+
+        let output = output.lock().unwrap();
+        assert_eq!(output.len(), 15 + 5);
+        assert_eq!(output[0], "--- None -> messages\n");
+        assert_eq!(output[1], "line one from messages\n");
+        assert_eq!(output[4], "--- Some(\"messages\") -> messages.1\n");
+        assert_eq!(output[5], "line one from messages.1\n");
+        assert_eq!(output[16], "--- Some(\"messages.10\") -> messages.20\n");
+    }
+
+    #[test]
+    fn line_callback() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let callback_calls = Arc::clone(&calls);
+        let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_line_callback(move |path, line_number, line| {
+                let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+                assert!(line.ends_with(&format!("from {}\n", file_name)));
+                callback_calls
+                    .lock()
+                    .unwrap()
+                    .push((file_name, line_number));
+            })
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), 15);
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 15);
+        assert_eq!(calls[0], ("messages".to_string(), 1));
+        assert_eq!(calls[2], ("messages".to_string(), 3));
+        assert_eq!(calls[3], ("messages.1".to_string(), 4));
+        assert_eq!(calls[14], ("messages.20".to_string(), 15));
+    }
+
+    #[test]
+    fn file_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let content: String = (0..10).map(|i| format!("line {:04}\n", i)).collect();
+        fs::write(dir.path().join("big"), content).unwrap();
+        fs::write(dir.path().join("small"), "small\n").unwrap();
+
+        let transitions = Arc::new(Mutex::new(0));
+        let callback_transitions = Arc::clone(&transitions);
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_file_size_limit(25)
+            .with_line_numbering_mode(LineNumberingMode::ByteOffset)
+            .with_file_transition_callback(move |_, _| *callback_transitions.lock().unwrap() += 1)
+            .build()
+            .unwrap();
+        let mut split_offsets = Vec::new();
+        let mut nb_transitions = *transitions.lock().unwrap();
+        while streamer.next().is_some() {
+            let new_nb_transitions = *transitions.lock().unwrap();
+            if new_nb_transitions != nb_transitions && streamer.current_file().ends_with("big") {
+                split_offsets.push(streamer.line_number());
+            }
+            nb_transitions = new_nb_transitions;
+        }
+        // Lines are 10 bytes long
+        assert_eq!(split_offsets, &[30, 60, 90]);
+        // Opening both files, and 3 splits
+        assert_eq!(nb_transitions, 5);
+
+        // Chunks are reported as files
+        let build = || {
+            DirectoryLinesStreamer::builder(dir.path())
+                .with_file_size_limit(25)
+                .build()
+                .unwrap()
+        };
+        let counts: Vec<usize> = build()
+            .lines_per_file()
+            .into_iter()
+            .map(|(_, count)| count)
+            .collect();
+        assert_eq!(counts, &[3, 3, 3, 1, 1]);
+        let first_lines: Vec<(PathBuf, String)> = build().fold_files(
+            String::new(),
+            |first, line| if first.is_empty() { line } else { first },
+        );
+        assert_eq!(
+            first_lines,
+            &[
+                (dir.path().join("big"), "line 0000\n".to_string()),
+                (dir.path().join("big"), "line 0003\n".to_string()),
+                (dir.path().join("big"), "line 0006\n".to_string()),
+                (dir.path().join("big"), "line 0009\n".to_string()),
+                (dir.path().join("small"), "small\n".to_string()),
+            ]
+        );
+    }
+
+    /// Three files, the second one being deleted by the caller after the streamer is built.
+    fn dir_with_file_to_delete() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("messages"),
+            "line one from messages\nline two from messages\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("messages.1"), "line one from messages.1\n").unwrap();
+        fs::write(dir.path().join("messages.2"), "line one from messages.2\n").unwrap();
+        let to_delete = dir.path().join("messages.1");
+        (dir, to_delete)
+    }
+
+    #[test]
+    fn collect_errors() {
+        let (dir, to_delete) = dir_with_file_to_delete();
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        fs::remove_file(&to_delete).unwrap();
+        let (lines, errors) = streamer.collect_errors();
+
+        assert_eq!(
+            lines,
+            &[
+                "line one from messages\n",
+                "line two from messages\n",
+                "line one from messages.2\n"
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            DirectoryLinesStreamerError::FileDeletedBeforeRead(file) => {
+                assert_eq!(file, &to_delete)
+            }
+            _ => panic!("Unexpected error"),
+        }
+    }
+
+    #[test]
+    fn error_policy_collect() {
+        let (dir, to_delete) = dir_with_file_to_delete();
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_error_policy(ErrorPolicy::Collect)
+            .build()
+            .unwrap();
+        fs::remove_file(&to_delete).unwrap();
+        assert_eq!(Iterator::by_ref(&mut streamer).count(), 3);
+        assert_eq!(streamer.take_errors().len(), 1);
+        assert!(streamer.take_errors().is_empty());
+    }
+
+    #[test]
+    fn current_file_and_line_number() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.line_number(), 0);
+        for _ in 0..4 {
+            streamer.next().unwrap();
+        }
+        assert_eq!(streamer.line_number(), 4);
+        assert_eq!(
+            streamer.current_file(),
+            Path::new("fixtures/non-empty-dir/messages.1")
+        );
+    }
+
+    #[test]
+    fn preload() {
+        let streamed: Vec<(usize, PathBuf, String)> = {
+            let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+            let mut lines = Vec::new();
+            while let Some(line) = streamer.next() {
+                lines.push((
+                    streamer.line_number(),
+                    streamer.current_file().to_path_buf(),
+                    line,
+                ));
+            }
+            lines
+        };
+
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_preload(true)
+            .build()
+            .unwrap();
+        // Everything is already in memory
+        assert!(streamer.files.is_empty());
+        assert_eq!(streamer.line_number(), 0);
+        let mut preloaded = Vec::new();
+        while let Some(line) = streamer.next() {
+            preloaded.push((
+                streamer.line_number(),
+                streamer.current_file().to_path_buf(),
+                line,
+            ));
+        }
+
+        assert_eq!(streamed.len(), 15);
+        assert_eq!(preloaded, streamed);
+    }
+
+    #[test]
+    fn concurrent_collect() {
+        use std::time::{Duration, Instant};
+
+        fn slow_streamer() -> DirectoryLinesStreamer {
+            DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+                .with_custom_open(|path| {
+                    std::thread::sleep(Duration::from_millis(100));
+                    Ok(Box::new(fs::File::open(path)?))
+                })
+                .build()
+                .unwrap()
+        }
+
+        let streamer = slow_streamer();
+        let start = Instant::now();
+        let expected: Vec<String> = streamer.collect();
+        let sequential = start.elapsed();
+        assert_eq!(expected.len(), 15);
+        // The first file is opened by `build()`
+        assert!(sequential >= Duration::from_millis(400));
+
+        for threads in [0, 1, 2, 5, 10] {
+            let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+            assert_eq!(streamer.concurrent_collect(threads), expected);
+        }
+
+        let streamer = slow_streamer();
+        let start = Instant::now();
+        assert_eq!(streamer.concurrent_collect(5), expected);
+        let concurrent = start.elapsed();
+        // One file per thread, all opened at the same time
+        assert!(concurrent < Duration::from_millis(300), "{:?}", concurrent);
+    }
+
+    #[test]
+    fn into_sink() {
+        let lines: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+
+        let mut output: Vec<u8> = Vec::new();
+        let nb_bytes = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .into_sink(&mut output)
+            .unwrap();
+        assert_eq!(output, lines.concat().as_bytes());
+        assert_eq!(nb_bytes, output.len() as u64);
+
+        let mut output: Vec<u8> = Vec::new();
+        let nb_bytes = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .into_sink_with_separator(&mut output, b"--\n")
+            .unwrap();
+        assert_eq!(output, lines.join("--\n").as_bytes());
+        assert_eq!(nb_bytes, output.len() as u64);
+    }
+
+    #[test]
+    fn tail() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(
+            streamer.tail(3),
+            &[
+                "line one from messages.20\n",
+                "line two from messages.20\n",
+                "line three from messages.20\n",
+            ]
+        );
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let all_lines: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        assert_eq!(streamer.tail(all_lines.len() + 1), all_lines);
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.tail(usize::MAX), all_lines);
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert!(streamer.tail(0).is_empty());
+    }
+
+    #[test]
+    fn head() {
+        let opened = Arc::new(Mutex::new(Vec::new()));
+        let callback_opened = Arc::clone(&opened);
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_file_transition_callback(move |_, opening| {
+                callback_opened.lock().unwrap().push(opening.to_path_buf());
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            streamer.head(4),
+            &[
+                "line one from messages\n",
+                "line two from messages\n",
+                "line three from messages\n",
+                "line one from messages.1\n",
+            ]
+        );
+        assert_eq!(
+            *opened.lock().unwrap(),
+            &[
+                PathBuf::from("fixtures/non-empty-dir/messages"),
+                PathBuf::from("fixtures/non-empty-dir/messages.1"),
+            ]
+        );
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.head(100).len(), 15);
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert!(streamer.head(0).is_empty());
+    }
+
+    #[test]
+    fn checksum_per_file() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let checksums = streamer.checksum_per_file();
+
+        assert_eq!(checksums.len(), 5);
+        for entry in fs::read_dir("fixtures/non-empty-dir").unwrap() {
+            let path = entry.unwrap().path();
+            let expected: [u8; 32] = Sha256::digest(fs::read(&path).unwrap()).into();
+            assert_eq!(checksums[&path], expected);
+        }
+        // sha256sum fixtures/non-empty-dir/messages
+        assert_eq!(
+            checksums[Path::new("fixtures/non-empty-dir/messages")][..4],
+            [0x98, 0xc0, 0x4c, 0x14]
+        );
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/with-empty-dir").unwrap();
+        let checksums = streamer.checksum_per_file();
+        assert_eq!(checksums.len(), 4);
+        let empty: [u8; 32] = Sha256::digest(b"").into();
+        assert_eq!(
+            checksums[Path::new("fixtures/with-empty-dir/messages")],
+            empty
+        );
+        assert_eq!(
+            checksums[Path::new("fixtures/with-empty-dir/messages.2")],
+            empty
+        );
+    }
+
+    #[test]
+    fn checksum_per_file_with_line_options() {
+        let digests = |dir: &str| -> HashMap<PathBuf, [u8; 32]> {
+            fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| {
+                    let path = entry.unwrap().path();
+                    let checksum = Sha256::digest(fs::read(&path).unwrap()).into();
+                    (path, checksum)
+                })
+                .collect()
+        };
+        for streamer in [
+            DirectoryLinesStreamer::builder("fixtures/with-empty-dir")
+                .with_skip_first_lines(1)
+                .build()
+                .unwrap(),
+            DirectoryLinesStreamer::builder("fixtures/with-empty-dir")
+                .with_empty_file_placeholder("EMPTY".to_string())
+                .build()
+                .unwrap(),
+            // No line of `messages.1` or `messages.3` has the prefix
+            DirectoryLinesStreamer::builder("fixtures/with-empty-dir")
+                .with_record_prefix("three".to_string())
+                .build()
+                .unwrap(),
+        ] {
+            assert_eq!(
+                streamer.checksum_per_file(),
+                digests("fixtures/with-empty-dir")
+            );
+        }
+
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        streamer.peek_first_line();
+        streamer.next();
+        assert_eq!(
+            streamer.checksum_per_file(),
+            digests("fixtures/non-empty-dir")
+        );
+    }
+
+    #[test]
+    fn compare_checksums() {
+        let dir = messages_dir();
+        fs::write(dir.path().join("empty"), "").unwrap();
+        let mut expected: HashMap<PathBuf, [u8; 32]> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let checksum = Sha256::digest(fs::read(&path).unwrap()).into();
+                (path, checksum)
+            })
+            .collect();
+        let corrupt = dir.path().join("messages.2");
+        fs::write(&corrupt, "line one from messages.2\ncorrupt\n").unwrap();
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        assert_eq!(
+            streamer.compare_checksums(expected.clone()),
+            &[(
+                corrupt.clone(),
+                ChecksumMismatch::Differs {
+                    actual: Sha256::digest(fs::read(&corrupt).unwrap()).into(),
+                    expected: expected[&corrupt],
+                }
+            )]
+        );
+
+        let missing = dir.path().join("messages.3");
+        expected.insert(missing.clone(), [0; 32]);
+        expected.remove(&corrupt);
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        assert_eq!(
+            streamer.compare_checksums(expected.clone()),
+            &[(
+                missing.clone(),
+                ChecksumMismatch::NotFound { expected: [0; 32] }
+            )]
+        );
+
+        // Deleted once listed
+        expected.remove(&missing);
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        let deleted = dir.path().join("messages.10");
+        fs::remove_file(&deleted).unwrap();
+        assert_eq!(
+            streamer.compare_checksums(expected.clone()),
+            &[(
+                deleted.clone(),
+                ChecksumMismatch::Unreadable {
+                    expected: expected[&deleted]
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn buf_read() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .map(|line| line.trim_end_matches('\n').to_string())
+            .collect();
+
+        let lines: Vec<String> = BufReader::new(streamer)
+            .lines()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines, expected);
+
+        // Reads smaller than a line
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let mut buf = [0u8; 5];
+        streamer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"line ");
+        let mut rest = String::new();
+        streamer.read_to_string(&mut rest).unwrap();
+        assert!(rest.starts_with("one from messages\nline two from messages\n"));
+        assert!(rest.ends_with("line three from messages.20\n"));
+
+        // A line stripped to nothing by the record prefix is not the end
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "> a\n>").unwrap();
+        fs::write(dir.path().join("messages.1"), "> b\n").unwrap();
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_record_prefix(">".to_string())
+            .build()
+            .unwrap();
+        let mut content = String::new();
+        streamer.read_to_string(&mut content).unwrap();
+        assert_eq!(content, " a\n b\n");
+    }
+
+    #[test]
+    fn line_histogram() {
+        // Lines are 23 to 28 bytes long
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(
+            streamer.line_histogram(&[24, 25, 26, usize::MAX]),
+            &[2, 5, 4, 4]
+        );
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.line_histogram(&[25]), &[7]);
+
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert!(streamer.line_histogram(&[]).is_empty());
+    }
+
+    #[test]
+    fn fold_files() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let lines_per_file = streamer.fold_files(0, |count, _line| count + 1);
+
+        let names: Vec<String> = lines_per_file
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            &[
+                "messages",
+                "messages.1",
+                "messages.2",
+                "messages.10",
+                "messages.20"
+            ]
+        );
+        for (path, count) in lines_per_file {
+            assert_eq!(count, fs::read_to_string(&path).unwrap().lines().count());
+        }
+
+        /// Counts its clones
+        struct Counted<'a>(&'a Cell<usize>);
+
+        impl Clone for Counted<'_> {
+            fn clone(&self) -> Self {
+                self.0.set(self.0.get() + 1);
+                Counted(self.0)
+            }
+        }
+
+        let clones = Cell::new(0);
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let folded = streamer.fold_files(Counted(&clones), |accumulator, _line| accumulator);
+        assert_eq!(folded.len(), 5);
+        // Once per file, not once per line
+        assert_eq!(clones.get(), 5);
+    }
+
+    #[test]
+    fn position() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let position = streamer.position();
+        assert_eq!(position.file_index, 0);
+        assert_eq!(position.byte_offset, 0);
+        assert_eq!(position.line_number, 0);
+
+        let read: Vec<String> = Iterator::take(&mut streamer, 7).collect();
+        let position = streamer.position();
+        assert_eq!(position.dir, PathBuf::from("fixtures/non-empty-dir"));
+        assert_eq!(position.file_index, 2);
+        assert_eq!(
+            position.file,
+            PathBuf::from("fixtures/non-empty-dir/messages.2")
+        );
+        assert_eq!(position.byte_offset, read[6].len() as u64);
+        assert_eq!(position.line_number, 7);
+
+        let resumed = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_start_position(position)
+            .build()
+            .unwrap();
+        let all_lines: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        assert_eq!([read, resumed.collect()].concat(), all_lines);
+    }
+
+    #[test]
+    fn position_preloaded() {
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_preload(true)
+            .build()
+            .unwrap();
+        Iterator::take(&mut streamer, 7).count();
+        let position = streamer.position();
+        assert_eq!(position.file_index, 2);
+        assert_eq!(position.byte_offset, 25);
+    }
+
+    #[test]
+    fn start_position_mismatch() {
+        let mut position = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .position();
+        position.file_index = 1;
+        match DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_start_position(position)
+            .build()
+            .unwrap_err()
+        {
+            DirectoryLinesStreamerError::InvalidCheckpoint(_) => {}
+            _ => panic!("Unexpected error"),
+        }
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn checkpoint_file() {
+        let checkpoint_dir = tempfile::tempdir().unwrap();
+        let checkpoint = checkpoint_dir.path().join("checkpoint.json");
+
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let read: Vec<String> = Iterator::take(&mut streamer, 5).collect();
+        streamer.checkpoint_file(&checkpoint).unwrap();
+
+        let mut content: serde_json::Value =
+            serde_json::from_slice(&fs::read(&checkpoint).unwrap()).unwrap();
+        assert_eq!(content["version"], 1);
+
+        let resumed = DirectoryLinesStreamer::restore_from_checkpoint_file(
+            "fixtures/non-empty-dir",
+            &checkpoint,
+        )
+        .unwrap();
+        let all_lines: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        assert_eq!([read, resumed.collect()].concat(), all_lines);
+
+        // Only the version differs from a valid checkpoint
+        content["version"] = serde_json::Value::from(2);
+        fs::write(&checkpoint, content.to_string()).unwrap();
+        match DirectoryLinesStreamer::restore_from_checkpoint_file(
+            "fixtures/non-empty-dir",
+            &checkpoint,
+        ) {
+            Err(DirectoryLinesStreamerError::InvalidCheckpoint(message)) => {
+                assert_eq!(message, "unsupported version 2")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // No temporary file is left behind
+        assert_eq!(fs::read_dir(checkpoint_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn from_stdin() {
+        use std::{
+            io::{BufWriter, Write},
+            thread,
+        };
+
+        // The read end of a pipe stands for standard input, shared by the tests
+        let (reader, writer) = io::pipe().unwrap();
+        let writer = thread::spawn(move || {
+            let mut writer = BufWriter::new(writer);
+            writer
+                .write_all(&fs::read("fixtures/non-empty-dir/messages").unwrap())
+                .unwrap();
+            // Dropping the writer closes the pipe
+        });
+
+        let mut streamer = DirectoryLinesStreamer::from_input(reader);
+        let lines: Vec<String> = Iterator::by_ref(&mut streamer).collect();
+        writer.join().unwrap();
+
+        assert_eq!(
+            lines,
+            &[
+                "line one from messages\n",
+                "line two from messages\n",
+                "line three from messages\n"
+            ]
+        );
+        assert_eq!(streamer.current_file(), Path::new("<stdin>"));
+        assert_eq!(streamer.line_number(), 3);
+    }
+
+    #[test]
+    fn path_prefix_strip() {
+        let dir = fs::canonicalize("fixtures/non-empty-dir").unwrap();
+        let mut streamer = DirectoryLinesStreamer::builder(&dir)
+            .with_path_prefix_strip(&dir)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.current_file(), Path::new("messages"));
+        Iterator::take(&mut streamer, 4).count();
+        assert_eq!(streamer.current_file(), Path::new("messages.1"));
+
+        // Paths outside of the prefix are kept as is
+        let streamer = DirectoryLinesStreamer::builder(&dir)
+            .with_path_prefix_strip("/not/a/prefix")
+            .build()
+            .unwrap();
+        assert_eq!(streamer.current_file(), dir.join("messages"));
+    }
+
+    #[test]
+    fn extend() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+        streamer.extend(vec!["alarm\n".to_string(), "heartbeat\n".to_string()]);
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(lines.len(), 16);
+        assert_eq!(lines[0], "alarm\n");
+        assert_eq!(lines[1], "heartbeat\n");
+        assert_eq!(lines[2], "line two from messages\n");
+    }
+
+    #[test]
+    fn peek_first_line() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        let peeked = streamer.peek_first_line().unwrap().to_string();
+        assert_eq!(peeked, "line one from messages\n");
+        assert_eq!(streamer.peek_first_line(), Some(peeked.as_str()));
+        assert_eq!(streamer.next(), Some(peeked));
+
+        streamer.skip_first_line();
+        assert_eq!(streamer.first_line().unwrap(), "line three from messages\n");
+        streamer.peek_first_line();
+        streamer.skip_first_line();
+        assert_eq!(streamer.next().unwrap(), "line two from messages.1\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), b"\xff\nsecond\nthird\n").unwrap();
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_utf8_mode(Utf8Mode::Skip)
+            .build()
+            .unwrap();
+        streamer.skip_first_line();
+        assert_eq!(streamer.next().unwrap(), "third\n");
+    }
+
+    #[test]
+    fn peek_first_line_nul_terminated_with_line_callback() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let callback_calls = Arc::clone(&calls);
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_nul_terminated_lines(true)
+            .with_line_callback(move |_, line_number, line| {
+                callback_calls
+                    .lock()
+                    .unwrap()
+                    .push((line_number, line.to_string()));
+            })
+            .build()
+            .unwrap();
+        assert_eq!(streamer.peek_first_line(), Some("line one from messages\0"));
+        assert_eq!(streamer.peek_first_line(), Some("line one from messages\0"));
+        assert_eq!(streamer.next().unwrap(), "line one from messages\0");
+        assert_eq!(streamer.next().unwrap(), "line two from messages\0");
+        assert_eq!(
+            *calls.lock().unwrap(),
+            &[
+                (1, "line one from messages\0".to_string()),
+                (2, "line two from messages\0".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn line_numbering_mode() {
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_line_numbering_mode(LineNumberingMode::ByteOffset)
+            .build()
+            .unwrap();
+        let first = streamer.next().unwrap();
+        assert_eq!(streamer.line_number(), 0);
+        let second = streamer.next().unwrap();
+        assert_eq!(streamer.line_number(), first.len());
+        streamer.next();
+        assert_eq!(streamer.line_number(), first.len() + second.len());
+        // Offsets restart with each file
+        streamer.next();
+        assert_eq!(streamer.line_number(), 0);
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("file"), "caf\u{e9}\nline\n").unwrap();
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_line_numbering_mode(LineNumberingMode::CharOffset)
+            .build()
+            .unwrap();
+        streamer.next();
+        assert_eq!(streamer.line_number(), 0);
+        streamer.next();
+        assert_eq!(streamer.line_number(), 5);
+    }
+
+    #[test]
+    fn virtual_concat_filename() {
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_virtual_concat_filename("all-messages")
+            .build()
+            .unwrap();
+        let mut nb_lines = 0;
+        while streamer.next().is_some() {
+            nb_lines += 1;
+            assert_eq!(streamer.current_file(), Path::new("all-messages"));
+            assert_eq!(streamer.line_number(), nb_lines);
+        }
+        assert_eq!(nb_lines, 15);
+    }
+
+    #[test]
+    fn sum_line_lengths() {
+        let expected: u64 = fs::read_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum();
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.sum_line_lengths(), expected);
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.sum_line_lengths_utf8(), expected);
+
+        // Invalid sequences are replaced by 3 bytes long replacement characters
+        let content = fs::read("fixtures/invalid-utf8-dir/messages").unwrap();
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/invalid-utf8-dir").unwrap();
+        assert_eq!(streamer.sum_line_lengths(), content.len() as u64);
+        let streamer = DirectoryLinesStreamer::builder("fixtures/invalid-utf8-dir")
+            .with_utf8_mode(Utf8Mode::Lossy)
+            .build()
+            .unwrap();
+        assert_eq!(
+            streamer.sum_line_lengths_utf8(),
+            String::from_utf8_lossy(&content).len() as u64
+        );
+    }
+
+    #[test]
+    fn count_distinct_lines() {
+        let streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.count_distinct_lines(), 15);
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "a\nb\na\n").unwrap();
+        fs::write(dir.path().join("messages.1"), "b\nc\n").unwrap();
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        let distinct = streamer.distinct_lines();
+        assert_eq!(distinct.len(), 3);
+        assert!(distinct.contains("c\n"));
+    }
+
+    #[cfg(feature = "hyperloglog")]
+    #[test]
+    fn count_distinct_approx() {
+        let dir = tempfile::tempdir().unwrap();
+        let content: String = (0..10_000)
+            .map(|i| format!("line {}\n", i % 5_000))
+            .collect();
+        fs::write(dir.path().join("messages"), content).unwrap();
+
+        let error_rate = 0.02;
+        let streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        let approx = streamer.count_distinct_approx(error_rate);
+        let error = (approx as f64 - 5_000.0).abs() / 5_000.0;
+        assert!(error <= error_rate, "estimated {} distinct lines", approx);
+    }
+
+    #[test]
+    fn line_buffer_reuse() {
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_line_buffer_reuse(true)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.collect::<Vec<String>>(), expected);
+
+        let streamer = DirectoryLinesStreamer::builder("fixtures/invalid-utf8-dir")
+            .with_line_buffer_reuse(true)
+            .with_utf8_mode(Utf8Mode::Strict)
+            .build()
+            .unwrap();
+        let (lines, errors) = streamer.collect_errors();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn skip_first_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("messages"),
+            "# header\nline one\nline two\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("messages.1"), "# header\n").unwrap();
+        fs::write(dir.path().join("messages.2"), "# header\nline three\n").unwrap();
+
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_skip_first_lines(1)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = Iterator::by_ref(&mut streamer).collect();
+        assert_eq!(lines, &["line one\n", "line two\n", "line three\n"]);
+        // Skipped lines are still counted
+        assert_eq!(streamer.line_number(), 6);
+    }
+
+    #[test]
+    fn drop_incomplete_last_line() {
+        let lines: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/incomplete-dir")
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[2], "partial");
+
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/incomplete-dir")
+            .with_drop_incomplete_last_line(true)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = Iterator::by_ref(&mut streamer).collect();
+        assert_eq!(
+            lines,
+            &["line one\n", "line two\n", "line three\n", "line four\n"]
+        );
+        assert_eq!(streamer.line_number(), 4);
+
+        // Resuming reads the dropped line once complete
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages");
+        fs::write(&path, "line one\npart").unwrap();
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_drop_incomplete_last_line(true)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one\n");
+        assert_eq!(streamer.next(), None);
+        let position = streamer.position();
+        assert_eq!(position.byte_offset, 9);
+        fs::write(&path, "line one\npartial\n").unwrap();
+        let lines: Vec<String> = DirectoryLinesStreamer::builder(dir.path())
+            .with_start_position(position)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines, &["partial\n"]);
+    }
+
+    #[test]
+    fn record_prefix() {
+        let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/prefixed-dir")
+            .with_record_prefix("[LOG] ".to_string())
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines, &["started\n", "working\n", "resumed\n", "done\n"]);
+
+        let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/prefixed-dir")
+            .with_record_prefix_strip_only("[LOG] ".to_string())
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(
+            lines,
+            &[
+                "started\n",
+                "stack trace line\n",
+                "working\n",
+                "resumed\n",
+                "  [LOG] indented\n",
+                "done\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn reverse_line_order() {
+        let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_reverse_line_order(true)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(lines[0], "line three from messages\n");
+        assert_eq!(lines[2], "line one from messages\n");
+        assert_eq!(lines[3], "line three from messages.1\n");
+        assert_eq!(lines[14], "line one from messages.20\n");
+
+        let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_reverse_line_order(true)
+            .with_take_last_lines(2)
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "line three from messages\n");
+        assert_eq!(lines[1], "line two from messages\n");
+    }
+
+    #[test]
+    fn take_last_lines() {
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_take_last_lines(2)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        let preloaded = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_take_last_lines(2)
+            .with_preload(true)
+            .build()
+            .unwrap();
+        assert_eq!(preloaded.collect::<Vec<String>>(), lines);
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "line two from messages\n");
+        assert_eq!(lines[1], "line three from messages\n");
+        assert_eq!(lines[9], "line three from messages.20\n");
+        for file in &[
+            "messages",
+            "messages.1",
+            "messages.2",
+            "messages.10",
+            "messages.20",
+        ] {
+            let from_file = format!("from {}\n", file);
+            assert_eq!(lines.iter().filter(|l| l.ends_with(&from_file)).count(), 2);
+        }
+
+        let streamer = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+            .with_take_last_lines(0)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.count(), 0);
     }
 }
@@ -0,0 +1,76 @@
+use crate::DirectoryLinesStreamer;
+
+/// Number of lines read with each line ending, see
+/// `DirectoryLinesStreamerBuilder::with_line_ending_statistics()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineEndingStats {
+    /// Lines ending with `\n`
+    pub lf: u64,
+    /// Lines ending with `\r\n`
+    pub crlf: u64,
+    /// Lines ending with a bare `\r`
+    pub cr: u64,
+    /// Lines without line ending: incomplete last lines, or lines ending with another record
+    /// delimiter
+    pub none: u64,
+}
+
+impl LineEndingStats {
+    /// Count the line ending of `line`, as read from a file.
+    pub(crate) fn record(&mut self, line: &[u8]) {
+        if line.ends_with(b"\r\n") {
+            self.crlf += 1;
+        } else if line.ends_with(b"\n") {
+            self.lf += 1;
+        } else if line.ends_with(b"\r") {
+            self.cr += 1;
+        } else {
+            self.none += 1;
+        }
+    }
+}
+
+impl DirectoryLinesStreamer {
+    /// Line endings of the lines read so far, all zero unless enabled with
+    /// `with_line_ending_statistics()`.
+    pub fn line_ending_stats(&self) -> &LineEndingStats {
+        &self.line_ending_stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_ending_statistics() {
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/line-endings-dir")
+            .with_line_ending_statistics(true)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.by_ref().count(), 8);
+        assert_eq!(
+            *streamer.line_ending_stats(),
+            LineEndingStats {
+                lf: 3,
+                crlf: 3,
+                cr: 1,
+                none: 1,
+            }
+        );
+
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/line-endings-dir").unwrap();
+        assert_eq!(streamer.by_ref().count(), 8);
+        assert_eq!(*streamer.line_ending_stats(), LineEndingStats::default());
+
+        // Counted before the delimiter is replaced by a NUL byte
+        let mut streamer = DirectoryLinesStreamer::builder("fixtures/line-endings-dir")
+            .with_line_ending_statistics(true)
+            .with_nul_terminated_lines(true)
+            .build()
+            .unwrap();
+        assert!(streamer.by_ref().all(|line| line.ends_with('\0')));
+        assert_eq!(streamer.line_ending_stats().crlf, 3);
+        assert_eq!(streamer.line_ending_stats().lf, 3);
+    }
+}
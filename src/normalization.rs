@@ -0,0 +1,59 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode normalization form applied to lines, see
+/// `DirectoryLinesStreamerBuilder::with_utf8_normalization()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationForm {
+    /// Canonical composition
+    Nfc,
+    /// Canonical decomposition
+    Nfd,
+    /// Compatibility composition
+    Nfkc,
+    /// Compatibility decomposition
+    Nfkd,
+}
+
+/// Normalize `line` to `form`, without reallocating lines that are plain ASCII.
+pub(crate) fn normalize(line: String, form: NormalizationForm) -> String {
+    if line.is_ascii() {
+        return line;
+    }
+    match form {
+        NormalizationForm::Nfc => line.nfc().collect(),
+        NormalizationForm::Nfd => line.nfd().collect(),
+        NormalizationForm::Nfkc => line.nfkc().collect(),
+        NormalizationForm::Nfkd => line.nfkd().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DirectoryLinesStreamer;
+
+    use super::*;
+
+    #[test]
+    fn utf8_normalization() {
+        let lines: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/nfd-dir")
+            .unwrap()
+            .collect();
+        assert_eq!(lines[0], "cafe\u{301}\n");
+
+        let streamer = DirectoryLinesStreamer::builder("fixtures/nfd-dir")
+            .with_utf8_normalization(NormalizationForm::Nfc)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        assert_eq!(lines, &["caf\u{e9}\n", "r\u{e9}sum\u{e9}\n", "plain\n"]);
+
+        assert_eq!(
+            normalize("caf\u{e9}".to_string(), NormalizationForm::Nfd),
+            "cafe\u{301}"
+        );
+        assert_eq!(
+            normalize("\u{fb01}".to_string(), NormalizationForm::Nfkc),
+            "fi"
+        );
+    }
+}
@@ -0,0 +1,667 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom},
+    ops::BitOr,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::builder::Options;
+
+/// Size and modification time of a file, see
+/// `DirectoryLinesStreamerBuilder::with_metadata_source()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualMetadata {
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// Metadata of `path`, from the metadata source if any, or from the filesystem.
+pub(crate) fn file_metadata(path: &Path, options: &Options) -> Option<VirtualMetadata> {
+    match &options.metadata_source {
+        Some(metadata_source) => metadata_source(path),
+        None => {
+            let metadata = fs::metadata(options.physical_path(path)).ok()?;
+            Some(VirtualMetadata {
+                size: metadata.len(),
+                modified: metadata.modified().ok()?,
+            })
+        }
+    }
+}
+
+/// Platform-specific flags passed to `open(2)` when opening files.
+///
+/// Flags are only applied on Linux; on other platforms they are ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenFlags {
+    bits: i32,
+}
+
+impl OpenFlags {
+    /// Do not update the file's last access time (`O_NOATIME`).
+    ///
+    /// The kernel only allows it for files owned by the process (or with `CAP_FOWNER`);
+    /// other files are opened without it.
+    #[cfg(target_os = "linux")]
+    pub const NOATIME: OpenFlags = OpenFlags {
+        bits: libc::O_NOATIME,
+    };
+    #[cfg(not(target_os = "linux"))]
+    pub const NOATIME: OpenFlags = OpenFlags { bits: 0 };
+
+    pub const fn empty() -> OpenFlags {
+        OpenFlags { bits: 0 }
+    }
+
+    /// Use raw `open(2)` flags, for example `libc::O_DIRECT`.
+    pub const fn from_bits(bits: i32) -> OpenFlags {
+        OpenFlags { bits }
+    }
+
+    pub const fn bits(self) -> i32 {
+        self.bits
+    }
+
+    pub const fn contains(self, other: OpenFlags) -> bool {
+        self.bits & other.bits == other.bits
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = OpenFlags;
+
+    fn bitor(self, rhs: OpenFlags) -> OpenFlags {
+        OpenFlags {
+            bits: self.bits | rhs.bits,
+        }
+    }
+}
+
+/// First bytes of an LZ4 frame.
+#[cfg(feature = "lz4")]
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4D, 0x18];
+
+/// Decoded content of an opened file.
+pub(crate) enum FileReader {
+    /// File read as is, which can be seeked
+    File(File),
+    Other(Box<dyn Read + Send>),
+}
+
+impl FileReader {
+    pub(crate) fn new<R>(reader: R) -> FileReader
+    where
+        R: Read + Send + 'static,
+    {
+        FileReader::Other(Box::new(reader))
+    }
+
+    /// Skip the first `offset` bytes of the content, before anything is read: by seeking in
+    /// files read as is, by reading through the others (decompressed, or FIFOs).
+    pub(crate) fn skip_to(&mut self, offset: u64) -> io::Result<()> {
+        if let FileReader::File(file) = self {
+            if file.seek(SeekFrom::Start(offset)).is_ok() {
+                return Ok(());
+            }
+        }
+        io::copy(&mut self.take(offset), &mut io::sink()).map(|_| ())
+    }
+}
+
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FileReader::File(file) => file.read(buf),
+            FileReader::Other(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl fmt::Debug for FileReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("FileReader")
+    }
+}
+
+/// Open `path` and decompress its content if needed.
+pub(crate) fn open_reader(path: &Path, options: &Options) -> io::Result<FileReader> {
+    let physical_path = options.physical_path(path);
+    let file = match options.open_timeout {
+        Some(timeout) => open_source_with_timeout(physical_path, options, timeout)?,
+        None => open_source(&physical_path, options)?,
+    };
+    let reader = decode(path, file, options)?;
+    let reader = match options.read_timeout {
+        Some(timeout) => FileReader::new(TimeoutReader::new(reader, timeout)),
+        None => reader,
+    };
+    Ok(match options.per_line_timeout {
+        Some(timeout) => FileReader::new(LineTimeoutReader::new(reader, timeout, options.newline)),
+        None => reader,
+    })
+}
+
+/// Like `open_reader()`, retrying as configured with `with_open_retries()`.
+///
+/// Missing files are not retried.
+pub(crate) fn open_reader_with_retries(path: &Path, options: &Options) -> io::Result<FileReader> {
+    let mut attempt = 0;
+    loop {
+        match open_reader(path, options) {
+            Err(error)
+                if attempt < options.open_retries && error.kind() != io::ErrorKind::NotFound =>
+            {
+                let delay = options.open_backoff.delay(attempt);
+                log::debug!("Retrying to open {:?} in {:?}: {}", path, delay, error);
+                match &options.sleep {
+                    Some(sleep) => sleep(delay),
+                    None => thread::sleep(delay),
+                }
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether `error`, returned by `open_reader()`, means that opening took too long.
+pub(crate) fn is_open_timeout(error: &io::Error, options: &Options) -> bool {
+    options.open_timeout.is_some() && error.kind() == io::ErrorKind::TimedOut
+}
+
+/// Open `path` from a background thread, failing with `io::ErrorKind::TimedOut` if it takes
+/// more than `timeout`.
+///
+/// A thread that is still opening the file on timeout is detached: it closes the file and
+/// exits once the open returns.
+fn open_source_with_timeout(
+    path: PathBuf,
+    options: &Options,
+    timeout: Duration,
+) -> io::Result<FileReader> {
+    let (sender, receiver) = mpsc::channel();
+    let options = options.clone();
+    thread::spawn(move || {
+        // Fails (dropping the file) if the caller timed out
+        let _ = sender.send(open_source(&path, &options));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "open timed out")),
+    }
+}
+
+/// Reads from a background thread, failing with `io::ErrorKind::TimedOut` when a read
+/// takes more than `timeout`. Once timed out, it behaves as if at end of file.
+pub(crate) struct TimeoutReader {
+    // Size of the reads requested to the thread
+    requests: mpsc::Sender<usize>,
+    responses: mpsc::Receiver<io::Result<Vec<u8>>>,
+    timeout: Duration,
+    timed_out: bool,
+}
+
+impl TimeoutReader {
+    pub(crate) fn new(mut reader: FileReader, timeout: Duration) -> TimeoutReader {
+        let (requests, requests_receiver) = mpsc::channel::<usize>();
+        let (responses_sender, responses) = mpsc::channel();
+        // Stops when the `TimeoutReader` is dropped, unless stuck in a read.
+        thread::spawn(move || {
+            for len in requests_receiver {
+                let mut buf = vec![0; len];
+                let response = reader.read(&mut buf).map(|nb_bytes| {
+                    buf.truncate(nb_bytes);
+                    buf
+                });
+                if responses_sender.send(response).is_err() {
+                    break;
+                }
+            }
+        });
+        TimeoutReader {
+            requests,
+            responses,
+            timeout,
+            timed_out: false,
+        }
+    }
+}
+
+impl Read for TimeoutReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.timed_out {
+            return Ok(0);
+        }
+        let stopped = || io::Error::other("reader thread stopped");
+        self.requests.send(buf.len()).map_err(|_| stopped())?;
+        match self.responses.recv_timeout(self.timeout) {
+            Ok(response) => {
+                let data = response?;
+                buf[..data.len()].copy_from_slice(&data);
+                Ok(data.len())
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                self.timed_out = true;
+                Err(io::Error::new(io::ErrorKind::TimedOut, "read timed out"))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(stopped()),
+        }
+    }
+}
+
+/// Error of a `LineTimeoutReader` timing out, telling it apart from other timeouts.
+#[derive(Debug)]
+struct LineTimedOut;
+
+impl fmt::Display for LineTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("line read timed out")
+    }
+}
+
+impl std::error::Error for LineTimedOut {}
+
+/// Whether `error` comes from a `LineTimeoutReader` timing out.
+pub(crate) fn is_line_timeout(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<LineTimedOut>())
+}
+
+/// Reads whole lines from a background thread, failing with `io::ErrorKind::TimedOut` when
+/// a line takes more than `timeout`. Once timed out, it behaves as if at end of file.
+pub(crate) struct LineTimeoutReader {
+    requests: mpsc::Sender<()>,
+    responses: mpsc::Receiver<io::Result<Vec<u8>>>,
+    timeout: Duration,
+    timed_out: bool,
+    // Line received from the thread, and how much of it was returned
+    line: Vec<u8>,
+    consumed: usize,
+}
+
+impl LineTimeoutReader {
+    pub(crate) fn new(reader: FileReader, timeout: Duration, newline: u8) -> LineTimeoutReader {
+        use std::io::{BufRead, BufReader};
+
+        let (requests, requests_receiver) = mpsc::channel::<()>();
+        let (responses_sender, responses) = mpsc::channel();
+        // Stops when the `LineTimeoutReader` is dropped, unless stuck in a read.
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            for () in requests_receiver {
+                let mut line = Vec::new();
+                let response = reader.read_until(newline, &mut line).map(|_| line);
+                if responses_sender.send(response).is_err() {
+                    break;
+                }
+            }
+        });
+        LineTimeoutReader {
+            requests,
+            responses,
+            timeout,
+            timed_out: false,
+            line: Vec::new(),
+            consumed: 0,
+        }
+    }
+}
+
+impl Read for LineTimeoutReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.timed_out {
+            return Ok(0);
+        }
+        if self.consumed == self.line.len() {
+            let stopped = || io::Error::other("reader thread stopped");
+            self.requests.send(()).map_err(|_| stopped())?;
+            match self.responses.recv_timeout(self.timeout) {
+                Ok(response) => {
+                    self.line = response?;
+                    self.consumed = 0;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    self.timed_out = true;
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, LineTimedOut));
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(stopped()),
+            }
+        }
+        let remaining = &self.line[self.consumed..];
+        let nb_bytes = remaining.len().min(buf.len());
+        buf[..nb_bytes].copy_from_slice(&remaining[..nb_bytes]);
+        self.consumed += nb_bytes;
+        Ok(nb_bytes)
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn decode(path: &Path, file: FileReader, options: &Options) -> io::Result<FileReader> {
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        return Ok(FileReader::new(flate2::read::MultiGzDecoder::new(file)));
+    }
+    decode_lz4(path, file, options)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn decode(path: &Path, file: FileReader, options: &Options) -> io::Result<FileReader> {
+    decode_lz4(path, file, options)
+}
+
+#[cfg(not(feature = "lz4"))]
+fn decode_lz4(_path: &Path, file: FileReader, _options: &Options) -> io::Result<FileReader> {
+    Ok(file)
+}
+
+#[cfg(feature = "lz4")]
+fn decode_lz4(path: &Path, file: FileReader, options: &Options) -> io::Result<FileReader> {
+    use std::io::{BufRead, BufReader};
+
+    if path.extension().is_some_and(|extension| extension == "lz4") {
+        return Ok(FileReader::new(lz4_flex::frame::FrameDecoder::new(file)));
+    }
+    if options.magic_byte_detection {
+        let mut reader = BufReader::new(file);
+        if reader.fill_buf()?.starts_with(&LZ4_MAGIC) {
+            return Ok(FileReader::new(lz4_flex::frame::FrameDecoder::new(reader)));
+        }
+        return Ok(FileReader::new(reader));
+    }
+    Ok(file)
+}
+
+/// Open `path` with the custom open function if any, or as a file.
+pub(crate) fn open_source(path: &Path, options: &Options) -> io::Result<FileReader> {
+    match &options.custom_open {
+        Some(custom_open) => custom_open(path).map(FileReader::Other),
+        None => open_file(path, options).map(|file| {
+            advise_access_pattern(&file, options);
+            FileReader::File(file)
+        }),
+    }
+}
+
+/// Give the `posix_fadvise(2)` hints enabled in `options` for `file`.
+#[cfg(target_os = "linux")]
+fn advise_access_pattern(file: &File, options: &Options) {
+    use std::os::unix::io::AsRawFd;
+
+    for (enabled, advice) in [
+        (options.fadvise_sequential, libc::POSIX_FADV_SEQUENTIAL),
+        (options.fadvise_willneed, libc::POSIX_FADV_WILLNEED),
+    ] {
+        if !enabled {
+            continue;
+        }
+        // Safe: the file descriptor stays open during the call.
+        let result = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice) };
+        if result != 0 {
+            // Only a hint: reading works all the same.
+            log::debug!(
+                "posix_fadvise failed: {}",
+                io::Error::from_raw_os_error(result)
+            );
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_access_pattern(_file: &File, _options: &Options) {}
+
+/// Open `path` for reading, honoring the streamer's options.
+fn open_file(path: &Path, options: &Options) -> io::Result<File> {
+    if let Some(factory) = &options.open_options {
+        return factory(path).open(path);
+    }
+    let flags = options.open_flags;
+    if flags == OpenFlags::empty() {
+        return File::open(path);
+    }
+    match open_with_flags(path, flags) {
+        Err(ref e)
+            if e.kind() == io::ErrorKind::PermissionDenied
+                && flags.contains(OpenFlags::NOATIME)
+                && OpenFlags::NOATIME != OpenFlags::empty() =>
+        {
+            // `O_NOATIME` is refused for files we don't own; it is only an optimization.
+            let flags = OpenFlags::from_bits(flags.bits() & !OpenFlags::NOATIME.bits());
+            open_with_flags(path, flags)
+        }
+        result => result,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_with_flags(path: &Path, flags: OpenFlags) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(flags.bits())
+        .open(path)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_with_flags(path: &Path, _flags: OpenFlags) -> io::Result<File> {
+    OpenOptions::new().read(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        fs::{self, FileTimes},
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use crate::{DirectoryLinesStreamer, DirectoryLinesStreamerError};
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn open_flags_noatime() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages");
+        fs::write(&path, "line one\nline two\n").unwrap();
+        // An access time older than the modification time is always updated on `relatime` mounts.
+        let old_atime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_times(FileTimes::new().set_accessed(old_atime))
+            .unwrap();
+
+        let streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_open_flags(OpenFlags::NOATIME)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.count(), 2);
+
+        assert_eq!(fs::metadata(&path).unwrap().accessed().unwrap(), old_atime);
+    }
+
+    #[test]
+    fn skip_to() {
+        let path = Path::new("fixtures/non-empty-dir/messages");
+        let options = Options::default();
+        let mut reader = open_reader(path, &options).unwrap();
+        assert!(matches!(reader, FileReader::File(_)));
+        reader.skip_to(23).unwrap();
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "line two from messages\nline three from messages\n");
+
+        let mut reader = FileReader::new(File::open(path).unwrap());
+        reader.skip_to(23).unwrap();
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "line two from messages\nline three from messages\n");
+    }
+
+    #[test]
+    fn fadvise() {
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        for (sequential, willneed) in [(true, false), (false, true), (true, true)] {
+            let lines: Vec<String> = DirectoryLinesStreamer::builder("fixtures/non-empty-dir")
+                .with_fadvise_sequential(sequential)
+                .with_fadvise_willneed(willneed)
+                .build()
+                .unwrap()
+                .collect();
+            assert_eq!(lines, expected);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn open_flags_bits() {
+        let flags = OpenFlags::NOATIME | OpenFlags::from_bits(libc::O_CLOEXEC);
+        assert!(flags.contains(OpenFlags::NOATIME));
+        assert!(!OpenFlags::empty().contains(OpenFlags::NOATIME));
+        assert_eq!(flags.bits(), libc::O_NOATIME | libc::O_CLOEXEC);
+    }
+
+    /// Yields one line, then blocks
+    struct SlowReader {
+        first_read: bool,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.first_read {
+                self.first_read = false;
+                let line = b"line one\n";
+                buf[..line.len()].copy_from_slice(line);
+                Ok(line.len())
+            } else {
+                thread::sleep(Duration::from_millis(500));
+                Ok(0)
+            }
+        }
+    }
+
+    #[test]
+    fn read_timeout() {
+        let timeout = Duration::from_millis(50);
+        let options = Options {
+            read_timeout: Some(timeout),
+            ..Options::default()
+        };
+        let reader = TimeoutReader::new(FileReader::new(SlowReader { first_read: true }), timeout);
+        let streamer = DirectoryLinesStreamer::from_parts(
+            PathBuf::from("slow-dir"),
+            Vec::new(),
+            PathBuf::from("slow-dir/messages"),
+            FileReader::new(reader),
+            options,
+        );
+        let mut lines = streamer.try_lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "line one\n");
+        match lines.next().unwrap().unwrap_err() {
+            DirectoryLinesStreamerError::ReadTimeout(file, error_timeout) => {
+                assert_eq!(file, PathBuf::from("slow-dir/messages"));
+                assert_eq!(error_timeout, timeout);
+            }
+            error => panic!("Unexpected error: {}", error),
+        }
+        assert!(lines.next().is_none());
+    }
+
+    /// Yields one line, then another one byte at a time
+    struct TricklingReader {
+        lines: &'static [u8],
+        offset: usize,
+    }
+
+    impl Read for TricklingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.offset > 0 {
+                thread::sleep(Duration::from_millis(20));
+            }
+            let nb_bytes = match self.offset {
+                0 => 5,
+                _ => 1,
+            }
+            .min(self.lines.len() - self.offset);
+            buf[..nb_bytes].copy_from_slice(&self.lines[self.offset..self.offset + nb_bytes]);
+            self.offset += nb_bytes;
+            Ok(nb_bytes)
+        }
+    }
+
+    #[test]
+    fn per_line_timeout() {
+        let timeout = Duration::from_millis(100);
+        let options = Options {
+            per_line_timeout: Some(timeout),
+            read_timeout: Some(Duration::from_millis(50)),
+            ..Options::default()
+        };
+        let trickling = TricklingReader {
+            lines: b"fast\nslow line\n",
+            offset: 0,
+        };
+        let reader = TimeoutReader::new(FileReader::new(trickling), Duration::from_millis(50));
+        let reader = LineTimeoutReader::new(FileReader::new(reader), timeout, b'\n');
+        let streamer = DirectoryLinesStreamer::from_parts(
+            PathBuf::from("slow-dir"),
+            Vec::new(),
+            PathBuf::from("slow-dir/messages"),
+            FileReader::new(reader),
+            options,
+        );
+        let mut lines = streamer.try_lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "fast\n");
+        let start = Instant::now();
+        match lines.next().unwrap().unwrap_err() {
+            DirectoryLinesStreamerError::LineReadTimeout(file, line_id, error_timeout) => {
+                assert_eq!(file, PathBuf::from("slow-dir/messages"));
+                assert_eq!(line_id, 2);
+                assert_eq!(error_timeout, timeout);
+            }
+            error => panic!("Unexpected error: {}", error),
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= timeout && elapsed < Duration::from_millis(180));
+        assert!(lines.next().is_none());
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        for entry in fs::read_dir("fixtures/non-empty-dir").unwrap() {
+            let path = entry.unwrap().path();
+            // Some files have the `.lz4` extension, the others are detected
+            let mut file_name = path.file_name().unwrap().to_os_string();
+            if file_name == "messages.1" || file_name == "messages.20" {
+                file_name.push(".lz4");
+            }
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(
+                File::create(dir.path().join(file_name)).unwrap(),
+            );
+            encoder.write_all(&fs::read(&path).unwrap()).unwrap();
+            encoder.finish().unwrap();
+        }
+
+        let streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_magic_byte_detection(true)
+            .build()
+            .unwrap();
+        let lines: Vec<String> = streamer.collect();
+        let expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        assert_eq!(lines, expected);
+    }
+}
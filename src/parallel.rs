@@ -0,0 +1,61 @@
+use std::sync::mpsc;
+
+use crate::DirectoryLinesStreamer;
+
+impl DirectoryLinesStreamer {
+    /// Read the remaining lines in batches of `batch_size` lines, and call `f` on each batch
+    /// from the Rayon thread pool.
+    ///
+    /// Batches are processed while the next ones are read. Results are returned in batch order.
+    pub fn par_batch_process<B, F>(&mut self, batch_size: usize, f: F) -> Vec<B>
+    where
+        F: Fn(Vec<String>) -> B + Sync,
+        B: Send,
+    {
+        let batch_size = batch_size.max(1);
+        let (sender, receiver) = mpsc::channel();
+        let f = &f;
+        rayon::scope(|scope| {
+            let mut index = 0;
+            loop {
+                let batch: Vec<String> = Iterator::by_ref(&mut *self).take(batch_size).collect();
+                if batch.is_empty() {
+                    break;
+                }
+                let sender = sender.clone();
+                // The receiver outlives the scope, sending cannot fail
+                scope.spawn(move |_| sender.send((index, f(batch))).unwrap());
+                index += 1;
+            }
+        });
+        drop(sender);
+        let mut results: Vec<(usize, B)> = receiver.into_iter().collect();
+        results.sort_unstable_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn par_batch_process() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+        let batches = streamer.par_batch_process(4, |batch| batch);
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<usize>>(),
+            &[4, 4, 4, 2]
+        );
+        let lines: Vec<String> = batches.into_iter().flatten().collect();
+        let mut expected: Vec<String> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .collect();
+        expected.remove(0);
+        assert_eq!(lines, expected);
+        // The streamer is not consumed, but exhausted
+        assert_eq!(streamer.next(), None);
+        assert!(streamer.par_batch_process(4, |batch| batch).is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use std::{cmp::Ordering, path::PathBuf};
+#[cfg(feature = "serde_json")]
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+#[cfg(feature = "serde_json")]
+use serde::{Deserialize, Serialize};
+
+/// Where a streamer is in its directory, as returned by `DirectoryLinesStreamer::position()`.
+///
+/// A streamer built with `DirectoryLinesStreamerBuilder::with_start_position()` continues
+/// right after the last line read at that position.
+///
+/// Positions in the same directory are ordered by file index, then by byte offset. Positions
+/// in different directories are not comparable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde_json", derive(Serialize, Deserialize))]
+pub struct StreamPosition {
+    /// Directory being streamed
+    pub dir: PathBuf,
+    /// Index (0-based) of `file` in the sorted list of files
+    pub file_index: usize,
+    /// File the last line was read from
+    pub file: PathBuf,
+    /// Offset in `file` right after the last line read
+    pub byte_offset: u64,
+    /// Global line number of the last line read (0 before the first line)
+    pub line_number: usize,
+}
+
+impl PartialOrd for StreamPosition {
+    fn partial_cmp(&self, other: &StreamPosition) -> Option<Ordering> {
+        if self.dir != other.dir {
+            return None;
+        }
+        Some(
+            self.file_index
+                .cmp(&other.file_index)
+                .then(self.byte_offset.cmp(&other.byte_offset))
+                // Only break ties, to stay consistent with `PartialEq`
+                .then(self.line_number.cmp(&other.line_number))
+                .then_with(|| self.file.cmp(&other.file)),
+        )
+    }
+}
+
+/// Position inside the sorted list of files, tracked while reading.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FilePosition {
+    pub(crate) index: usize,
+    pub(crate) byte_offset: u64,
+    // Lines read from the current file
+    pub(crate) lines: usize,
+    // Offsets of the start of the last line read, in bytes and in characters
+    pub(crate) line_start: u64,
+    pub(crate) line_start_chars: u64,
+    // Characters read from the current file, only counted in `LineNumberingMode::CharOffset`
+    pub(crate) chars: u64,
+    // Offset of the current chunk of the file, see `with_file_size_limit()`
+    pub(crate) chunk_start: u64,
+}
+
+impl FilePosition {
+    /// Position before the first line of the file at `index`.
+    pub(crate) fn start_of(index: usize) -> FilePosition {
+        FilePosition {
+            index,
+            ..FilePosition::default()
+        }
+    }
+
+    /// Account for `line` having just been read from the current file.
+    pub(crate) fn advance(&mut self, line: &[u8], count_chars: bool) {
+        self.line_start = self.byte_offset;
+        self.line_start_chars = self.chars;
+        self.byte_offset += line.len() as u64;
+        if count_chars {
+            // Count the bytes starting a UTF-8 sequence, like lossy decoding would (roughly)
+            self.chars += line.iter().filter(|byte| (**byte & 0xC0) != 0x80).count() as u64;
+        }
+        self.lines += 1;
+    }
+}
+
+#[cfg(feature = "serde_json")]
+pub(crate) const CHECKPOINT_VERSION: u32 = 1;
+
+/// Content of the files written by `DirectoryLinesStreamer::checkpoint_file()`.
+#[cfg(feature = "serde_json")]
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    pub(crate) version: u32,
+    pub(crate) position: StreamPosition,
+}
+
+#[cfg(feature = "serde_json")]
+impl Checkpoint {
+    /// Write the checkpoint to `path` atomically: to a temporary file of the same directory,
+    /// synced to disk, then renamed over `path`. The previous checkpoint is kept on failure.
+    pub(crate) fn write(&self, path: &Path) -> io::Result<()> {
+        let mut temporary_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?
+            .to_os_string();
+        temporary_name.push(format!(".{}.tmp", std::process::id()));
+        let temporary_path = path.with_file_name(temporary_name);
+        let result = self
+            .write_synced(&temporary_path)
+            .and_then(|()| fs::rename(&temporary_path, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary_path);
+        }
+        result
+    }
+
+    fn write_synced(&self, path: &Path) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(dir: &str, file_index: usize, byte_offset: u64) -> StreamPosition {
+        StreamPosition {
+            dir: PathBuf::from(dir),
+            file_index,
+            file: PathBuf::from(format!("{}/messages.{}", dir, file_index)),
+            byte_offset,
+            line_number: file_index * 3,
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        let earliest = position("logs", 0, 100);
+        assert!(earliest < position("logs", 1, 0));
+        assert!(position("logs", 1, 10) < position("logs", 1, 20));
+        assert!(position("logs", 2, 0) > position("logs", 1, 20));
+        assert_eq!(
+            earliest.partial_cmp(&position("logs", 0, 100)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(earliest, position("logs", 0, 100));
+
+        assert_eq!(earliest.partial_cmp(&position("other-logs", 0, 100)), None);
+        assert_eq!(earliest.partial_cmp(&position("other-logs", 1, 0)), None);
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    builder::Options,
+    open::{open_reader_with_retries, FileReader},
+};
+
+type PrereadFile = (PathBuf, io::Result<Vec<u8>>);
+
+/// Files read in memory ahead of the streamer by background threads, see
+/// `DirectoryLinesStreamerBuilder::with_concurrent_file_preread()`.
+#[derive(Debug)]
+pub(crate) struct Prereader {
+    // Files given to the threads, and not taken yet
+    files: HashSet<PathBuf>,
+    receiver: Receiver<PrereadFile>,
+    // Files received before the streamer asked for them
+    received: HashMap<PathBuf, io::Result<Vec<u8>>>,
+}
+
+impl Prereader {
+    /// Start `n` threads reading `files`, in order.
+    ///
+    /// Files known not to be regular files, like FIFOs, are left to the streamer to open: they
+    /// could block a thread, or never end.
+    pub(crate) fn spawn<I>(files: I, n: usize, options: &Options) -> Prereader
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        let queue: VecDeque<PathBuf> = files
+            .into_iter()
+            .filter(|path| {
+                !matches!(fs::metadata(options.physical_path(path)),
+                    Ok(metadata) if !metadata.is_file())
+            })
+            .collect();
+        let files = queue.iter().cloned().collect();
+        let queue = Arc::new(Mutex::new(queue));
+        let (sender, receiver) = mpsc::sync_channel(n);
+        for _ in 0..n {
+            let queue = Arc::clone(&queue);
+            let sender = sender.clone();
+            let options = options.clone();
+            thread::spawn(move || preread_files(&queue, &sender, &options));
+        }
+        Prereader {
+            files,
+            receiver,
+            received: HashMap::new(),
+        }
+    }
+
+    /// Whether `path` is read by the threads, and not taken yet.
+    #[cfg(test)]
+    pub(crate) fn is_preread(&self, path: &Path) -> bool {
+        self.files.contains(path)
+    }
+
+    /// Content of `path`, waiting for it to be read if needed.
+    ///
+    /// Returns `None` if `path` is not one of the files read by the threads.
+    pub(crate) fn take(&mut self, path: &Path) -> Option<io::Result<FileReader>> {
+        if !self.files.remove(path) {
+            return None;
+        }
+        loop {
+            if let Some(content) = self.received.remove(path) {
+                return Some(content.map(|content| FileReader::new(io::Cursor::new(content))));
+            }
+            // Fails once all threads are done
+            let (received_path, content) = self.receiver.recv().ok()?;
+            self.received.insert(received_path, content);
+        }
+    }
+}
+
+/// Read the files of `queue` until it is empty, or until the streamer is dropped.
+fn preread_files(
+    queue: &Mutex<VecDeque<PathBuf>>,
+    sender: &SyncSender<PrereadFile>,
+    options: &Options,
+) {
+    loop {
+        let path = match queue.lock().unwrap().pop_front() {
+            Some(path) => path,
+            None => return,
+        };
+        log::debug!("Prereading {:?}", path);
+        let content = open_reader_with_retries(&path, options).and_then(|mut reader| {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            Ok(content)
+        });
+        if sender.send((path, content)).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::{io::BufReader, path::Path};
+
+use crate::{
+    open::open_reader_with_retries, position::FilePosition, DirectoryLinesStreamer,
+    DirectoryLinesStreamerError,
+};
+
+/// Device and inode of a file.
+pub(crate) type FileIdentity = (u64, u64);
+
+#[cfg(unix)]
+pub(crate) fn file_identity(path: &Path) -> Option<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path)
+        .ok()
+        .map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+/// Files cannot be identified on platforms other than Unix.
+#[cfg(not(unix))]
+pub(crate) fn file_identity(_path: &Path) -> Option<FileIdentity> {
+    None
+}
+
+impl DirectoryLinesStreamer {
+    /// Remember which file the current path names, see `with_file_rename_detection()`.
+    pub(crate) fn record_opened_file_identity(&mut self) {
+        if self.options.file_rename_detection {
+            self.opened_file_identity = self.current_file_identity();
+        }
+    }
+
+    /// Identity of the file the current path names, on the filesystem.
+    fn current_file_identity(&self) -> Option<FileIdentity> {
+        file_identity(&self.options.physical_path(self.current_physical_path()))
+    }
+
+    /// Whether the rename of the current file should be checked before reading a new line.
+    pub(crate) fn rename_check_due(&self) -> bool {
+        self.options.file_rename_detection
+            && self
+                .file_position
+                .lines
+                .is_multiple_of(self.options.rename_check_interval.max(1))
+    }
+
+    /// Read the current path from its start if it now names another file than the one
+    /// opened. Returns whether the new file was opened.
+    ///
+    /// The path looked up is the one on disk, not `opened_file_path` which may have had a
+    /// prefix stripped, see `with_path_prefix_strip()`.
+    pub(crate) fn detect_file_rename(&mut self) -> bool {
+        if !self.options.file_rename_detection {
+            return false;
+        }
+        let path = self.current_physical_path().to_path_buf();
+        let identity = self.current_file_identity();
+        if identity.is_none() || identity == self.opened_file_identity {
+            return false;
+        }
+        log::info!(
+            "{:?} was renamed, reading the new file",
+            self.opened_file_path
+        );
+        match open_reader_with_retries(&path, &self.options) {
+            Ok(reader) => {
+                self.audit_close();
+                self.opened_file = BufReader::new(reader);
+                self.opened_file_empty = true;
+                self.opened_file_identity = identity;
+                self.file_position = FilePosition::start_of(self.file_position.index);
+                self.audit_open();
+                true
+            }
+            Err(error) => {
+                // Keep reading the renamed file; opening is tried again on the next check.
+                let file = self.opened_file_path.to_path_buf();
+                self.emit_error(DirectoryLinesStreamerError::OpenFile { file, error });
+                false
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, path::Path, thread};
+
+    use crate::DirectoryLinesStreamer;
+
+    #[test]
+    fn file_rename_detection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages");
+        fs::write(&path, "old one\nold two\nold three\n").unwrap();
+
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_file_rename_detection(true)
+            .with_rename_check_interval(1)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.next().unwrap(), "old one\n");
+
+        // Rotate the file, like logrotate
+        let rotated = dir.path().join("messages.1");
+        thread::spawn(move || {
+            fs::rename(&path, rotated).unwrap();
+            fs::write(&path, "new one\nnew two\n").unwrap();
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(streamer.next().unwrap(), "new one\n");
+        assert_eq!(streamer.line_number(), 2);
+        assert_eq!(streamer.next().unwrap(), "new two\n");
+        assert_eq!(streamer.next(), None);
+
+        // Between checks, the rename is detected at the end of the file.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages");
+        fs::write(&path, "old one\n").unwrap();
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_file_rename_detection(true)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.next().unwrap(), "old one\n");
+        fs::rename(&path, dir.path().join("messages.1")).unwrap();
+        fs::write(&path, "new one\n").unwrap();
+        assert_eq!(streamer.next().unwrap(), "new one\n");
+        assert_eq!(streamer.next(), None);
+
+        // Without detection, the renamed file is read to its end.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages");
+        fs::write(&path, "old one\nold two\n").unwrap();
+        let mut streamer = DirectoryLinesStreamer::from_dir(dir.path()).unwrap();
+        assert_eq!(streamer.next().unwrap(), "old one\n");
+        fs::rename(&path, dir.path().join("messages.1")).unwrap();
+        fs::write(&path, "new one\n").unwrap();
+        assert_eq!(streamer.next().unwrap(), "old two\n");
+        assert_eq!(streamer.next(), None);
+    }
+
+    #[test]
+    fn file_rename_detection_with_path_prefix_strip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages");
+        fs::write(&path, "old one\nold two\n").unwrap();
+
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_path_prefix_strip(dir.path())
+            .with_file_rename_detection(true)
+            .with_rename_check_interval(1)
+            .build()
+            .unwrap();
+        assert_eq!(streamer.next().unwrap(), "old one\n");
+        assert_eq!(streamer.current_file(), Path::new("messages"));
+
+        fs::rename(&path, dir.path().join("messages.1")).unwrap();
+        fs::write(&path, "new one\n").unwrap();
+        assert_eq!(streamer.next().unwrap(), "new one\n");
+        assert_eq!(streamer.current_file(), Path::new("messages"));
+        assert_eq!(streamer.next(), None);
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    borrow::Cow,
+    cmp::{Ordering, Reverse},
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs, mem,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{builder::Options, open::file_metadata, rng::SplitMix64};
+
+/// Order files are read in, see `DirectoryLinesStreamer::detected_sort_order()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SortOrder {
+    /// Natural order of the file names: numeric segments compared by value, the rest byte by
+    /// byte. Covers numeric suffixes (`app.log.2` before `app.log.10`) as well as timestamp
+    /// prefixes (`2024-01-02-app.log` before `2024-01-10-app.log`).
+    AlphanumericAscending,
+}
+
+/// Files kept in each directory, see
+/// `DirectoryLinesStreamerBuilder::with_select_files_per_directory()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectMode {
+    /// The first `n` files in natural order.
+    First(usize),
+    /// The `n` most recently modified files.
+    Newest(usize),
+    /// The `n` least recently modified files.
+    Oldest(usize),
+    /// `n` files picked at random, the same ones for the same seed.
+    Random(usize, u64),
+}
+
+impl SelectMode {
+    fn max_files(self) -> usize {
+        match self {
+            SelectMode::First(n)
+            | SelectMode::Newest(n)
+            | SelectMode::Oldest(n)
+            | SelectMode::Random(n, _) => n,
+        }
+    }
+}
+
+/// Keep at most `select_mode.max_files()` files of each directory, in the same order.
+pub(crate) fn select_files_per_directory(
+    files: &mut Vec<PathBuf>,
+    select_mode: SelectMode,
+    options: &Options,
+) {
+    let mut by_directory: HashMap<&Path, Vec<&PathBuf>> = HashMap::new();
+    for path in files.iter() {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        by_directory.entry(dir).or_default().push(path);
+    }
+    let max_files = select_mode.max_files();
+    let mut omitted: HashSet<PathBuf> = HashSet::new();
+    for (dir, mut dir_files) in by_directory {
+        if dir_files.len() <= max_files {
+            continue;
+        }
+        log::debug!(
+            "Keeping {} of the {} files of {:?}",
+            max_files,
+            dir_files.len(),
+            dir
+        );
+        // Natural order first, to break ties and not depend on the scan order
+        dir_files.sort_by(|a, b| compare_file_names(a, b, options));
+        let modified = |path: &&PathBuf| {
+            file_metadata(path, options).map_or(SystemTime::UNIX_EPOCH, |m| m.modified)
+        };
+        match select_mode {
+            SelectMode::First(_) => {}
+            SelectMode::Newest(_) => dir_files.sort_by_cached_key(|p| Reverse(modified(p))),
+            SelectMode::Oldest(_) => dir_files.sort_by_cached_key(modified),
+            SelectMode::Random(_, seed) => shuffle(&mut dir_files, seed),
+        }
+        omitted.extend(dir_files[max_files..].iter().map(|path| path.to_path_buf()));
+    }
+    files.retain(|path| !omitted.contains(path));
+}
+
+/// Fisher-Yates shuffle of `items`, seeded with `seed`.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = SplitMix64::new(seed);
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.below(i + 1));
+    }
+}
+
+/// Sort the files in natural order, which will place `file-2` before `file-11`.
+///
+/// Only file names are compared, so that in recursive mode files with the same name in
+/// different directories are grouped together.
+pub(crate) fn sort_files(files: &mut [PathBuf], options: &Options) {
+    if let Some(priority) = &options.file_priority {
+        // Call `priority` once per file, instead of on every comparison
+        let mut keyed: Vec<(i64, PathBuf)> = files
+            .iter()
+            .map(|path| (priority(path), path.clone()))
+            .collect();
+        sort_by(&mut keyed, options, |(a_priority, a), (b_priority, b)| {
+            b_priority
+                .cmp(a_priority)
+                .then_with(|| compare_file_names(a, b, options))
+        });
+        for (file, (_, path)) in files.iter_mut().zip(keyed) {
+            *file = path;
+        }
+    } else if options.symlink_target_sort {
+        // Resolve each link once, instead of on every comparison
+        let mut keyed: Vec<(PathBuf, PathBuf)> = files
+            .iter()
+            .map(|path| (symlink_target(path), path.clone()))
+            .collect();
+        sort_by(&mut keyed, options, |(a, _), (b, _)| {
+            compare_file_names(a, b, options)
+        });
+        for (file, (_, path)) in files.iter_mut().zip(keyed) {
+            *file = path;
+        }
+    } else {
+        // Extract each sort key once, instead of on every comparison
+        let mut unsorted: Vec<PathBuf> = files.iter_mut().map(mem::take).collect();
+        let mut keyed: Vec<(Cow<'_, [u8]>, usize)> = unsorted
+            .iter()
+            .enumerate()
+            .map(|(index, path)| (sort_key(path, options), index))
+            .collect();
+        sort_by(&mut keyed, options, |(a, _), (b, _)| compare_natural(a, b));
+        let order: Vec<usize> = keyed.into_iter().map(|(_, index)| index).collect();
+        for (file, index) in files.iter_mut().zip(order) {
+            *file = mem::take(&mut unsorted[index]);
+        }
+    }
+}
+
+fn sort_by<T, F>(items: &mut [T], options: &Options, compare: F)
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    if options.stable_sort {
+        items.sort_by(compare);
+    } else {
+        items.sort_unstable_by(compare);
+    }
+}
+
+/// Target of `path` if it is a symbolic link, `path` itself otherwise.
+fn symlink_target(path: &Path) -> PathBuf {
+    fs::read_link(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Compare the file names of `a` and `b` in natural order.
+pub(crate) fn compare_file_names(a: &Path, b: &Path, options: &Options) -> Ordering {
+    compare_natural(&sort_key(a, options), &sort_key(b, options))
+}
+
+/// Bytes of the file name of `path` compared when sorting.
+fn sort_key<'a>(path: &'a Path, options: &Options) -> Cow<'a, [u8]> {
+    let file_name = path.file_name().unwrap_or(path.as_os_str());
+    let key = match &options.file_name_normalizer {
+        Some(normalizer) => Cow::Owned(os_str_bytes(&normalizer(file_name)).into_owned()),
+        None => os_str_bytes(file_name),
+    };
+    if options.case_insensitive_sort {
+        Cow::Owned(to_lowercase(key))
+    } else {
+        key
+    }
+}
+
+/// Compare `a` and `b` by splitting them in alternating non-numeric and numeric segments.
+///
+/// Numeric segments are compared by value, the others byte by byte.
+pub(crate) fn compare_natural(mut a: &[u8], mut b: &[u8]) -> Ordering {
+    loop {
+        let (first_a, first_b) = match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(first_a), Some(first_b)) => (first_a, first_b),
+        };
+        let (segment_a, rest_a) = split_segment(a);
+        let (segment_b, rest_b) = split_segment(b);
+        let ordering = if first_a.is_ascii_digit() && first_b.is_ascii_digit() {
+            compare_numbers(segment_a, segment_b)
+        } else {
+            segment_a.cmp(segment_b)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        a = rest_a;
+        b = rest_b;
+    }
+}
+
+/// Split the leading run of digits, or of non-digits, from the rest of `s`.
+fn split_segment(s: &[u8]) -> (&[u8], &[u8]) {
+    let is_numeric = s[0].is_ascii_digit();
+    let len = s
+        .iter()
+        .position(|c| c.is_ascii_digit() != is_numeric)
+        .unwrap_or(s.len());
+    s.split_at(len)
+}
+
+/// Compare two runs of digits by value, without overflowing on long runs.
+///
+/// Equal values are ordered by their number of leading zeros, fewer first.
+fn compare_numbers(a: &[u8], b: &[u8]) -> Ordering {
+    let trimmed_a = trim_leading_zeros(a);
+    let trimmed_b = trim_leading_zeros(b);
+    trimmed_a
+        .len()
+        .cmp(&trimmed_b.len())
+        .then_with(|| trimmed_a.cmp(trimmed_b))
+        .then_with(|| a.len().cmp(&b.len()))
+}
+
+fn trim_leading_zeros(digits: &[u8]) -> &[u8] {
+    let nb_zeros = digits.iter().take_while(|c| **c == b'0').count();
+    &digits[nb_zeros..]
+}
+
+fn os_str_bytes(file_name: &OsStr) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        Cow::Borrowed(file_name.as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        match file_name.to_string_lossy() {
+            Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+            Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+        }
+    }
+}
+
+/// Lowercase `name`, only for ASCII letters if it is not valid UTF-8.
+fn to_lowercase(name: Cow<'_, [u8]>) -> Vec<u8> {
+    match std::str::from_utf8(&name) {
+        Ok(s) => s.to_lowercase().into_bytes(),
+        Err(_) => name.to_ascii_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn fixture_order() {
+        let mut files: Vec<PathBuf> = fs::read_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        sort_files(&mut files, &Options::default());
+        let file_names: Vec<&str> = files
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            file_names,
+            &[
+                "messages",
+                "messages.1",
+                "messages.2",
+                "messages.10",
+                "messages.20"
+            ]
+        );
+    }
+
+    #[test]
+    fn file_name_normalizer() {
+        let nfc = "caf\u{e9}";
+        let nfd = "cafe\u{301}";
+        let mut files = vec![
+            PathBuf::from(format!("{}-2", nfc)),
+            PathBuf::from(format!("{}-10", nfd)),
+            PathBuf::from(format!("{}-1", nfd)),
+        ];
+        let mut options = Options::default();
+        sort_files(&mut files, &options);
+        assert_eq!(
+            files,
+            &[
+                PathBuf::from(format!("{}-1", nfd)),
+                PathBuf::from(format!("{}-10", nfd)),
+                PathBuf::from(format!("{}-2", nfc)),
+            ]
+        );
+
+        // Compose the only decomposed character used here
+        options.file_name_normalizer = Some(Arc::new(move |name: &OsStr| {
+            name.to_string_lossy().replace(nfd, nfc).into()
+        }));
+        sort_files(&mut files, &options);
+        assert_eq!(
+            files,
+            &[
+                PathBuf::from(format!("{}-1", nfd)),
+                PathBuf::from(format!("{}-2", nfc)),
+                PathBuf::from(format!("{}-10", nfd)),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_target_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            dir.path().join("b"),
+            dir.path().join("c"),
+            dir.path().join("a"),
+        ];
+        fs::write(&files[0], "").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("a.target"), &files[1]).unwrap();
+        // Resolved, even though the target does not exist
+        std::os::unix::fs::symlink("missing", &files[2]).unwrap();
+
+        let options = Options {
+            symlink_target_sort: true,
+            ..Options::default()
+        };
+        let mut sorted = files.clone();
+        sort_files(&mut sorted, &options);
+        assert_eq!(
+            sorted,
+            [files[1].clone(), files[0].clone(), files[2].clone()]
+        );
+
+        // Paths that are not links keep their own name
+        let unresolved = [dir.path().join("z"), dir.path().join("y")];
+        let mut sorted = unresolved.clone();
+        sort_files(&mut sorted, &options);
+        assert_eq!(sorted, [unresolved[1].clone(), unresolved[0].clone()]);
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(compare_natural(b"file-2", b"file-11"), Ordering::Less);
+        assert_eq!(compare_natural(b"file-11", b"file-11"), Ordering::Equal);
+        assert_eq!(compare_natural(b"file", b"file-1"), Ordering::Less);
+        assert_eq!(compare_natural(b"a10b2", b"a10b10"), Ordering::Less);
+        assert_eq!(compare_natural(b"1", b"a"), Ordering::Less);
+        assert_eq!(compare_natural(b"file-007", b"file-7"), Ordering::Greater);
+        assert_eq!(
+            compare_natural(b"99999999999999999999999", b"100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::{ffi::OsStr, path::Path};
+
+use chrono::{Datelike, NaiveDate};
+
+/// Zero-padded placeholders of a file name template, with their number of digits
+const PLACEHOLDERS: [(&str, usize); 4] = [("{YYYY}", 4), ("{YY}", 2), ("{MM}", 2), ("{DD}", 2)];
+
+/// File names formatted with a template for a date of `[start, end]`, see
+/// `DirectoryLinesStreamerBuilder::with_file_name_template_filter()`.
+#[derive(Debug, Clone)]
+pub(crate) struct FileNameTemplate {
+    template: String,
+    start: NaiveDate,
+    end: NaiveDate,
+}
+
+/// Date fields read from a file name, `None` when the template does not have them.
+#[derive(Debug, Default)]
+struct DateFields {
+    year: Option<u32>,
+    short_year: Option<u32>,
+    month: Option<u32>,
+    day: Option<u32>,
+}
+
+impl FileNameTemplate {
+    pub(crate) fn new(template: &str, start: NaiveDate, end: NaiveDate) -> FileNameTemplate {
+        FileNameTemplate {
+            template: template.to_string(),
+            start,
+            end,
+        }
+    }
+
+    /// Whether `file_name` is the template formatted with a date of `[start, end]`.
+    pub(crate) fn matches(&self, file_name: &OsStr) -> bool {
+        let fields = match file_name
+            .to_str()
+            .and_then(|file_name| parse_date_fields(file_name, &self.template))
+        {
+            Some(fields) => fields,
+            None => return false,
+        };
+        // Fields missing from the template can take any value of the range
+        for year in self.start.year()..=self.end.year() {
+            if fields.year.is_some_and(|value| year != value as i32)
+                || fields
+                    .short_year
+                    .is_some_and(|value| year.rem_euclid(100) != value as i32)
+            {
+                continue;
+            }
+            for month in fields.month.map_or(1..=12, |value| value..=value) {
+                for day in fields.day.map_or(1..=31, |value| value..=value) {
+                    if NaiveDate::from_ymd_opt(year, month, day)
+                        .is_some_and(|date| self.start <= date && date <= self.end)
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// The date fields of `file_name` formatted with `template`, or `None` if it does not match.
+fn parse_date_fields(mut file_name: &str, mut template: &str) -> Option<DateFields> {
+    let mut fields = DateFields::default();
+    while let Some(character) = template.chars().next() {
+        match PLACEHOLDERS
+            .iter()
+            .find(|(placeholder, _)| template.starts_with(placeholder))
+        {
+            Some((placeholder, digits)) => {
+                let value = file_name.get(..*digits)?;
+                if !value.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return None;
+                }
+                let value: u32 = value.parse().ok()?;
+                let field = match *placeholder {
+                    "{YYYY}" => &mut fields.year,
+                    "{YY}" => &mut fields.short_year,
+                    "{MM}" => &mut fields.month,
+                    _ => &mut fields.day,
+                };
+                // A placeholder repeated with another value
+                if field.is_some_and(|previous| previous != value) {
+                    return None;
+                }
+                *field = Some(value);
+                file_name = &file_name[*digits..];
+                template = &template[placeholder.len()..];
+            }
+            None => {
+                file_name = file_name.strip_prefix(character)?;
+                template = &template[character.len_utf8()..];
+            }
+        }
+    }
+    file_name.is_empty().then_some(fields)
+}
+
+/// Whether the file name of `path` is a date of `[start, end]` formatted with `pattern`, see
+/// `DirectoryLinesStreamer::from_dir_date_range()`.
+pub(crate) fn file_name_date_in_range(
+    path: &Path,
+    pattern: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> bool {
+    path.file_name()
+        .and_then(|file_name| file_name.to_str())
+        .and_then(|file_name| NaiveDate::parse_from_str(file_name, pattern).ok())
+        .is_some_and(|date| start <= date && date <= end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::{DirectoryLinesStreamer, DirectoryLinesStreamerError};
+
+    #[test]
+    fn file_name_template_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        for day in 1..=5 {
+            let name = format!("app-2024-01-{:02}.log", day);
+            fs::write(dir.path().join(&name), format!("{}\n", name)).unwrap();
+        }
+        fs::write(dir.path().join("other.log"), "other.log\n").unwrap();
+
+        let date = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        let lines: Vec<String> = DirectoryLinesStreamer::builder(dir.path())
+            .with_file_name_template_filter("app-{YYYY}-{MM}-{DD}.log", date(2), date(4))
+            .build()
+            .unwrap()
+            .collect();
+        assert_eq!(
+            lines,
+            &[
+                "app-2024-01-02.log\n",
+                "app-2024-01-03.log\n",
+                "app-2024-01-04.log\n"
+            ]
+        );
+
+        let template = FileNameTemplate::new("{YY}{MM}{DD}-{YYYY}", date(1), date(31));
+        assert!(template.matches(OsStr::new("240131-2024")));
+        assert!(!template.matches(OsStr::new("240131-2023")));
+        assert!(!template.matches(OsStr::new("240132-2024")));
+        assert!(!template.matches(OsStr::new("24131-2024")));
+        // Missing fields, or the same field twice
+        let template = FileNameTemplate::new("app-2024-01-{DD}.log", date(2), date(4));
+        assert!(template.matches(OsStr::new("app-2024-01-03.log")));
+        assert!(!template.matches(OsStr::new("app-2024-01-05.log")));
+        let template = FileNameTemplate::new("{DD}-{DD}", date(1), date(31));
+        assert!(template.matches(OsStr::new("03-03")));
+        assert!(!template.matches(OsStr::new("03-04")));
+        assert!(!FileNameTemplate::new("{DD}", date(4), date(2)).matches(OsStr::new("03")));
+    }
+
+    #[test]
+    fn from_dir_date_range() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "2024-01-30.log",
+            "2024-01-31.log",
+            "2024-02-01.log",
+            "2024-02-02.log",
+            "2024-02-01.log.1",
+            "notes.log",
+        ] {
+            fs::write(dir.path().join(name), format!("{}\n", name)).unwrap();
+        }
+
+        let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        let lines: Vec<String> = DirectoryLinesStreamer::from_dir_date_range(
+            dir.path(),
+            "%Y-%m-%d.log",
+            date(1, 31),
+            date(2, 1),
+        )
+        .unwrap()
+        .collect();
+        assert_eq!(lines, &["2024-01-31.log\n", "2024-02-01.log\n"]);
+
+        // No file name matches the pattern
+        assert!(matches!(
+            DirectoryLinesStreamer::from_dir_date_range(
+                dir.path(),
+                "%Y%m%d",
+                date(1, 1),
+                date(12, 31)
+            ),
+            Err(DirectoryLinesStreamerError::EmptyDirectory(_))
+        ));
+    }
+}
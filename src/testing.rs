@@ -0,0 +1,100 @@
+use std::{error, fmt, path::PathBuf};
+
+use crate::{builder::Options, sort::sort_files, strip_newline, DirectoryLinesStreamer};
+
+/// Sort `files` in the natural order of their file names, like the streamer does, for the
+/// benchmarks.
+pub fn sort_files_natural(files: &mut [PathBuf]) {
+    sort_files(files, &Options::default());
+}
+
+/// First difference found by `DirectoryLinesStreamer::compare_to_sorted_reference()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompareError {
+    /// Index (0-based) of the first differing line
+    pub index: usize,
+    /// Line read by the streamer, `None` if it ran out of lines
+    pub actual: Option<String>,
+    /// Expected line, `None` if the streamer has more lines than expected
+    pub expected: Option<String>,
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {} differs: got {:?}, expected {:?}",
+            self.index, self.actual, self.expected
+        )
+    }
+}
+
+impl error::Error for CompareError {}
+
+impl DirectoryLinesStreamer {
+    /// Check that the streamer yields exactly the `expected` lines, in order.
+    ///
+    /// Lines are compared without their trailing newline delimiter.
+    pub fn compare_to_sorted_reference(mut self, expected: &[&str]) -> Result<(), CompareError> {
+        let newline = self.options.newline;
+        let mut expected_lines = expected.iter();
+        let mut index = 0;
+        loop {
+            match (self.next(), expected_lines.next()) {
+                (None, None) => return Ok(()),
+                (Some(actual), Some(expected))
+                    if strip_newline(&actual, newline) == strip_newline(expected, newline) => {}
+                (actual, expected) => {
+                    return Err(CompareError {
+                        index,
+                        actual,
+                        expected: expected.map(|line| line.to_string()),
+                    })
+                }
+            }
+            index += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_to_sorted_reference() {
+        let lines = vec!["one".to_string(), "two".to_string(), "three".to_string()];
+        assert_eq!(
+            DirectoryLinesStreamer::from_lines(lines.clone())
+                .compare_to_sorted_reference(&["one", "two", "three\n"]),
+            Ok(())
+        );
+        assert_eq!(
+            DirectoryLinesStreamer::from_lines(lines.clone())
+                .compare_to_sorted_reference(&["one", "2", "three"]),
+            Err(CompareError {
+                index: 1,
+                actual: Some("two\n".to_string()),
+                expected: Some("2".to_string()),
+            })
+        );
+        assert_eq!(
+            DirectoryLinesStreamer::from_lines(lines.clone())
+                .compare_to_sorted_reference(&["one", "two"]),
+            Err(CompareError {
+                index: 2,
+                actual: Some("three\n".to_string()),
+                expected: None,
+            })
+        );
+        let error = DirectoryLinesStreamer::from_lines(lines)
+            .compare_to_sorted_reference(&["one", "two", "three", "four"])
+            .unwrap_err();
+        assert_eq!(error.index, 3);
+        assert_eq!(error.actual, None);
+        assert_eq!(
+            error.to_string(),
+            "line 3 differs: got None, expected Some(\"four\")"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{builder::list_files, DirectoryLinesStreamer, DirectoryLinesStreamerError};
+
+/// Stops a streamer from watching its directory, see `DirectoryLinesStreamer::watch_stopper()`.
+#[derive(Debug, Clone, Default)]
+pub struct WatchStopper(Arc<AtomicBool>);
+
+impl WatchStopper {
+    /// Make the streamer return `None` once it runs out of lines, instead of polling again.
+    pub fn stop_watching(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl DirectoryLinesStreamer {
+    /// Handle to stop watching the directory from another thread, see
+    /// `DirectoryLinesStreamerBuilder::with_watch_interval()`.
+    pub fn watch_stopper(&self) -> WatchStopper {
+        self.watch_stopper.clone()
+    }
+
+    /// Stop watching the directory, see `WatchStopper::stop_watching()`.
+    pub fn stop_watching(&self) {
+        self.watch_stopper.stop_watching();
+    }
+
+    /// Poll the directory until new files appear, and push them to `files`.
+    ///
+    /// Returns `false` when not watching, or once watching is stopped.
+    pub(crate) fn watch_for_new_files(&mut self) -> bool {
+        #[cfg(all(feature = "inotify", target_os = "linux"))]
+        if self.inotify_watch.is_some() {
+            return self.wait_for_inotify_events();
+        }
+        #[cfg(all(
+            feature = "kqueue",
+            any(
+                target_os = "macos",
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd"
+            )
+        ))]
+        if self.kqueue_watch.is_some() {
+            return self.wait_for_kqueue_events();
+        }
+        let interval = match self.options.watch_interval {
+            Some(interval) if !self.options.preload => interval,
+            _ => return false,
+        };
+        while !self.watch_stopper.is_stopped() {
+            thread::sleep(interval);
+            if self.watch_stopper.is_stopped() {
+                break;
+            }
+            match list_files(&self.dir, &self.options) {
+                Ok(files) => {
+                    let seen_files = &mut self.seen_files;
+                    let new_files: Vec<PathBuf> = files
+                        .into_iter()
+                        .filter(|file| seen_files.insert(file.clone()))
+                        .collect();
+                    if !new_files.is_empty() {
+                        log::debug!("New files: {:?}", new_files);
+                        self.all_files.extend(new_files.iter().cloned());
+                        // We'll `pop()` the last file first
+                        self.files.splice(0..0, new_files.into_iter().rev());
+                        return true;
+                    }
+                }
+                Err(error) => self.emit_error(DirectoryLinesStreamerError::Io(error)),
+            }
+        }
+        false
+    }
+
+    /// Add `new_files` to the files to read, keeping them all in order.
+    #[cfg(any(
+        all(feature = "inotify", target_os = "linux"),
+        all(
+            feature = "kqueue",
+            any(
+                target_os = "macos",
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd"
+            )
+        )
+    ))]
+    pub(crate) fn insert_new_files(&mut self, new_files: Vec<PathBuf>) {
+        use crate::sort::sort_files;
+
+        log::debug!("New files: {:?}", new_files);
+        self.all_files.extend(new_files.iter().cloned());
+        // `files` is in reverse order, as we `pop()` the next file
+        let mut files: Vec<PathBuf> = self.files.drain(..).rev().chain(new_files).collect();
+        sort_files(&mut files, &self.options);
+        files.reverse();
+        self.files = files;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{fs, time::Duration};
+
+    #[test]
+    fn watch_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let staging = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("messages"), "line one from messages\n").unwrap();
+
+        let mut streamer = DirectoryLinesStreamer::builder(dir.path())
+            .with_watch_interval(Duration::from_millis(20))
+            .build()
+            .unwrap();
+        assert_eq!(streamer.next().unwrap(), "line one from messages\n");
+
+        let stopper = streamer.watch_stopper();
+        let dir_path = dir.path().to_path_buf();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            // Rename a complete file, so that it is never read half written
+            let staged = staging.path().join("messages.1");
+            fs::write(&staged, "line one from messages.1\n").unwrap();
+            fs::rename(&staged, dir_path.join("messages.1")).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            stopper.stop_watching();
+        });
+
+        assert_eq!(streamer.next().unwrap(), "line one from messages.1\n");
+        assert_eq!(streamer.next(), None);
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::DirectoryLinesStreamer;
+
+/// Size of the chunks the lines of a `ZeroCopyLines` are sliced from.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Iterator over the lines of a streamer as `Bytes`, see
+/// `DirectoryLinesStreamer::zero_copy_lines()`.
+#[derive(Debug)]
+pub struct ZeroCopyLines {
+    streamer: DirectoryLinesStreamer,
+    // Lines are appended here, then split off
+    buffer: BytesMut,
+}
+
+impl DirectoryLinesStreamer {
+    /// Yield the lines as `Bytes`, sliced from large shared chunks instead of allocating a
+    /// `String` per line.
+    ///
+    /// Lines are yielded as read, newline delimiter included: the `Utf8Mode` and the line
+    /// transformations are not applied. The lines already taken out of the files, like the
+    /// line of `peek_first_line()`, are yielded first.
+    pub fn zero_copy_lines(self) -> ZeroCopyLines {
+        ZeroCopyLines {
+            streamer: self,
+            buffer: BytesMut::new(),
+        }
+    }
+}
+
+impl Iterator for ZeroCopyLines {
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Bytes> {
+        if let Some(line) = self.streamer.next_taken_line_bytes() {
+            return Some(Bytes::from(line));
+        }
+        if !self.streamer.read_raw_line() {
+            return None;
+        }
+        let line = &self.streamer.line_buf;
+        if self.buffer.capacity() < line.len() {
+            self.buffer.reserve(line.len().max(CHUNK_SIZE));
+        }
+        self.buffer.extend_from_slice(line);
+        Some(self.buffer.split().freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_copy_lines() {
+        let lines: Vec<Bytes> = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir")
+            .unwrap()
+            .zero_copy_lines()
+            .collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(lines[0], &b"line one from messages\n"[..]);
+        assert_eq!(lines[14], &b"line three from messages.20\n"[..]);
+    }
+
+    #[test]
+    fn zero_copy_lines_after_peek() {
+        let mut streamer = DirectoryLinesStreamer::from_dir("fixtures/non-empty-dir").unwrap();
+        streamer.peek_first_line();
+        streamer.extend(vec!["injected\n".to_string()]);
+        let lines: Vec<Bytes> = streamer.zero_copy_lines().collect();
+        assert_eq!(lines.len(), 16);
+        assert_eq!(lines[0], &b"line one from messages\n"[..]);
+        assert_eq!(lines[1], &b"injected\n"[..]);
+        assert_eq!(lines[2], &b"line two from messages\n"[..]);
+    }
+}
@@ -0,0 +1,53 @@
+//! Allocations of `DirectoryLinesStreamer::zero_copy_lines()`, in their own test binary so that
+//! the counting allocator does not replace the one of the unit tests.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    fs,
+};
+
+use bytes::Bytes;
+
+use dir_lines_streamer::DirectoryLinesStreamer;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the allocations of each thread
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn zero_copy_lines_allocations() {
+    let dir = tempfile::tempdir().unwrap();
+    let content: String = (0..1000).map(|i| format!("line {}\n", i)).collect();
+    fs::write(dir.path().join("messages"), content).unwrap();
+    let lines = DirectoryLinesStreamer::from_dir(dir.path())
+        .unwrap()
+        .zero_copy_lines();
+    let mut collected: Vec<Bytes> = Vec::with_capacity(1000);
+    let before = ALLOCATIONS.with(Cell::get);
+    collected.extend(lines);
+    let allocations = ALLOCATIONS.with(Cell::get) - before;
+    // One chunk for every 64 KiB of lines, not one allocation per line
+    assert!(allocations < 10, "{} allocations", allocations);
+    assert_eq!(collected.len(), 1000);
+    for (i, line) in collected.iter().enumerate() {
+        assert_eq!(line, format!("line {}\n", i).as_bytes());
+    }
+}